@@ -0,0 +1,89 @@
+//! `Vec`で持つ`BtreeNode`と、固定容量の配列で持つ`InlineBtree`・`ArenaBtree`の比較
+//!
+//! `cargo bench --bench node_layout`で実行する。
+
+use btree_rust::{ArenaBtree, Btree, Delete, InlineBtree, Insert, Search};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const COUNT: u64 = 100_000;
+const ROUNDS: usize = 5;
+
+/// 同じ順序で使うための擬似乱数のキー列
+fn random_keys(count: u64) -> Vec<u64> {
+    let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+    (0..count)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % (count * 4)
+        })
+        .collect()
+}
+
+/// `ROUNDS`回測った中で最も速い時間を返す
+fn measure(mut f: impl FnMut()) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn run<T: Search<u64, u64> + Insert<u64, u64> + Delete<u64>>(
+    name: &str,
+    keys: &[u64],
+    new: impl Fn() -> T,
+) {
+    let insert = measure(|| {
+        let mut tree = new();
+        for &key in keys {
+            tree.insert(key, key);
+        }
+        black_box(&tree);
+    });
+
+    let mut tree = new();
+    for &key in keys {
+        tree.insert(key, key);
+    }
+    let search = measure(|| {
+        for key in keys {
+            black_box(tree.search(black_box(key)));
+        }
+    });
+
+    let delete = measure(|| {
+        let mut tree = new();
+        for &key in keys {
+            tree.insert(key, key);
+        }
+        for key in keys {
+            tree.delete(key);
+        }
+        black_box(&tree);
+    });
+
+    let per_op = |d: Duration| d.as_nanos() as f64 / keys.len() as f64;
+    println!(
+        "{name:<20} insert {:>8.1} ns/op  search {:>8.1} ns/op  insert+delete {:>8.1} ns/op",
+        per_op(insert),
+        per_op(search),
+        per_op(delete),
+    );
+}
+
+fn main() {
+    let keys = random_keys(COUNT);
+    println!("{COUNT} random u64 keys, best of {ROUNDS} rounds");
+    run("Btree (Vec, 16)", &keys, || Btree::new(16));
+    run("InlineBtree<16>", &keys, InlineBtree::<u64, u64, 16>::new);
+    run("ArenaBtree<16>", &keys, ArenaBtree::<u64, u64, 16>::new);
+    run("Btree (Vec, 64)", &keys, || Btree::new(64));
+    run("InlineBtree<64>", &keys, InlineBtree::<u64, u64, 64>::new);
+    run("ArenaBtree<64>", &keys, ArenaBtree::<u64, u64, 64>::new);
+}
@@ -0,0 +1,79 @@
+//! 整数のキーをまとめて比較するノード内の探索と、1つずつ比較する汎用の探索の比較
+//!
+//! `cargo bench --bench node_search`で実行する。
+//! `Opaque`は`u64`と同じ順序を持つが整数型ではないので、汎用の探索を使う。
+
+use btree_rust::{ArenaBtree, Btree, Insert, Search};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 9;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+struct Opaque(u64);
+
+fn random_keys(count: u64) -> Vec<u64> {
+    let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+    (0..count)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % (count * 4)
+        })
+        .collect()
+}
+
+/// `ROUNDS`回測った中で最も速い時間を返す
+fn measure(mut f: impl FnMut()) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn run<K: Copy, T: Search<K, u64> + Insert<K, u64>>(name: &str, keys: &[K], mut tree: T) {
+    for &key in keys {
+        tree.insert(key, 0);
+    }
+    let search = measure(|| {
+        for key in keys {
+            black_box(tree.search(black_box(key)));
+        }
+    });
+    println!(
+        "{name:<24} search {:>8.1} ns/op",
+        search.as_nanos() as f64 / keys.len() as f64
+    );
+}
+
+fn main() {
+    // キャッシュに収まる大きさと収まらない大きさ
+    for count in [10_000, 1_000_000] {
+        let keys = random_keys(count);
+        let opaque: Vec<Opaque> = keys.iter().map(|&k| Opaque(k)).collect();
+        println!("{count} random u64 keys, best of {ROUNDS} rounds");
+        for max_count in [8, 16, 64, 256] {
+            run(
+                &format!("Btree u64 ({max_count})"),
+                &keys,
+                Btree::new(max_count),
+            );
+            run(
+                &format!("Btree Opaque ({max_count})"),
+                &opaque,
+                Btree::new(max_count),
+            );
+        }
+        run("ArenaBtree<u64, 64>", &keys, ArenaBtree::<_, _, 64>::new());
+        run(
+            "ArenaBtree<Opaque, 64>",
+            &opaque,
+            ArenaBtree::<_, _, 64>::new(),
+        );
+    }
+}
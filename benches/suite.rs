@@ -0,0 +1,354 @@
+//! 挿入・検索・削除・範囲走査・昇順の挿入のベンチマーク
+//!
+//! `Btree`を最大要素数ごとに、`BTreeMap`・`HashMap`と同じ操作列で比較する。
+//! キーの並びは連番・一様乱数・Zipf分布の3種類で、キーの大きさは`u64`と16/64バイトの文字列。
+//!
+//! ```text
+//! cargo bench --bench suite                           # 表を出力する
+//! cargo bench --bench suite -- --quick                # 件数を減らして短時間で実行する
+//! cargo bench --bench suite -- --json results.json    # 結果をJSONでも書き出す
+//! ```
+
+use btree_rust::{Btree, Delete, Insert, Search};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::hash::Hash;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 3;
+const BRANCHING: [usize; 4] = [4, 16, 64, 256];
+/// 1回の範囲走査でたどるエントリの数
+const RANGE_LEN: usize = 100;
+/// Zipf分布の偏り（1に近いほど一部のキーに集中する）
+const ZIPF_EXPONENT: f64 = 0.99;
+
+/// 比較する連想配列の共通の操作
+trait Map<K, V>: Clone {
+    fn insert(&mut self, key: K, value: V);
+    fn get(&self, key: &K) -> bool;
+    fn remove(&mut self, key: &K);
+    /// `start`以降のエントリを最大`len`個たどる（範囲走査できなければ`None`）
+    fn scan(&self, start: &K, len: usize) -> Option<usize>;
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Map<K, V> for Btree<K, V> {
+    fn insert(&mut self, key: K, value: V) {
+        Insert::insert(self, key, value);
+    }
+    fn get(&self, key: &K) -> bool {
+        self.search(key).is_some()
+    }
+    fn remove(&mut self, key: &K) {
+        self.delete(key);
+    }
+    fn scan(&self, start: &K, len: usize) -> Option<usize> {
+        Some(self.range(start.clone()..).take(len).count())
+    }
+}
+
+impl<K: Clone + Ord, V: Clone> Map<K, V> for BTreeMap<K, V> {
+    fn insert(&mut self, key: K, value: V) {
+        BTreeMap::insert(self, key, value);
+    }
+    fn get(&self, key: &K) -> bool {
+        BTreeMap::get(self, key).is_some()
+    }
+    fn remove(&mut self, key: &K) {
+        BTreeMap::remove(self, key);
+    }
+    fn scan(&self, start: &K, len: usize) -> Option<usize> {
+        Some(self.range(start.clone()..).take(len).count())
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Map<K, V> for HashMap<K, V> {
+    fn insert(&mut self, key: K, value: V) {
+        HashMap::insert(self, key, value);
+    }
+    fn get(&self, key: &K) -> bool {
+        HashMap::get(self, key).is_some()
+    }
+    fn remove(&mut self, key: &K) {
+        HashMap::remove(self, key);
+    }
+    fn scan(&self, _: &K, _: usize) -> Option<usize> {
+        None
+    }
+}
+
+/// 大きさの違うキーを、番号の順序を保って作る
+trait BenchKey: 'static + Clone + Ord + Hash {
+    const NAME: &'static str;
+    fn make(n: u64) -> Self;
+}
+
+impl BenchKey for u64 {
+    const NAME: &'static str = "u64";
+    fn make(n: u64) -> Self {
+        n
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Str16(String);
+
+impl BenchKey for Str16 {
+    const NAME: &'static str = "str16";
+    fn make(n: u64) -> Self {
+        Str16(format!("{n:016}"))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Str64(String);
+
+impl BenchKey for Str64 {
+    const NAME: &'static str = "str64";
+    fn make(n: u64) -> Self {
+        Str64(format!("{:>64}", format!("key/{n:016}")))
+    }
+}
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, (self.next() % (i as u64 + 1)) as usize);
+        }
+    }
+}
+
+/// 挿入する順序と、検索・削除・範囲走査で使うキーの列
+struct Workload {
+    name: &'static str,
+    inserts: Vec<u64>,
+    lookups: Vec<u64>,
+}
+
+fn workloads(count: u64) -> Vec<Workload> {
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    let sequential: Vec<u64> = (0..count).collect();
+    let mut shuffled = sequential.clone();
+    rng.shuffle(&mut shuffled);
+
+    // Zipf分布：順位kのキーが選ばれる確率は1/k^sに比例する（順位とキーの対応は乱数で決める）
+    let mut cdf: Vec<f64> = (1..=count)
+        .map(|k| 1.0 / (k as f64).powf(ZIPF_EXPONENT))
+        .collect();
+    for i in 1..cdf.len() {
+        cdf[i] += cdf[i - 1];
+    }
+    let total = cdf[cdf.len() - 1];
+    let zipfian: Vec<u64> = (0..count)
+        .map(|_| {
+            // 乱数は1回だけ引く（比較のたびに引くと二分探索の境界がずれ、分布が崩れる）
+            let u = rng.unit() * total;
+            let rank = cdf.partition_point(|&c| c < u);
+            shuffled[rank.min(shuffled.len() - 1)]
+        })
+        .collect();
+
+    vec![
+        Workload {
+            name: "sequential",
+            inserts: sequential.clone(),
+            lookups: sequential,
+        },
+        Workload {
+            name: "random",
+            inserts: shuffled.clone(),
+            lookups: shuffled.clone(),
+        },
+        Workload {
+            name: "zipfian",
+            inserts: shuffled,
+            lookups: zipfian,
+        },
+    ]
+}
+
+/// `ROUNDS`回測った中で最も速い時間
+fn measure(mut f: impl FnMut() -> Duration) -> Duration {
+    (0..ROUNDS).map(|_| f()).min().unwrap()
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+struct Record {
+    map: String,
+    key: &'static str,
+    workload: &'static str,
+    op: &'static str,
+    count: usize,
+    ns_per_op: f64,
+}
+
+struct Report {
+    records: Vec<Record>,
+}
+
+impl Report {
+    fn push(
+        &mut self,
+        map: &str,
+        key: &'static str,
+        workload: &'static str,
+        op: &'static str,
+        count: usize,
+        elapsed: Duration,
+    ) {
+        let record = Record {
+            map: map.to_string(),
+            key,
+            workload,
+            op,
+            count,
+            ns_per_op: elapsed.as_nanos() as f64 / count as f64,
+        };
+        println!(
+            "{:<12} {:<6} {:<11} {:<7} {:>10.1} ns/op",
+            record.map, record.key, record.workload, record.op, record.ns_per_op
+        );
+        self.records.push(record);
+    }
+
+    fn to_json(&self) -> String {
+        let mut json = String::from("[\n");
+        for (i, r) in self.records.iter().enumerate() {
+            let separator = if i + 1 < self.records.len() { "," } else { "" };
+            writeln!(
+                json,
+                r#"  {{"map": "{}", "key": "{}", "workload": "{}", "op": "{}", "count": {}, "ns_per_op": {:.2}}}{separator}"#,
+                r.map, r.key, r.workload, r.op, r.count, r.ns_per_op
+            )
+            .unwrap();
+        }
+        json.push_str("]\n");
+        json
+    }
+}
+
+/// 一つの連想配列について、全ての操作を測る
+fn run_map<K: BenchKey, M: Map<K, u64>>(
+    report: &mut Report,
+    name: &str,
+    workload: &Workload,
+    new: impl Fn() -> M,
+) {
+    let inserts: Vec<K> = workload.inserts.iter().map(|&n| K::make(n)).collect();
+    let lookups: Vec<K> = workload.lookups.iter().map(|&n| K::make(n)).collect();
+    let push = |report: &mut Report, op, count, elapsed| {
+        report.push(name, K::NAME, workload.name, op, count, elapsed)
+    };
+
+    let mut built = new();
+    let elapsed = measure(|| {
+        let mut map = new();
+        let elapsed = time(|| {
+            for key in &inserts {
+                map.insert(key.clone(), 0);
+            }
+        });
+        built = map;
+        elapsed
+    });
+    push(report, "insert", inserts.len(), elapsed);
+
+    let elapsed = measure(|| {
+        time(|| {
+            for key in &lookups {
+                black_box(built.get(key));
+            }
+        })
+    });
+    push(report, "search", lookups.len(), elapsed);
+
+    let ranges = lookups.len() / RANGE_LEN;
+    if built.scan(&lookups[0], 1).is_some() {
+        let elapsed = measure(|| {
+            time(|| {
+                for key in lookups.iter().take(ranges) {
+                    black_box(built.scan(key, RANGE_LEN));
+                }
+            })
+        });
+        push(report, "range", ranges, elapsed);
+    }
+
+    let elapsed = measure(|| {
+        let mut map = built.clone();
+        time(|| {
+            for key in &lookups {
+                map.remove(key);
+            }
+        })
+    });
+    push(report, "delete", lookups.len(), elapsed);
+
+    // どの連想配列も1件ずつ挿入する（`BTreeMap::from_iter`のような一括構築とは比べない）
+    let mut sorted = inserts.clone();
+    sorted.sort();
+    let elapsed = measure(|| {
+        let mut map = new();
+        time(|| {
+            for key in &sorted {
+                map.insert(key.clone(), 0);
+            }
+            black_box(&map);
+        })
+    });
+    push(report, "sorted insert", sorted.len(), elapsed);
+}
+
+fn run_key<K: BenchKey>(report: &mut Report, count: u64) {
+    for workload in workloads(count) {
+        for max_count in BRANCHING {
+            let new = || Btree::<K, u64>::new(max_count);
+            run_map(report, &format!("Btree({max_count})"), &workload, new);
+        }
+        run_map(report, "BTreeMap", &workload, BTreeMap::<K, u64>::new);
+        run_map(report, "HashMap", &workload, HashMap::<K, u64>::new);
+    }
+}
+
+fn main() {
+    // `cargo bench`が渡す`--bench`などの引数は無視する
+    let args: Vec<String> = std::env::args().collect();
+    let count = if args.iter().any(|a| a == "--quick") {
+        10_000
+    } else {
+        100_000
+    };
+    let json = args
+        .iter()
+        .position(|a| a == "--json")
+        .and_then(|i| args.get(i + 1));
+
+    let mut report = Report { records: vec![] };
+    println!("{count} entries, best of {ROUNDS} rounds");
+    run_key::<u64>(&mut report, count);
+    run_key::<Str16>(&mut report, count);
+    run_key::<Str64>(&mut report, count);
+
+    if let Some(path) = json {
+        std::fs::write(path, report.to_json()).expect("failed to write results");
+        println!("wrote {} results to {path}", report.records.len());
+    }
+}
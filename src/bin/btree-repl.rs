@@ -0,0 +1,349 @@
+//! `Btree`の操作を1行ずつ試す対話環境
+//!
+//! ```text
+//! btree-repl                                  # 対話的に実行する
+//! btree-repl session.txt                      # ファイルのコマンドを順に実行し、記録を出力する
+//! btree-repl session.txt --expect out.txt     # 記録が`out.txt`と一致するか確かめる
+//! ```
+//!
+//! キーは整数、値は文字列。書き換えるコマンドの後には木の形を表示する。
+//! スクリプトでは各コマンドを`> `に続けて出力するので、記録を保存しておけば同じ操作を回帰テストとして再実行できる。
+//! `#`で始まる行と空行は読み飛ばす。
+
+use btree_rust::{Btree, Delete, Insert, Search};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::ExitCode;
+
+/// 最初の木の最大要素数（各ノードのキーは3つまで）
+const DEFAULT_ORDER: usize = 4;
+/// 取り消せる操作の数
+const HISTORY_LIMIT: usize = 1000;
+
+const HELP: &str = "\
+insert <key> <value>   insert or overwrite an entry
+delete <key>           delete an entry
+find <key>             print the value for a key
+range <from> <to>      print entries with from <= key <= to
+show                   print the tree
+undo                   revert the last insert or delete
+set-order <n>          rebuild the tree with at most n children per node (clears undo history)
+help                   print this help
+quit                   exit";
+
+/// 対話環境の状態
+struct Session {
+    tree: Btree<i64, String>,
+}
+
+impl Session {
+    fn new(order: usize) -> Self {
+        let mut tree = Btree::new(order);
+        tree.enable_history(HISTORY_LIMIT);
+        Session { tree }
+    }
+
+    /// 1行のコマンドを実行する（`quit`なら`false`を返す）
+    fn execute(&mut self, line: &str, out: &mut impl Write) -> io::Result<bool> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(true);
+        };
+        if command.starts_with('#') {
+            return Ok(true);
+        }
+        match self.run(command, args, out) {
+            Ok(quit) => Ok(!quit),
+            Err(Error::Io(e)) => Err(e),
+            Err(Error::Usage(message)) => {
+                writeln!(out, "error: {message}")?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// コマンドを実行し、終了する場合は`true`を返す
+    fn run(&mut self, command: &str, args: &[&str], out: &mut impl Write) -> Result<bool, Error> {
+        match (command, args) {
+            ("insert", [key, value @ ..]) if !value.is_empty() => {
+                self.tree.insert(parse_key(key)?, value.join(" "));
+                self.show(out)?;
+            }
+            ("delete", [key]) => {
+                let key = parse_key(key)?;
+                if self.tree.search(&key).is_none() {
+                    writeln!(out, "{key} not found")?;
+                } else {
+                    self.tree.delete(&key);
+                    self.show(out)?;
+                }
+            }
+            ("find", [key]) => match self.tree.search(&parse_key(key)?) {
+                Some((key, value)) => writeln!(out, "{key} = {value}")?,
+                None => writeln!(out, "{key} not found")?,
+            },
+            ("range", [from, to]) => {
+                let (from, to) = (parse_key(from)?, parse_key(to)?);
+                let mut found = false;
+                for (key, value) in self.tree.range(from..=to) {
+                    writeln!(out, "{key} = {value}")?;
+                    found = true;
+                }
+                if !found {
+                    writeln!(out, "no entries")?;
+                }
+            }
+            ("show", []) => self.show(out)?,
+            ("undo", []) => {
+                if self.tree.undo() {
+                    self.show(out)?;
+                } else {
+                    writeln!(out, "nothing to undo")?;
+                }
+            }
+            ("set-order", [order]) => {
+                let order: usize = order
+                    .parse()
+                    .ok()
+                    .filter(|&order| order >= 3)
+                    .ok_or_else(|| Error::Usage(format!("order must be at least 3: {order}")))?;
+                let mut tree = Btree::new(order);
+                tree.extend(self.tree.iter().map(|(key, value)| (*key, value.clone())));
+                // 作り直した木への挿入は取り消しの対象にしない
+                tree.enable_history(HISTORY_LIMIT);
+                self.tree = tree;
+                self.show(out)?;
+            }
+            ("help", []) => writeln!(out, "{HELP}")?,
+            ("quit" | "exit", []) => return Ok(true),
+            (
+                "insert" | "delete" | "find" | "range" | "show" | "undo" | "set-order" | "help"
+                | "quit" | "exit",
+                _,
+            ) => {
+                return Err(Error::Usage(format!("wrong arguments for {command}")));
+            }
+            _ => {
+                return Err(Error::Usage(format!(
+                    "unknown command {command} (try help)"
+                )));
+            }
+        }
+        Ok(false)
+    }
+
+    fn show(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "{}", self.tree.render())
+    }
+}
+
+enum Error {
+    Io(io::Error),
+    /// 利用者に表示して、次のコマンドに進む誤り
+    Usage(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+fn parse_key(text: &str) -> Result<i64, Error> {
+    text.parse()
+        .map_err(|_| Error::Usage(format!("key must be an integer: {text}")))
+}
+
+/// スクリプトを実行し、コマンドと出力を交互に並べた記録を返す
+fn replay(script: &str) -> io::Result<String> {
+    let mut session = Session::new(DEFAULT_ORDER);
+    let mut out = vec![];
+    for line in script.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        writeln!(out, "> {line}")?;
+        if !session.execute(line, &mut out)? {
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// 2つの記録を比べ、最初に異なる行の番号と内容を返す
+fn first_difference(actual: &str, expected: &str) -> Option<(usize, String, String)> {
+    let mut actual_lines = actual.lines();
+    let mut expected_lines = expected.lines();
+    for number in 1.. {
+        match (actual_lines.next(), expected_lines.next()) {
+            (None, None) => return None,
+            (a, e) if a == e => {}
+            (a, e) => {
+                let show = |line: Option<&str>| line.unwrap_or("<end of output>").to_string();
+                return Some((number, show(a), show(e)));
+            }
+        }
+    }
+    unreachable!()
+}
+
+fn interactive() -> io::Result<()> {
+    let mut session = Session::new(DEFAULT_ORDER);
+    let stdin = io::stdin();
+    let prompt = stdin.is_terminal();
+    let mut out = io::stdout().lock();
+    if prompt {
+        writeln!(out, "btree-repl: type help for commands")?;
+    }
+    let mut lines = stdin.lock().lines();
+    loop {
+        if prompt {
+            write!(out, "btree> ")?;
+            out.flush()?;
+        }
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        if !session.execute(&line?, &mut out)? {
+            return Ok(());
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [] => interactive().map(|()| ExitCode::SUCCESS),
+        [script] => fs::read_to_string(script)
+            .and_then(|script| replay(&script))
+            .map(|transcript| {
+                print!("{transcript}");
+                ExitCode::SUCCESS
+            }),
+        [script, flag, expected] if flag == "--expect" => fs::read_to_string(script)
+            .and_then(|script| replay(&script))
+            .and_then(|actual| Ok((actual, fs::read_to_string(expected)?)))
+            .map(
+                |(actual, expected)| match first_difference(&actual, &expected) {
+                    None => ExitCode::SUCCESS,
+                    Some((number, actual, expected)) => {
+                        eprintln!("line {number} differs");
+                        eprintln!("  expected: {expected}");
+                        eprintln!("  actual:   {actual}");
+                        ExitCode::from(1)
+                    }
+                },
+            ),
+        _ => {
+            eprintln!("usage: btree-repl [<script> [--expect <transcript>]]");
+            return ExitCode::from(2);
+        }
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("btree-repl: {e}");
+        ExitCode::from(2)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_session() {
+        let script = "\
+# 4つ目のキーで根が分割される
+insert 20 twenty
+insert 10 ten
+insert 30 thirty
+insert 40 forty
+find 10
+range 15 35
+delete 99
+# 取り消しは40を削除し直すので、分割前の形には戻らない
+undo
+show
+";
+        assert_eq!(
+            replay(script).unwrap(),
+            "\
+> insert 20 twenty
+Btree (max_count = 4, height = 1, keys = 1)
+[20]  level 0  1/3 (33%)
+> insert 10 ten
+Btree (max_count = 4, height = 1, keys = 2)
+[10, 20]  level 0  2/3 (66%)
+> insert 30 thirty
+Btree (max_count = 4, height = 1, keys = 3)
+[10, 20, 30]  level 0  3/3 (100%)
+> insert 40 forty
+Btree (max_count = 4, height = 2, keys = 4)
+[30]  level 0  1/3 (33%)
++-- [10, 20]  level 1  2/3 (66%)
+`-- [40]  level 1  1/3 (33%)
+> find 10
+10 = ten
+> range 15 35
+20 = twenty
+30 = thirty
+> delete 99
+99 not found
+> undo
+Btree (max_count = 4, height = 2, keys = 3)
+[20]  level 0  1/3 (33%)
++-- [10]  level 1  1/3 (33%)
+`-- [30]  level 1  1/3 (33%)
+> show
+Btree (max_count = 4, height = 2, keys = 3)
+[20]  level 0  1/3 (33%)
++-- [10]  level 1  1/3 (33%)
+`-- [30]  level 1  1/3 (33%)
+"
+        );
+    }
+
+    #[test]
+    fn test_set_order_and_errors() {
+        let transcript = replay(
+            "insert 1 a b\nset-order 3\nundo\nset-order 2\ninsert x 1\nfrobnicate\nfind\nquit\nshow\n",
+        )
+        .unwrap();
+        assert_eq!(
+            transcript,
+            "\
+> insert 1 a b
+Btree (max_count = 4, height = 1, keys = 1)
+[1]  level 0  1/3 (33%)
+> set-order 3
+Btree (max_count = 3, height = 1, keys = 1)
+[1]  level 0  1/2 (50%)
+> undo
+nothing to undo
+> set-order 2
+error: order must be at least 3: 2
+> insert x 1
+error: key must be an integer: x
+> frobnicate
+error: unknown command frobnicate (try help)
+> find
+error: wrong arguments for find
+> quit
+"
+        );
+        assert!(replay("find 1\n").unwrap().ends_with("1 not found\n"));
+    }
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(first_difference("a\nb\n", "a\nb\n"), None);
+        assert_eq!(
+            first_difference("a\nc\n", "a\nb\n"),
+            Some((2, "c".to_string(), "b".to_string()))
+        );
+        assert_eq!(
+            first_difference("a\n", "a\nb\n"),
+            Some((2, "<end of output>".to_string(), "b".to_string()))
+        );
+    }
+}
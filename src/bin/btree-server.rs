@@ -0,0 +1,78 @@
+//! 名前を付けた`Btree`を、localhostのTCPでRESPのコマンドから読み書きさせるサーバー
+//!
+//! ```text
+//! btree-server [--port N] [--max-count N]
+//! ```
+//!
+//! コマンドは`btree_rust::Server`を参照。`redis-cli -p <port>`や`telnet`からも使える。
+
+use btree_rust::Server;
+use std::process::ExitCode;
+
+const DEFAULT_PORT: u16 = 7379;
+const DEFAULT_MAX_COUNT: usize = 64;
+
+fn parse_args(args: &[String]) -> Result<(u16, usize), String> {
+    let mut port = DEFAULT_PORT;
+    let mut max_count = DEFAULT_MAX_COUNT;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = iter.next().ok_or(format!("{arg} needs a value"))?;
+        let invalid = || format!("invalid value for {arg}: {value}");
+        match arg.as_str() {
+            "--port" => port = value.parse().map_err(|_| invalid())?,
+            "--max-count" => {
+                max_count = value.parse().ok().filter(|&n| n >= 3).ok_or_else(invalid)?
+            }
+            _ => return Err(format!("unknown option {arg}")),
+        }
+    }
+    Ok((port, max_count))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (port, max_count) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("btree-server: {message}");
+            eprintln!("usage: btree-server [--port N] [--max-count N]");
+            return ExitCode::from(2);
+        }
+    };
+    // 他のホストからは接続させない
+    let server = match Server::bind(("127.0.0.1", port), max_count) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("btree-server: cannot listen on port {port}: {e}");
+            return ExitCode::from(1);
+        }
+    };
+    if let Ok(addr) = server.local_addr() {
+        println!("listening on {addr}");
+    }
+    server.run();
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(&[]), Ok((DEFAULT_PORT, DEFAULT_MAX_COUNT)));
+        assert_eq!(
+            parse_args(&args(&["--max-count", "8", "--port", "0"])),
+            Ok((0, 8))
+        );
+        assert!(parse_args(&args(&["--port"])).is_err());
+        assert!(parse_args(&args(&["--port", "70000"])).is_err());
+        assert!(parse_args(&args(&["--max-count", "2"])).is_err());
+        assert!(parse_args(&args(&["--host", "0.0.0.0"])).is_err());
+    }
+}
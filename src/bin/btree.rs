@@ -0,0 +1,455 @@
+//! `Btree`のファイルを作成・検索・検査するコマンド
+//!
+//! ```text
+//! btree create <file> <input> [--max-count N] [--csv | --tsv] [--header]
+//! btree get <file> <key>
+//! btree put <file> <key> <value>
+//! btree del <file> <key>
+//! btree range <file> [<start> [<end>]] [--limit N]
+//! btree stats <file>
+//! btree render <file> [--depth N] [--highlight KEY]
+//! btree dot <file> [--depth N] [--highlight KEY]
+//! btree verify <file>
+//! ```
+//!
+//! キーと値は文字列で、`create`の入力は1行に1組のCSVかTSV（`-`なら標準入力）。
+//! 区切り文字を指定しない場合は、拡張子が`.tsv`ならタブ、それ以外はカンマを使う。
+//! `range`は`start`以上`end`未満のエントリを、キーと値をタブで区切って出力する。
+//! 見つからないキーや検査で見つかった違反は終了コード1、使い方の誤りやファイルの読み書きの失敗は2で終わる。
+
+use btree_rust::{Btree, Delete, Insert, RenderOptions, Search, VerifyError};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+type Tree = Btree<String, String>;
+
+const DEFAULT_MAX_COUNT: usize = 64;
+
+/// 値を取るオプション
+const VALUE_OPTIONS: [&str; 4] = ["--max-count", "--limit", "--depth", "--highlight"];
+/// 値を取らないオプション
+const FLAG_OPTIONS: [&str; 3] = ["--csv", "--tsv", "--header"];
+
+const USAGE: &str = "\
+usage: btree create <file> <input> [--max-count N] [--csv | --tsv] [--header]
+       btree get <file> <key>
+       btree put <file> <key> <value>
+       btree del <file> <key>
+       btree range <file> [<start> [<end>]] [--limit N]
+       btree stats <file>
+       btree render <file> [--depth N] [--highlight KEY]
+       btree dot <file> [--depth N] [--highlight KEY]
+       btree verify <file>";
+
+/// コマンドの引数を、位置で決まる引数とオプションに分けたもの
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Args {
+            positional: vec![],
+            options: vec![],
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if VALUE_OPTIONS.contains(&arg.as_str()) {
+                let value = iter.next().ok_or(format!("{arg} needs a value"))?;
+                parsed.options.push((arg.clone(), Some(value.clone())));
+            } else if FLAG_OPTIONS.contains(&arg.as_str()) {
+                parsed.options.push((arg.clone(), None));
+            } else if arg.starts_with("--") {
+                return Err(format!("unknown option {arg}"));
+            } else {
+                parsed.positional.push(arg.clone());
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    fn number(&self, name: &str) -> Result<Option<usize>, String> {
+        self.value(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("{name} expects a number, got {value:?}"))
+            })
+            .transpose()
+    }
+
+    /// 位置で決まる引数がちょうど`N`個あることを確かめる
+    fn exact<const N: usize>(&self) -> Result<&[String; N], String> {
+        self.positional
+            .as_slice()
+            .try_into()
+            .map_err(|_| USAGE.to_string())
+    }
+}
+
+/// CSVの1行を項目に分ける
+///
+/// `"`で囲んだ項目の中ではカンマと、`""`で表した`"`を使える。項目内の改行には対応しない。
+fn split_csv(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// 入力の各行をキーと値の組にする（空行は読み飛ばす）
+fn read_rows(
+    input: impl BufRead,
+    tsv: bool,
+    header: bool,
+) -> Result<Vec<(String, String)>, String> {
+    let mut rows = vec![];
+    for (index, line) in input.lines().enumerate().skip(usize::from(header)) {
+        let number = index + 1;
+        let line = line.map_err(|e| format!("line {number}: {e}"))?;
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if line.is_empty() {
+            continue;
+        }
+        let fields = if tsv {
+            line.split('\t').map(str::to_string).collect()
+        } else {
+            split_csv(line).map_err(|e| format!("line {number}: {e}"))?
+        };
+        match <[String; 2]>::try_from(fields) {
+            Ok([key, value]) => rows.push((key, value)),
+            Err(fields) => {
+                return Err(format!(
+                    "line {number}: expected 2 fields, found {}",
+                    fields.len()
+                ));
+            }
+        }
+    }
+    Ok(rows)
+}
+
+fn load(path: &Path) -> Result<Tree, String> {
+    read(path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// 木の条件を満たさないファイルは、`VerifyError`を包んだエラーになる
+fn read(path: &Path) -> io::Result<Tree> {
+    Tree::load(BufReader::new(File::open(path)?))
+}
+
+/// 一時ファイルに書き出してから置き換えるので、途中で失敗しても元のファイルは壊れない
+fn save(tree: &Tree, path: &Path) -> Result<(), String> {
+    let mut temporary = PathBuf::from(path);
+    temporary.as_mut_os_string().push(".tmp");
+    let result = File::create(&temporary)
+        .and_then(|file| tree.save(BufWriter::new(file)))
+        .and_then(|()| fs::rename(&temporary, path));
+    result.map_err(|e| {
+        let _ = fs::remove_file(&temporary);
+        format!("{}: {e}", path.display())
+    })
+}
+
+fn render_options(args: &Args) -> Result<RenderOptions<String>, String> {
+    Ok(RenderOptions {
+        max_depth: args.number("--depth")?,
+        highlight: args.value("--highlight").map(str::to_string),
+    })
+}
+
+/// コマンドを実行し、結果を`out`に書く
+///
+/// キーが見つからない場合や検査に失敗した場合は`Ok(false)`を返す。
+fn run(args: &[String], out: &mut impl Write) -> Result<bool, String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(USAGE.to_string());
+    };
+    let args = Args::parse(rest)?;
+    let write_error = |e: io::Error| e.to_string();
+    match command.as_str() {
+        "create" => {
+            let [path, input] = args.exact::<2>()?;
+            let max_count = args.number("--max-count")?.unwrap_or(DEFAULT_MAX_COUNT);
+            if max_count < 3 {
+                return Err("--max-count must be at least 3".to_string());
+            }
+            let tsv = args.flag("--tsv") || (!args.flag("--csv") && input.ends_with(".tsv"));
+            let rows = if input == "-" {
+                read_rows(io::stdin().lock(), tsv, args.flag("--header"))
+            } else {
+                let file = File::open(input).map_err(|e| format!("{input}: {e}"))?;
+                read_rows(BufReader::new(file), tsv, args.flag("--header"))
+            }
+            .map_err(|e| format!("{input}: {e}"))?;
+
+            let mut tree = Tree::new(max_count);
+            tree.extend(rows);
+            save(&tree, Path::new(path))?;
+            let stats = tree.stats();
+            writeln!(
+                out,
+                "created {path}: {} keys, height {}",
+                stats.keys, stats.height
+            )
+            .map_err(write_error)?;
+        }
+        "get" => {
+            let [path, key] = args.exact::<2>()?;
+            match load(Path::new(path))?.search(key) {
+                Some((_, value)) => writeln!(out, "{value}").map_err(write_error)?,
+                None => return Ok(false),
+            }
+        }
+        "put" => {
+            let [path, key, value] = args.exact::<3>()?;
+            let mut tree = load(Path::new(path))?;
+            tree.insert(key.clone(), value.clone());
+            save(&tree, Path::new(path))?;
+        }
+        "del" => {
+            let [path, key] = args.exact::<2>()?;
+            let mut tree = load(Path::new(path))?;
+            if tree.search(key).is_none() {
+                return Ok(false);
+            }
+            tree.delete(key);
+            save(&tree, Path::new(path))?;
+        }
+        "range" => {
+            let positional = &args.positional;
+            if positional.is_empty() || positional.len() > 3 {
+                return Err(USAGE.to_string());
+            }
+            let tree = load(Path::new(&positional[0]))?;
+            let bound = |i: usize, f: fn(String) -> Bound<String>| {
+                positional.get(i).cloned().map_or(Bound::Unbounded, f)
+            };
+            let limit = args.number("--limit")?.unwrap_or(usize::MAX);
+            let range = (bound(1, Bound::Included), bound(2, Bound::Excluded));
+            for (key, value) in tree.range(range).take(limit) {
+                writeln!(out, "{key}\t{value}").map_err(write_error)?;
+            }
+        }
+        "stats" => {
+            let [path] = args.exact::<1>()?;
+            let tree = load(Path::new(path))?;
+            let stats = tree.stats();
+            let levels: Vec<String> = stats.nodes_per_level.iter().map(usize::to_string).collect();
+            let percent = |fill: f64| fill * 100.0;
+            writeln!(
+                out,
+                "\
+height    {}
+nodes     {} ({})
+leaves    {}
+keys      {}
+fill      avg {:.1}%  min {:.1}%  max {:.1}%
+memory    keys {} B  values {} B  nodes {} B  total {} B",
+                stats.height,
+                stats.nodes(),
+                levels.join(", "),
+                stats.leaves,
+                stats.keys,
+                percent(stats.average_fill),
+                percent(stats.min_fill),
+                percent(stats.max_fill),
+                stats.memory.keys,
+                stats.memory.values,
+                stats.memory.nodes,
+                stats.memory.total()
+            )
+            .map_err(write_error)?;
+        }
+        "render" | "dot" => {
+            let [path] = args.exact::<1>()?;
+            let tree = load(Path::new(path))?;
+            let options = render_options(&args)?;
+            let text = if command == "dot" {
+                tree.to_dot_with(&options)
+            } else {
+                tree.render_with(&options)
+            };
+            out.write_all(text.as_bytes()).map_err(write_error)?;
+        }
+        "verify" => {
+            let [path] = args.exact::<1>()?;
+            let error = match read(Path::new(path)) {
+                Ok(_) => {
+                    writeln!(out, "ok").map_err(write_error)?;
+                    return Ok(true);
+                }
+                Err(error) => error,
+            };
+            let violation = error
+                .get_ref()
+                .and_then(|e| e.downcast_ref::<VerifyError>());
+            let Some(violation) = violation else {
+                return Err(format!("{path}: {error}"));
+            };
+            writeln!(out, "{violation}").map_err(write_error)?;
+            return Ok(false);
+        }
+        _ => return Err(format!("unknown command {command}\n{USAGE}")),
+    }
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut out = io::stdout().lock();
+    match run(&args, &mut out) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(message) => {
+            eprintln!("btree: {message}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_args(args: &[&str]) -> (Result<bool, String>, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let mut out = vec![];
+        let result = run(&args, &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_split_csv() {
+        assert_eq!(split_csv("a,b").unwrap(), vec!["a", "b"]);
+        assert_eq!(split_csv("\"a,b\",c").unwrap(), vec!["a,b", "c"]);
+        assert_eq!(
+            split_csv("\"say \"\"hi\"\"\",").unwrap(),
+            vec!["say \"hi\"", ""]
+        );
+        assert!(split_csv("\"open,x").is_err());
+    }
+
+    #[test]
+    fn test_read_rows() {
+        let input = "key,value\r\napple,1\n\n\"b,c\",2\n";
+        let rows = read_rows(input.as_bytes(), false, true).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("apple".to_string(), "1".to_string()),
+                ("b,c".to_string(), "2".to_string())
+            ]
+        );
+
+        let rows = read_rows("a\t1,2\n".as_bytes(), true, false).unwrap();
+        assert_eq!(rows, vec![("a".to_string(), "1,2".to_string())]);
+
+        let error = read_rows("a,1\nb\n".as_bytes(), false, false).unwrap_err();
+        assert_eq!(error, "line 2: expected 2 fields, found 1");
+    }
+
+    #[test]
+    fn test_commands() {
+        let dir = std::env::temp_dir().join(format!("btree-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.tsv");
+        let file = dir.join("index.btree");
+        let input_path = input.to_str().unwrap();
+        let path = file.to_str().unwrap();
+        let rows: String = (0..50).map(|i| format!("k{i:02}\tv{i}\n")).collect();
+        fs::write(&input, rows).unwrap();
+
+        let (result, out) = run_args(&["create", path, input_path, "--max-count", "4"]);
+        assert_eq!(result, Ok(true));
+        assert!(out.starts_with(&format!("created {path}: 50 keys")));
+
+        assert_eq!(run_args(&["get", path, "k07"]), (Ok(true), "v7\n".into()));
+        assert_eq!(run_args(&["get", path, "zz"]), (Ok(false), String::new()));
+
+        assert_eq!(run_args(&["put", path, "k07", "seven"]).0, Ok(true));
+        assert_eq!(run_args(&["del", path, "k08"]).0, Ok(true));
+        assert_eq!(run_args(&["del", path, "k08"]).0, Ok(false));
+        assert_eq!(
+            run_args(&["range", path, "k06", "k10"]),
+            (Ok(true), "k06\tv6\nk07\tseven\nk09\tv9\n".into())
+        );
+        assert_eq!(
+            run_args(&["range", path, "--limit", "2"]).1,
+            "k00\tv0\nk01\tv1\n"
+        );
+
+        let (_, stats) = run_args(&["stats", path]);
+        assert!(stats.contains("keys      49\n"));
+        assert!(
+            run_args(&["dot", path, "--depth", "0"])
+                .1
+                .starts_with("digraph btree {")
+        );
+        assert!(
+            run_args(&["render", path, "--highlight", "k07"])
+                .1
+                .contains("<\"k07\">")
+        );
+        assert_eq!(run_args(&["verify", path]), (Ok(true), "ok\n".into()));
+
+        assert!(run_args(&["get", path]).0.is_err());
+        assert!(run_args(&["stats", path, "--nope"]).0.is_err());
+        assert!(run_args(&["get", input_path, "k00"]).0.is_err());
+
+        // キーのない内部ノードを根に持つ、形式だけ正しいファイル
+        let mut corrupt = b"BTREE\0\0\x01".to_vec();
+        corrupt.extend(3u32.to_le_bytes());
+        corrupt.extend(1u64.to_le_bytes());
+        for n in [0u32, 1, 1, 0, 1] {
+            corrupt.extend(n.to_le_bytes());
+        }
+        corrupt.extend(b"a");
+        corrupt.extend(1u32.to_le_bytes());
+        corrupt.extend(b"x");
+        fs::write(&file, corrupt).unwrap();
+        assert!(run_args(&["del", path, "a"]).0.is_err());
+        assert_eq!(
+            run_args(&["verify", path]),
+            (
+                Ok(false),
+                "node at path []: 0 keys are below the minimum\n".into()
+            )
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
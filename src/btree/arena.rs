@@ -0,0 +1,429 @@
+use crate::btree::inline::InlineVec;
+use crate::btree::node::{
+    DeleteFromChildOperation, NodeStorage, merge_into, rotate_left, rotate_right,
+};
+use crate::btree::{BinarySearch, Delete, Insert, Search};
+
+/// 配列上に置くB木のノード
+///
+/// 子ノードは`ArenaBtree::nodes`上の位置で参照する。
+/// キー・値・子ノードはノード内の固定容量の配列に持つので、ノードごとのヒープ確保はない。
+struct ArenaNode<K, V, const B: usize> {
+    keys: InlineVec<K, B>,
+    values: InlineVec<V, B>,
+    children: InlineVec<u32, B>,
+}
+
+/// ノードを一つの配列にまとめて持つB木
+///
+/// `B`は子ノードの最大数で、キーは最大`B - 1`個まで持つ。
+/// 併合で使わなくなったノードは空きリストに戻して再利用する。
+/// 全てのノードが一つの配列に入っているので、木の破棄や`clear`で解放する領域は一つだけになる。
+///
+/// `Btree`とは別の型で、`search`・`insert`・`delete`・`clear`だけを持つ（`Btree`のノードは`Box`のまま）。
+pub struct ArenaBtree<K, V, const B: usize> {
+    nodes: Vec<ArenaNode<K, V, B>>,
+    free: Vec<u32>,
+    root: Option<u32>,
+}
+
+/// 分割で親に追加する中央のエントリと右のノード
+type ArenaSplit<K, V> = (K, V, u32);
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    ArenaNode<K, V, B>
+{
+    fn new() -> Self {
+        ArenaNode {
+            keys: InlineVec::new(),
+            values: InlineVec::new(),
+            children: InlineVec::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn lookup(&self, key: &K) -> Result<usize, usize> {
+        self.keys.as_slice().binary_lookup(key)
+    }
+
+    /// キーが`B`個になったノードから、中央のエントリと右半分のキー・値を切り出す
+    fn split_keys(&mut self) -> (K, V, ArenaNode<K, V, B>) {
+        let mid_index = B / 2;
+        let mut right = ArenaNode::new();
+        right.keys = self.keys.split_off(mid_index + 1);
+        right.values = self.values.split_off(mid_index + 1);
+        let key = self.keys.pop().unwrap();
+        let value = self.values.pop().unwrap();
+        (key, value, right)
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    ArenaBtree<K, V, B>
+{
+    const VALID_CAPACITY: () = assert!(B >= 3, "B must be at least 3");
+
+    pub fn new() -> Self {
+        let () = Self::VALID_CAPACITY;
+        ArenaBtree {
+            nodes: vec![],
+            free: vec![],
+            root: None,
+        }
+    }
+
+    /// 全てのエントリを削除する
+    ///
+    /// ノードの配列は確保したまま残すので、続けて挿入しても再確保しない。
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.free.clear();
+        self.root = None;
+    }
+
+    fn min_count() -> usize {
+        (B - 1) / 2
+    }
+
+    fn node(&self, id: u32) -> &ArenaNode<K, V, B> {
+        &self.nodes[id as usize]
+    }
+
+    fn node_mut(&mut self, id: u32) -> &mut ArenaNode<K, V, B> {
+        &mut self.nodes[id as usize]
+    }
+
+    fn allocate(&mut self, node: ArenaNode<K, V, B>) -> u32 {
+        match self.free.pop() {
+            Some(id) => {
+                *self.node_mut(id) = node;
+                id
+            }
+            None => {
+                let id = u32::try_from(self.nodes.len()).expect("too many nodes");
+                self.nodes.push(node);
+                id
+            }
+        }
+    }
+
+    /// 使わなくなったノードを取り出し、位置を再利用できるようにする
+    fn release(&mut self, id: u32) -> ArenaNode<K, V, B> {
+        self.free.push(id);
+        std::mem::replace(self.node_mut(id), ArenaNode::new())
+    }
+
+    /// ノードに挿入し、分割した場合は親に追加するエントリと右のノードを返す
+    fn insert_into(&mut self, id: u32, key: K, value: V) -> Option<ArenaSplit<K, V>> {
+        let node = self.node_mut(id);
+        let index = match node.lookup(&key) {
+            Ok(i) => {
+                node.keys.as_mut_slice()[i] = key;
+                node.values.as_mut_slice()[i] = value;
+                return None;
+            }
+            Err(i) => i,
+        };
+        if node.is_leaf() {
+            node.keys.insert(index, key);
+            node.values.insert(index, value);
+            if node.keys.len() < B {
+                return None;
+            }
+            let (key, value, right) = node.split_keys();
+            return Some((key, value, self.allocate(right)));
+        }
+
+        let child = node.children.as_slice()[index];
+        let (key, value, right) = self.insert_into(child, key, value)?;
+        let node = self.node_mut(id);
+        node.keys.insert(index, key);
+        node.values.insert(index, value);
+        if node.keys.len() < B {
+            node.children.insert(index + 1, right);
+            return None;
+        }
+
+        // 子ノードの配列には空きがないので、先に分割してから新しい子を入れる
+        let mid_index = B / 2;
+        let (mid_key, mid_value, mut sibling) = node.split_keys();
+        if index < mid_index {
+            sibling.children = node.children.split_off(mid_index);
+            node.children.insert(index + 1, right);
+        } else {
+            sibling.children = node.children.split_off(mid_index + 1);
+            sibling.children.insert(index - mid_index, right);
+        }
+        Some((mid_key, mid_value, self.allocate(sibling)))
+    }
+
+    fn delete_from(&mut self, id: u32, key: &K) {
+        let node = self.node_mut(id);
+        match node.lookup(key) {
+            Ok(i) => {
+                if node.is_leaf() {
+                    node.keys.remove(i);
+                    node.values.remove(i);
+                } else {
+                    // 左の部分木の最大のエントリで置き換える
+                    let child = node.children.as_slice()[i];
+                    let (key, value) = self.pop_max(child);
+                    let node = self.node_mut(id);
+                    node.keys.as_mut_slice()[i] = key;
+                    node.values.as_mut_slice()[i] = value;
+                    self.rebalance_child(id, i);
+                }
+            }
+            Err(i) => {
+                if node.is_leaf() {
+                    return;
+                }
+                let child = node.children.as_slice()[i];
+                self.delete_from(child, key);
+                self.rebalance_child(id, i);
+            }
+        }
+    }
+
+    /// 部分木から最大のエントリを取り除いて返す
+    fn pop_max(&mut self, id: u32) -> (K, V) {
+        let node = self.node_mut(id);
+        if node.is_leaf() {
+            return (node.keys.pop().unwrap(), node.values.pop().unwrap());
+        }
+        let index = node.children.len() - 1;
+        let child = node.children.as_slice()[index];
+        let entry = self.pop_max(child);
+        self.rebalance_child(id, index);
+        entry
+    }
+
+    /// 削除で子ノードが最小数を下回った場合に、兄弟からの回転または併合で補う
+    fn rebalance_child(&mut self, parent: u32, index: usize) {
+        let children = self.node(parent).children.as_slice();
+        let count = |i: usize| self.node(children[i]).entry_count();
+        if count(index) >= Self::min_count() {
+            return;
+        }
+        let has_spare = |i: usize| count(i) > Self::min_count();
+        match DeleteFromChildOperation::fill(index, children.len(), has_spare) {
+            DeleteFromChildOperation::None => {}
+            DeleteFromChildOperation::RotateRight => {
+                let ids = [parent, children[index - 1], children[index]];
+                let [parent, left, child] = self.nodes_mut(ids);
+                let separator = (
+                    &mut parent.keys.as_mut_slice()[index - 1],
+                    &mut parent.values.as_mut_slice()[index - 1],
+                );
+                rotate_right(separator, left, child);
+            }
+            DeleteFromChildOperation::RotateLeft => {
+                let ids = [parent, children[index], children[index + 1]];
+                let [parent, child, right] = self.nodes_mut(ids);
+                let separator = (
+                    &mut parent.keys.as_mut_slice()[index],
+                    &mut parent.values.as_mut_slice()[index],
+                );
+                rotate_left(separator, child, right);
+            }
+            DeleteFromChildOperation::MergeToLeft => self.merge_children(parent, index - 1),
+            DeleteFromChildOperation::MergeToRight => self.merge_children(parent, index),
+        }
+    }
+
+    /// 親と2つの子ノードを、同時に書き換えられるように取り出す
+    fn nodes_mut(&mut self, ids: [u32; 3]) -> [&mut ArenaNode<K, V, B>; 3] {
+        self.nodes
+            .get_disjoint_mut(ids.map(|id| id as usize))
+            .expect("parent and children must be distinct nodes")
+    }
+
+    /// 子ノード`index`と`index + 1`を、間のエントリを挟んで左側にまとめる
+    fn merge_children(&mut self, parent: u32, index: usize) {
+        let parent = self.node_mut(parent);
+        let separator = (parent.keys.remove(index), parent.values.remove(index));
+        let left = parent.children.as_slice()[index];
+        let right = parent.children.remove(index + 1);
+
+        let mut right = self.release(right);
+        merge_into(self.node_mut(left), separator, &mut right);
+    }
+}
+
+impl<K, V, const B: usize> NodeStorage for ArenaNode<K, V, B> {
+    type Key = K;
+    type Value = V;
+    type Child = u32;
+    type Keys = InlineVec<K, B>;
+    type Values = InlineVec<V, B>;
+    type Children = InlineVec<u32, B>;
+
+    fn parts(&self) -> (&InlineVec<K, B>, &InlineVec<V, B>, &InlineVec<u32, B>) {
+        (&self.keys, &self.values, &self.children)
+    }
+
+    fn parts_mut(
+        &mut self,
+    ) -> (
+        &mut InlineVec<K, B>,
+        &mut InlineVec<V, B>,
+        &mut InlineVec<u32, B>,
+    ) {
+        (&mut self.keys, &mut self.values, &mut self.children)
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Default
+    for ArenaBtree<K, V, B>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Search<K, V>
+    for ArenaBtree<K, V, B>
+{
+    fn search(&self, key: &K) -> Option<(K, V)> {
+        let mut node = self.node(self.root?);
+        loop {
+            match node.lookup(key) {
+                Ok(i) => {
+                    return Some((
+                        node.keys.as_slice()[i].clone(),
+                        node.values.as_slice()[i].clone(),
+                    ));
+                }
+                Err(i) => {
+                    if node.is_leaf() {
+                        return None;
+                    }
+                    node = self.node(node.children.as_slice()[i]);
+                }
+            }
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Insert<K, V>
+    for ArenaBtree<K, V, B>
+{
+    fn insert(&mut self, key: K, value: V) {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                let root = self.allocate(ArenaNode::new());
+                self.root = Some(root);
+                root
+            }
+        };
+        if let Some((key, value, right)) = self.insert_into(root, key, value) {
+            let mut node = ArenaNode::new();
+            node.keys.push(key);
+            node.values.push(value);
+            node.children.push(root);
+            node.children.push(right);
+            self.root = Some(self.allocate(node));
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Delete<K>
+    for ArenaBtree<K, V, B>
+{
+    fn delete(&mut self, key: &K) {
+        let Some(root) = self.root else {
+            return;
+        };
+        self.delete_from(root, key);
+
+        // 根の要素がなくなった場合は、唯一の子ノードを新しい根にする
+        if self.node(root).keys.is_empty() {
+            self.root = self.node(root).children.as_slice().first().copied();
+            self.release(root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::test_util;
+
+    /// 根から辿れるノードと空きリストのノードで、配列上の全ての位置を重複なく使っている
+    fn check_slots<const B: usize>(tree: &ArenaBtree<i32, i32, B>) {
+        let mut used = vec![false; tree.nodes.len()];
+        let mut stack: Vec<u32> = tree
+            .root
+            .into_iter()
+            .chain(tree.free.iter().copied())
+            .collect();
+        while let Some(id) = stack.pop() {
+            assert!(!used[id as usize]);
+            used[id as usize] = true;
+            if !tree.free.contains(&id) {
+                stack.extend(tree.node(id).children.as_slice());
+            }
+        }
+        assert!(used.iter().all(|&u| u));
+    }
+
+    fn random_operations<const B: usize>() {
+        let mut tree: ArenaBtree<i32, i32, B> = ArenaBtree::new();
+        test_util::random_operations(&mut tree);
+        if let Some(root) = tree.root {
+            test_util::check_node(tree.node(root), &|&id| tree.node(id), B, true);
+        }
+        check_slots(&tree);
+    }
+
+    #[test]
+    fn test_random_operations() {
+        random_operations::<3>();
+        random_operations::<4>();
+        random_operations::<5>();
+        random_operations::<16>();
+    }
+
+    #[test]
+    fn test_delete_all_reuses_nodes() {
+        let mut tree: ArenaBtree<i32, i32, 4> = ArenaBtree::new();
+        for i in 0..200 {
+            tree.insert(i, i);
+        }
+        let allocated = tree.nodes.len();
+        for i in 0..200 {
+            tree.delete(&i);
+        }
+        assert_eq!(tree.root, None);
+        assert_eq!(tree.free.len(), allocated);
+
+        // 解放したノードを使い回す
+        for i in 0..200 {
+            tree.insert(i, i);
+        }
+        assert_eq!(tree.nodes.len(), allocated);
+        check_slots(&tree);
+    }
+
+    #[test]
+    fn test_clear_keeps_capacity() {
+        let mut tree: ArenaBtree<String, i32, 8> = ArenaBtree::new();
+        for i in 0..500 {
+            tree.insert(format!("{i:04}"), i);
+        }
+        let capacity = tree.nodes.capacity();
+        tree.clear();
+        assert_eq!(tree.search(&"0001".to_string()), None);
+        assert_eq!(tree.nodes.capacity(), capacity);
+
+        tree.insert("0001".to_string(), 1);
+        assert_eq!(
+            tree.search(&"0001".to_string()),
+            Some(("0001".to_string(), 1))
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use crate::btree::BinarySearch;
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+type Link<K, V> = Arc<RwLock<BlinkNode<K, V>>>;
+
+fn link<K, V>(node: BlinkNode<K, V>) -> Link<K, V> {
+    Arc::new(RwLock::new(node))
+}
+
+/// B-link木のノード
+///
+/// 各ノードは自身が担当するキーの上限（`high_key`、`None`は上限なし）と、
+/// 同じ階層の右隣のノードへのリンクを持つ。
+/// 内部ノードの`keys[i]`は`children[i]`が担当するキーの上限で、最後の子は`high_key`までを担当する。
+/// 値は葉ノードにだけ置く。
+struct BlinkNode<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    children: Vec<Link<K, V>>,
+    high_key: Option<K>,
+    right: Option<Link<K, V>>,
+    level: usize,
+}
+
+/// Lehman–Yaoの B-link木
+///
+/// 分割中のノードに行き当たった読み込みは、右隣へのリンクをたどって目的のノードに追いつくため、
+/// やり直しが不要で、同時に保持するラッチは常に1つだけになる。
+/// 挿入も親をロックせずに子を分割し、分割したノードを解放してから親へ区切りキーを追加する。
+///
+/// 削除は葉からエントリを取り除くだけで、ノードの併合は行わない。
+pub struct BlinkTree<K, V> {
+    root: RwLock<Link<K, V>>,
+    max_count: usize,
+}
+
+enum Step<K, V> {
+    Right(Link<K, V>),
+    Down(Link<K, V>),
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BlinkNode<K, V> {
+    fn new(level: usize) -> Self {
+        Self {
+            keys: vec![],
+            values: vec![],
+            children: vec![],
+            high_key: None,
+            right: None,
+            level,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.level == 0
+    }
+
+    fn is_full(&self, max_count: usize) -> bool {
+        self.keys.len() >= max_count
+    }
+
+    /// `key`がこのノードの担当範囲より右にあるか
+    fn is_beyond(&self, key: &K) -> bool {
+        match &self.high_key {
+            Some(high_key) => key > high_key,
+            None => false,
+        }
+    }
+
+    fn child_index(&self, key: &K) -> usize {
+        match self.keys.binary_lookup(key) {
+            Ok(i) | Err(i) => i,
+        }
+    }
+
+    /// `key`を探すときに次に進むノード
+    fn next_step(&self, key: &K) -> Option<Step<K, V>> {
+        if self.is_beyond(key) {
+            return self.right.clone().map(Step::Right);
+        }
+        if self.is_leaf() {
+            return None;
+        }
+        Some(Step::Down(Arc::clone(
+            &self.children[self.child_index(key)],
+        )))
+    }
+
+    /// ノードを分割して右半分を右隣に繋ぎ、親に追加する区切りキーと右のノードを返す
+    fn split_node(&mut self) -> (K, Link<K, V>) {
+        let mid_index = self.keys.len() / 2;
+        let (separator, right) = if self.is_leaf() {
+            let keys = self.keys.split_off(mid_index);
+            let values = self.values.split_off(mid_index);
+            let separator = self.keys[mid_index - 1].clone();
+            (separator, (keys, values, vec![]))
+        } else {
+            let keys = self.keys.split_off(mid_index + 1);
+            let children = self.children.split_off(mid_index + 1);
+            let separator = self.keys.pop().unwrap();
+            (separator, (keys, vec![], children))
+        };
+        let (keys, values, children) = right;
+        let right = link(BlinkNode {
+            keys,
+            values,
+            children,
+            high_key: self.high_key.replace(separator.clone()),
+            right: self.right.take(),
+            level: self.level,
+        });
+        self.right = Some(Arc::clone(&right));
+        (separator, right)
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BlinkTree<K, V> {
+    pub fn new(max_count: usize) -> Self {
+        assert!(max_count >= 3, "max_count must be at least 3");
+        BlinkTree {
+            root: RwLock::new(link(BlinkNode::new(0))),
+            max_count,
+        }
+    }
+
+    fn root(&self) -> Link<K, V> {
+        Arc::clone(&self.root.read().unwrap())
+    }
+
+    pub fn search(&self, key: &K) -> Option<(K, V)> {
+        let mut node = self.descend(key, 0, &mut vec![]);
+        loop {
+            let next = {
+                let guard = node.read().unwrap();
+                if !guard.is_beyond(key) {
+                    return match guard.keys.binary_lookup(key) {
+                        Ok(i) => Some((guard.keys[i].clone(), guard.values[i].clone())),
+                        Err(_) => None,
+                    };
+                }
+                Arc::clone(guard.right.as_ref().unwrap())
+            };
+            node = next;
+        }
+    }
+
+    /// 根から`level`の階層まで降り、通過した上の階層のノードを`stack`に積む
+    fn descend(&self, key: &K, level: usize, stack: &mut Vec<Link<K, V>>) -> Link<K, V> {
+        let mut node = self.root();
+        loop {
+            let step = {
+                let guard = node.read().unwrap();
+                if guard.level == level {
+                    return Arc::clone(&node);
+                }
+                guard.next_step(key)
+            };
+            match step {
+                Some(Step::Right(next)) => node = next,
+                Some(Step::Down(next)) => {
+                    stack.push(node);
+                    node = next;
+                }
+                None => unreachable!("descended below the leaf level"),
+            }
+        }
+    }
+
+    /// 他のスレッドの分割で担当範囲が右にずれていれば、右隣へのリンクをたどる
+    fn move_right(&self, mut node: Link<K, V>, key: &K) -> Link<K, V> {
+        loop {
+            let next = {
+                let guard = node.read().unwrap();
+                if guard.is_beyond(key) {
+                    guard.right.clone()
+                } else {
+                    None
+                }
+            };
+            match next {
+                Some(next) => node = next,
+                None => return node,
+            }
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut stack = vec![];
+        let mut node = self.descend(&key, 0, &mut stack);
+
+        let mut pending = loop {
+            let next = {
+                let mut guard = node.write().unwrap();
+                if guard.is_beyond(&key) {
+                    Arc::clone(guard.right.as_ref().unwrap())
+                } else {
+                    match guard.keys.binary_lookup(&key) {
+                        Ok(i) => {
+                            guard.values[i] = value;
+                            return;
+                        }
+                        Err(i) => {
+                            guard.keys.insert(i, key);
+                            guard.values.insert(i, value);
+                        }
+                    }
+                    if !guard.is_full(self.max_count) {
+                        return;
+                    }
+                    break guard.split_node();
+                }
+            };
+            node = next;
+        };
+
+        // 分割したノードのラッチは解放済みなので、親へ区切りキーを追加する間も他のスレッドは右リンクで進める
+        let mut level = 1;
+        loop {
+            let (separator, right) = pending;
+            let parent = match stack.pop() {
+                Some(parent) => parent,
+                None => loop {
+                    match self.grow_root(&node, separator.clone(), &right) {
+                        Ok(()) => return,
+                        // 他のスレッドが先に根を分割したので、新しい根から親の階層を探し直す
+                        Err(root_level) if root_level >= level => {
+                            break self.descend(&separator, level, &mut stack);
+                        }
+                        // 根の右隣を分割した場合は、根を分割したスレッドが新しい根を作るのを待つ
+                        Err(_) => thread::yield_now(),
+                    }
+                },
+            };
+            let mut parent = self.move_right(parent, &separator);
+
+            pending = loop {
+                let next = {
+                    let mut guard = parent.write().unwrap();
+                    if guard.is_beyond(&separator) {
+                        Arc::clone(guard.right.as_ref().unwrap())
+                    } else {
+                        let index = guard.child_index(&separator);
+                        guard.keys.insert(index, separator);
+                        guard.children.insert(index + 1, right);
+                        if !guard.is_full(self.max_count) {
+                            return;
+                        }
+                        break guard.split_node();
+                    }
+                };
+                parent = next;
+            };
+            node = parent;
+            level += 1;
+        }
+    }
+
+    /// `node`がまだ根であれば分割結果を子に持つ新しい根を作り、そうでなければ今の根の階層を返す
+    fn grow_root(&self, node: &Link<K, V>, separator: K, right: &Link<K, V>) -> Result<(), usize> {
+        let mut root = self.root.write().unwrap();
+        if !Arc::ptr_eq(&root, node) {
+            return Err(root.read().unwrap().level);
+        }
+        let level = node.read().unwrap().level + 1;
+        *root = link(BlinkNode {
+            keys: vec![separator],
+            values: vec![],
+            children: vec![Arc::clone(node), Arc::clone(right)],
+            high_key: None,
+            right: None,
+            level,
+        });
+        Ok(())
+    }
+
+    pub fn delete(&self, key: &K) {
+        let mut node = self.descend(key, 0, &mut vec![]);
+        loop {
+            let next = {
+                let mut guard = node.write().unwrap();
+                if !guard.is_beyond(key) {
+                    if let Ok(i) = guard.keys.binary_lookup(key) {
+                        guard.keys.remove(i);
+                        guard.values.remove(i);
+                    }
+                    return;
+                }
+                Arc::clone(guard.right.as_ref().unwrap())
+            };
+            node = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 各階層を右リンクでたどり、キーの順序と上限キーの整合性を検証する
+    fn check_invariants(tree: &BlinkTree<i64, i64>) {
+        let mut leftmost = Some(tree.root());
+        while let Some(first) = leftmost {
+            let mut previous_high: Option<i64> = None;
+            let mut node = Some(first.clone());
+            while let Some(current) = node {
+                let guard = current.read().unwrap();
+                assert!(guard.keys.windows(2).all(|w| w[0] < w[1]));
+                assert!(
+                    guard
+                        .keys
+                        .iter()
+                        .all(|k| previous_high.is_none_or(|h| h < *k))
+                );
+                assert!(
+                    guard
+                        .keys
+                        .iter()
+                        .all(|k| guard.high_key.is_none_or(|h| *k <= h))
+                );
+                if !guard.is_leaf() {
+                    assert_eq!(guard.children.len(), guard.keys.len() + 1);
+                }
+                assert_eq!(guard.right.is_none(), guard.high_key.is_none());
+                previous_high = guard.high_key;
+                node = guard.right.clone();
+            }
+            let guard = first.read().unwrap();
+            leftmost = guard.children.first().cloned();
+        }
+    }
+
+    #[test]
+    fn test_insert_and_search() {
+        let tree = BlinkTree::new(3);
+        for i in 1..=100 {
+            tree.insert(i * 10, i * 100);
+        }
+        for i in 1..=100 {
+            assert_eq!(tree.search(&(i * 10)), Some((i * 10, i * 100)));
+        }
+        assert_eq!(tree.search(&15), None);
+        assert_eq!(tree.search(&2000), None);
+        check_invariants(&tree);
+    }
+
+    #[test]
+    fn test_insert_same_key() {
+        let tree = BlinkTree::new(4);
+        for i in (0..50).rev() {
+            tree.insert(i, i);
+        }
+        for i in 0..50 {
+            tree.insert(i, i * 2);
+        }
+        for i in 0..50 {
+            assert_eq!(tree.search(&i), Some((i, i * 2)));
+        }
+        check_invariants(&tree);
+    }
+
+    #[test]
+    fn test_delete() {
+        let tree = BlinkTree::new(3);
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        for i in (0..100).step_by(3) {
+            tree.delete(&i);
+        }
+        tree.delete(&1000);
+        for i in 0..100 {
+            let expected = if i % 3 == 0 { None } else { Some((i, i)) };
+            assert_eq!(tree.search(&i), expected);
+        }
+        check_invariants(&tree);
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        for max_count in [3, 8, 32] {
+            let tree = BlinkTree::new(max_count);
+            let threads = 8;
+            let per_thread = 2_000;
+
+            thread::scope(|scope| {
+                for id in 0..threads {
+                    let tree = &tree;
+                    scope.spawn(move || {
+                        // スレッドごとにキーを交互に割り当て、同じノードの分割が競合するようにする
+                        for i in 0..per_thread {
+                            let key = i * threads + id;
+                            tree.insert(key, -key);
+                            assert_eq!(tree.search(&key), Some((key, -key)));
+                        }
+                    });
+                }
+                for _ in 0..2 {
+                    let tree = &tree;
+                    scope.spawn(move || {
+                        for key in 0..threads * per_thread {
+                            if let Some((k, v)) = tree.search(&key) {
+                                assert_eq!((k, v), (key, -key));
+                            }
+                        }
+                    });
+                }
+            });
+
+            for key in 0..threads * per_thread {
+                assert_eq!(tree.search(&key), Some((key, -key)));
+            }
+            check_invariants(&tree);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use crate::btree::node::BtreeNode;
+use crate::btree::tree::Btree;
+use crate::btree::{Delete, Insert, Search};
+use std::ops::RangeBounds;
+
+/// 容量を超えたときに追い出すエントリの選び方
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// 最後に使われたのが最も古いエントリ
+    #[default]
+    LeastRecentlyUsed,
+    SmallestKey,
+    LargestKey,
+}
+
+#[derive(Clone)]
+struct Entry<V> {
+    value: V,
+    /// 最後に使われた時刻（`recency`のキー）
+    tick: u64,
+    weight: usize,
+}
+
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> usize>;
+type EvictCallback<K, V> = Box<dyn FnMut(K, V)>;
+
+/// エントリの重さの合計が容量を超えないように、エントリを追い出すB木
+///
+/// 重さは`with_weigher`で決め、指定しなければ1エントリを1と数える（容量はエントリ数になる）。
+/// `insert`と`search`がエントリを使ったことになり、`peek`や`iter`は使ったことにならない。
+pub struct BoundedBtree<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    entries: Btree<K, Entry<V>>,
+    /// 使われた時刻の順に並べたキー
+    recency: Btree<u64, K>,
+    tick: u64,
+    len: usize,
+    weight: usize,
+    capacity: usize,
+    policy: EvictionPolicy,
+    weigher: Weigher<K, V>,
+    on_evict: EvictCallback<K, V>,
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BoundedBtree<K, V> {
+    /// 重さの合計が`capacity`までのエントリを持つ木を作る
+    ///
+    /// # Panics
+    ///
+    /// `max_count`が3未満の場合（`Btree::new`と同じ）
+    pub fn new(max_count: usize, capacity: usize) -> Self {
+        BoundedBtree {
+            entries: Btree::new(max_count),
+            recency: Btree::new(max_count),
+            tick: 0,
+            len: 0,
+            weight: 0,
+            capacity,
+            policy: EvictionPolicy::default(),
+            weigher: Box::new(|_, _| 1),
+            on_evict: Box::new(|_, _| {}),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// エントリの重さ（バイト数など）の数え方を指定する
+    ///
+    /// 既に入っているエントリの重さは数え直さないので、エントリを入れる前に呼ぶ。
+    pub fn with_weigher(mut self, weigher: impl Fn(&K, &V) -> usize + 'static) -> Self {
+        self.weigher = Box::new(weigher);
+        self
+    }
+
+    /// 追い出したエントリを受け取る関数を指定する（`delete`で削除したエントリは渡さない）
+    pub fn on_evict(mut self, callback: impl FnMut(K, V) + 'static) -> Self {
+        self.on_evict = Box::new(callback);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// エントリの重さの合計
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// エントリを挿入し、容量を超えた分だけ追い出す
+    ///
+    /// 1つで容量を超えるエントリは、他のエントリをすべて追い出した後、それ自身も追い出される。
+    pub fn insert(&mut self, key: K, value: V) {
+        self.remove(&key);
+        let weight = (self.weigher)(&key, &value);
+        let tick = self.next_tick();
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                tick,
+                weight,
+            },
+        );
+        self.len += 1;
+        self.weight += weight;
+        self.evict_over_capacity();
+    }
+
+    /// エントリを探し、見つかればそのエントリを使ったことにする
+    pub fn search(&mut self, key: &K) -> Option<(K, V)> {
+        let (key, mut entry) = self.entries.search(key)?;
+        self.recency.delete(&entry.tick);
+        entry.tick = self.next_tick();
+        self.recency.insert(entry.tick, key.clone());
+        self.entries.insert(key.clone(), entry.clone());
+        Some((key, entry.value))
+    }
+
+    /// 使ったことにせずにエントリを探す
+    pub fn peek(&self, key: &K) -> Option<(K, V)> {
+        self.entries
+            .search(key)
+            .map(|(key, entry)| (key, entry.value))
+    }
+
+    pub fn delete(&mut self, key: &K) {
+        self.remove(key);
+    }
+
+    /// エントリをキーの昇順にたどる
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.range(..)
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> impl Iterator<Item = (&K, &V)> {
+        self.entries
+            .range(range)
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, key: &K) -> Option<(K, V)> {
+        let (key, entry) = self.entries.search(key)?;
+        self.entries.delete(&key);
+        self.recency.delete(&entry.tick);
+        self.len -= 1;
+        self.weight -= entry.weight;
+        Some((key, entry.value))
+    }
+
+    fn evict_over_capacity(&mut self) {
+        while self.weight > self.capacity {
+            let Some(victim) = self.victim() else {
+                return;
+            };
+            if let Some((key, value)) = self.remove(&victim) {
+                (self.on_evict)(key, value);
+            }
+        }
+    }
+
+    /// 次に追い出すエントリのキー
+    fn victim(&self) -> Option<K> {
+        match self.policy {
+            EvictionPolicy::LeastRecentlyUsed => {
+                self.recency.iter().next().map(|(_, key)| key.clone())
+            }
+            EvictionPolicy::SmallestKey => self.entries.iter().next().map(|(key, _)| key.clone()),
+            EvictionPolicy::LargestKey => self.entries.root().map(largest_key),
+        }
+    }
+}
+
+/// 部分木の最大のキー（右端の葉の最後のキー）
+fn largest_key<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone>(
+    node: &BtreeNode<K, V>,
+) -> K {
+    match node.children().last() {
+        Some(child) => largest_key(child),
+        None => node.keys()[node.keys().len() - 1].clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::test_util::XorShift;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    fn keys(tree: &BoundedBtree<i32, String>) -> Vec<i32> {
+        tree.iter().map(|(key, _)| *key).collect()
+    }
+
+    #[test]
+    #[should_panic(expected = "max_count must be at least 3")]
+    fn test_max_count_too_small() {
+        BoundedBtree::<i32, ()>::new(2, 10);
+    }
+
+    #[test]
+    fn test_least_recently_used() {
+        let evicted = Rc::new(RefCell::new(vec![]));
+        let log = Rc::clone(&evicted);
+        let mut tree = BoundedBtree::new(3, 3)
+            .on_evict(move |key, value: String| log.borrow_mut().push((key, value)));
+        for i in 1..=3 {
+            tree.insert(i, i.to_string());
+        }
+        // 1を使ったので、次に追い出されるのは2
+        assert_eq!(tree.search(&1), Some((1, "1".to_string())));
+        assert_eq!(tree.peek(&2), Some((2, "2".to_string())));
+        tree.insert(4, "4".to_string());
+        assert_eq!(keys(&tree), vec![1, 3, 4]);
+        tree.insert(3, "three".to_string());
+        tree.insert(5, "5".to_string());
+        assert_eq!(keys(&tree), vec![3, 4, 5]);
+        assert_eq!(
+            *evicted.borrow(),
+            vec![(2, "2".to_string()), (1, "1".to_string())]
+        );
+
+        tree.delete(&4);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(evicted.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_key_order_policies() {
+        let mut smallest = BoundedBtree::new(3, 4).with_policy(EvictionPolicy::SmallestKey);
+        let mut largest = BoundedBtree::new(3, 4).with_policy(EvictionPolicy::LargestKey);
+        for i in [5, 1, 9, 3, 7, 2] {
+            smallest.insert(i, i.to_string());
+            largest.insert(i, i.to_string());
+        }
+        assert_eq!(keys(&smallest), vec![3, 5, 7, 9]);
+        assert_eq!(keys(&largest), vec![1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn test_weight_capacity() {
+        let evicted = Rc::new(RefCell::new(vec![]));
+        let log = Rc::clone(&evicted);
+        let mut tree = BoundedBtree::new(3, 10)
+            .with_weigher(|_, value: &String| value.len())
+            .on_evict(move |key, _| log.borrow_mut().push(key));
+        tree.insert(1, "aaaa".to_string());
+        tree.insert(2, "bbbb".to_string());
+        assert_eq!(tree.weight(), 8);
+        tree.insert(3, "cc".to_string());
+        assert_eq!(tree.weight(), 10);
+        tree.insert(2, "b".to_string());
+        assert_eq!(tree.weight(), 7);
+        tree.insert(4, "dddddd".to_string());
+        assert_eq!(keys(&tree), vec![2, 3, 4]);
+        assert_eq!(tree.weight(), 9);
+
+        // 容量より重いエントリは残らない
+        tree.insert(5, "e".repeat(11));
+        assert!(tree.is_empty());
+        assert_eq!(tree.weight(), 0);
+        assert_eq!(*evicted.borrow(), vec![1, 3, 2, 4, 5]);
+    }
+
+    #[test]
+    fn test_random_operations() {
+        let mut tree = BoundedBtree::new(4, 20);
+        // 使われた順（先頭が最も古い）に並べたキー
+        let mut expected: VecDeque<i32> = VecDeque::new();
+        let mut rng = XorShift::default();
+        for _ in 0..3000 {
+            let seed = rng.next_u64();
+            let key = (seed % 50) as i32;
+            let position = expected.iter().position(|&k| k == key);
+            match seed % 3 {
+                0 => {
+                    tree.delete(&key);
+                    position.map(|i| expected.remove(i));
+                }
+                1 => {
+                    assert_eq!(tree.search(&key).is_some(), position.is_some());
+                    if let Some(i) = position {
+                        expected.remove(i);
+                        expected.push_back(key);
+                    }
+                }
+                _ => {
+                    tree.insert(key, key.to_string());
+                    position.map(|i| expected.remove(i));
+                    expected.push_back(key);
+                    if expected.len() > 20 {
+                        expected.pop_front();
+                    }
+                }
+            }
+            let mut sorted: Vec<i32> = expected.iter().copied().collect();
+            sorted.sort();
+            assert_eq!(keys(&tree), sorted);
+            assert_eq!(tree.len(), expected.len());
+        }
+    }
+}
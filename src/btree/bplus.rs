@@ -0,0 +1,717 @@
+use crate::btree::{BinarySearch, Delete, Insert, Search};
+use std::ops::{Bound, RangeBounds};
+
+/// B+木のノード
+///
+/// 内部ノードは区切りキーだけを持ち、`keys[i]`は`children[i + 1]`の部分木の最小キー以下になる。
+/// 葉ノードは全てのエントリを持ち、左右の葉と双方向に繋がっている。
+#[derive(Clone)]
+enum BplusNode<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    Internal {
+        keys: Vec<K>,
+        children: Vec<usize>,
+    },
+    Leaf {
+        keys: Vec<K>,
+        values: Vec<V>,
+        prev: Option<usize>,
+        next: Option<usize>,
+    },
+}
+
+/// 値を葉ノードにだけ置くB+木
+///
+/// ノードは木が持つ配列に置き、子や隣の葉は配列上の位置で参照する。
+/// 範囲の走査は最初の葉を探したあとは葉の連結をたどるだけで、内部ノードには戻らない。
+#[derive(Clone)]
+pub struct BplusTree<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    nodes: Vec<BplusNode<K, V>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    max_count: usize,
+}
+
+/// `BplusTree`のエントリをキーの順にたどるイテレータ
+pub struct BplusRange<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    tree: &'a BplusTree<K, V>,
+    /// 次に前から返すエントリの位置
+    front: Option<(usize, usize)>,
+    /// 次に後ろから返すエントリの一つ後ろの位置
+    back: Option<(usize, usize)>,
+    start: Bound<K>,
+    end: Bound<K>,
+    last_front: Option<&'a K>,
+    last_back: Option<&'a K>,
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BplusNode<K, V> {
+    fn keys(&self) -> &Vec<K> {
+        match self {
+            BplusNode::Internal { keys, .. } | BplusNode::Leaf { keys, .. } => keys,
+        }
+    }
+
+    fn current_count(&self) -> usize {
+        self.keys().len()
+    }
+
+    /// `key`を含む子ノードの位置
+    fn child_index(&self, key: &K) -> usize {
+        match self.keys().binary_lookup(key) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BplusTree<K, V> {
+    pub fn new(max_count: usize) -> Self {
+        assert!(max_count >= 3, "max_count must be at least 3");
+        BplusTree {
+            nodes: vec![],
+            free: vec![],
+            root: None,
+            max_count,
+        }
+    }
+
+    fn min_count(&self) -> usize {
+        (self.max_count - 1) / 2
+    }
+
+    fn allocate(&mut self, node: BplusNode<K, V>) -> usize {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// 使わなくなったノードを空にして、再利用できるようにする
+    fn release(&mut self, id: usize) {
+        self.nodes[id] = BplusNode::Internal {
+            keys: vec![],
+            children: vec![],
+        };
+        self.free.push(id);
+    }
+
+    fn children(&self, id: usize) -> &Vec<usize> {
+        match &self.nodes[id] {
+            BplusNode::Internal { children, .. } => children,
+            BplusNode::Leaf { .. } => unreachable!("leaf has no children"),
+        }
+    }
+
+    fn set_next(&mut self, id: usize, link: Option<usize>) {
+        if let BplusNode::Leaf { next, .. } = &mut self.nodes[id] {
+            *next = link;
+        }
+    }
+
+    fn set_prev(&mut self, id: usize, link: Option<usize>) {
+        if let BplusNode::Leaf { prev, .. } = &mut self.nodes[id] {
+            *prev = link;
+        }
+    }
+
+    /// `key`が含まれるはずの葉ノード
+    fn find_leaf(&self, key: &K) -> Option<usize> {
+        let mut id = self.root?;
+        while let BplusNode::Internal { children, .. } = &self.nodes[id] {
+            id = children[self.nodes[id].child_index(key)];
+        }
+        Some(id)
+    }
+
+    fn leftmost_leaf(&self) -> Option<usize> {
+        let mut id = self.root?;
+        while let BplusNode::Internal { children, .. } = &self.nodes[id] {
+            id = children[0];
+        }
+        Some(id)
+    }
+
+    fn rightmost_leaf(&self) -> Option<usize> {
+        let mut id = self.root?;
+        while let BplusNode::Internal { children, .. } = &self.nodes[id] {
+            id = children[children.len() - 1];
+        }
+        Some(id)
+    }
+
+    pub fn iter(&self) -> BplusRange<'_, K, V> {
+        self.range(..)
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> BplusRange<'_, K, V> {
+        let front = match range.start_bound() {
+            Bound::Unbounded => self.leftmost_leaf().map(|id| (id, 0)),
+            Bound::Included(key) | Bound::Excluded(key) => self.find_leaf(key).map(|id| {
+                let index = match self.nodes[id].keys().binary_lookup(key) {
+                    Ok(i) if matches!(range.start_bound(), Bound::Excluded(_)) => i + 1,
+                    Ok(i) | Err(i) => i,
+                };
+                (id, index)
+            }),
+        };
+        let back = match range.end_bound() {
+            Bound::Unbounded => self
+                .rightmost_leaf()
+                .map(|id| (id, self.nodes[id].current_count())),
+            Bound::Included(key) | Bound::Excluded(key) => self.find_leaf(key).map(|id| {
+                let index = match self.nodes[id].keys().binary_lookup(key) {
+                    Ok(i) if matches!(range.end_bound(), Bound::Included(_)) => i + 1,
+                    Ok(i) | Err(i) => i,
+                };
+                (id, index)
+            }),
+        };
+        BplusRange {
+            tree: self,
+            front,
+            back,
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+            last_front: None,
+            last_back: None,
+        }
+    }
+
+    /// ノードに挿入し、分割した場合は親に追加する区切りキーと右のノードを返す
+    fn insert_into(&mut self, id: usize, key: K, value: V) -> Option<(K, usize)> {
+        let index = self.nodes[id].child_index(&key);
+        match &mut self.nodes[id] {
+            BplusNode::Leaf { keys, values, .. } => {
+                match keys.binary_lookup(&key) {
+                    Ok(i) => {
+                        values[i] = value;
+                        return None;
+                    }
+                    Err(i) => {
+                        keys.insert(i, key);
+                        values.insert(i, value);
+                    }
+                }
+                if keys.len() < self.max_count {
+                    return None;
+                }
+                Some(self.split_leaf(id))
+            }
+            BplusNode::Internal { children, .. } => {
+                let child = children[index];
+                let (separator, right) = self.insert_into(child, key, value)?;
+                if let BplusNode::Internal { keys, children } = &mut self.nodes[id] {
+                    keys.insert(index, separator);
+                    children.insert(index + 1, right);
+                    if keys.len() < self.max_count {
+                        return None;
+                    }
+                }
+                Some(self.split_internal(id))
+            }
+        }
+    }
+
+    fn split_leaf(&mut self, id: usize) -> (K, usize) {
+        let BplusNode::Leaf {
+            keys, values, next, ..
+        } = &mut self.nodes[id]
+        else {
+            unreachable!("split_leaf on internal node");
+        };
+        let mid_index = keys.len() / 2;
+        let right_keys = keys.split_off(mid_index);
+        let right_values = values.split_off(mid_index);
+        let old_next = *next;
+        let separator = right_keys[0].clone();
+
+        let right = self.allocate(BplusNode::Leaf {
+            keys: right_keys,
+            values: right_values,
+            prev: Some(id),
+            next: old_next,
+        });
+        self.set_next(id, Some(right));
+        if let Some(old_next) = old_next {
+            self.set_prev(old_next, Some(right));
+        }
+        (separator, right)
+    }
+
+    fn split_internal(&mut self, id: usize) -> (K, usize) {
+        let BplusNode::Internal { keys, children } = &mut self.nodes[id] else {
+            unreachable!("split_internal on leaf node");
+        };
+        let mid_index = keys.len() / 2;
+        let right_keys = keys.split_off(mid_index + 1);
+        let right_children = children.split_off(mid_index + 1);
+        let separator = keys.pop().unwrap();
+        let right = self.allocate(BplusNode::Internal {
+            keys: right_keys,
+            children: right_children,
+        });
+        (separator, right)
+    }
+
+    fn delete_from(&mut self, id: usize, key: &K) {
+        let index = self.nodes[id].child_index(key);
+        match &mut self.nodes[id] {
+            BplusNode::Leaf { keys, values, .. } => {
+                if let Ok(i) = keys.binary_lookup(key) {
+                    keys.remove(i);
+                    values.remove(i);
+                }
+            }
+            BplusNode::Internal { children, .. } => {
+                let child = children[index];
+                self.delete_from(child, key);
+                if self.nodes[child].current_count() < self.min_count() {
+                    self.rebalance_child(id, index);
+                }
+            }
+        }
+    }
+
+    /// 最小数を下回った子ノードを、兄弟からの移動または併合で補う
+    fn rebalance_child(&mut self, parent: usize, index: usize) {
+        let children = self.children(parent).clone();
+        if index > 0 && self.nodes[children[index - 1]].current_count() > self.min_count() {
+            // 一つ左が十分な要素を持っている
+            self.borrow_from_left(parent, index);
+        } else if index + 1 < children.len()
+            && self.nodes[children[index + 1]].current_count() > self.min_count()
+        {
+            // 一つ右が十分な要素を持っている
+            self.borrow_from_right(parent, index);
+        } else if index > 0 {
+            self.merge_children(parent, index - 1);
+        } else {
+            self.merge_children(parent, index);
+        }
+    }
+
+    fn separator(&mut self, parent: usize, index: usize) -> &mut K {
+        match &mut self.nodes[parent] {
+            BplusNode::Internal { keys, .. } => &mut keys[index],
+            BplusNode::Leaf { .. } => unreachable!("leaf has no separators"),
+        }
+    }
+
+    fn borrow_from_left(&mut self, parent: usize, index: usize) {
+        let left = self.children(parent)[index - 1];
+        let child = self.children(parent)[index];
+        match &mut self.nodes[left] {
+            BplusNode::Leaf { keys, values, .. } => {
+                let key = keys.pop().unwrap();
+                let value = values.pop().unwrap();
+                *self.separator(parent, index - 1) = key.clone();
+                if let BplusNode::Leaf { keys, values, .. } = &mut self.nodes[child] {
+                    keys.insert(0, key);
+                    values.insert(0, value);
+                }
+            }
+            BplusNode::Internal { keys, children } => {
+                let key = keys.pop().unwrap();
+                let grandchild = children.pop().unwrap();
+                let separator = std::mem::replace(self.separator(parent, index - 1), key);
+                if let BplusNode::Internal { keys, children } = &mut self.nodes[child] {
+                    keys.insert(0, separator);
+                    children.insert(0, grandchild);
+                }
+            }
+        }
+    }
+
+    fn borrow_from_right(&mut self, parent: usize, index: usize) {
+        let child = self.children(parent)[index];
+        let right = self.children(parent)[index + 1];
+        match &mut self.nodes[right] {
+            BplusNode::Leaf { keys, values, .. } => {
+                let key = keys.remove(0);
+                let value = values.remove(0);
+                *self.separator(parent, index) = keys[0].clone();
+                if let BplusNode::Leaf { keys, values, .. } = &mut self.nodes[child] {
+                    keys.push(key);
+                    values.push(value);
+                }
+            }
+            BplusNode::Internal { keys, children } => {
+                let key = keys.remove(0);
+                let grandchild = children.remove(0);
+                let separator = std::mem::replace(self.separator(parent, index), key);
+                if let BplusNode::Internal { keys, children } = &mut self.nodes[child] {
+                    keys.push(separator);
+                    children.push(grandchild);
+                }
+            }
+        }
+    }
+
+    /// 子ノード`index`と`index + 1`を左側にまとめる
+    fn merge_children(&mut self, parent: usize, index: usize) {
+        let BplusNode::Internal { keys, children } = &mut self.nodes[parent] else {
+            unreachable!("leaf has no children");
+        };
+        let separator = keys.remove(index);
+        let left = children[index];
+        let right = children.remove(index + 1);
+
+        let right_node = std::mem::replace(
+            &mut self.nodes[right],
+            BplusNode::Internal {
+                keys: vec![],
+                children: vec![],
+            },
+        );
+        let mut relink = None;
+        match (&mut self.nodes[left], right_node) {
+            (
+                BplusNode::Leaf {
+                    keys, values, next, ..
+                },
+                BplusNode::Leaf {
+                    keys: mut right_keys,
+                    values: mut right_values,
+                    next: right_next,
+                    ..
+                },
+            ) => {
+                keys.append(&mut right_keys);
+                values.append(&mut right_values);
+                *next = right_next;
+                relink = right_next;
+            }
+            (
+                BplusNode::Internal { keys, children },
+                BplusNode::Internal {
+                    keys: mut right_keys,
+                    children: mut right_children,
+                },
+            ) => {
+                keys.push(separator);
+                keys.append(&mut right_keys);
+                children.append(&mut right_children);
+            }
+            _ => unreachable!("siblings must be on the same level"),
+        }
+        if let Some(next) = relink {
+            self.set_prev(next, Some(left));
+        }
+        self.release(right);
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Search<K, V>
+    for BplusTree<K, V>
+{
+    fn search(&self, key: &K) -> Option<(K, V)> {
+        let leaf = self.find_leaf(key)?;
+        match &self.nodes[leaf] {
+            BplusNode::Leaf { keys, values, .. } => match keys.binary_lookup(key) {
+                Ok(i) => Some((keys[i].clone(), values[i].clone())),
+                Err(_) => None,
+            },
+            BplusNode::Internal { .. } => None,
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Insert<K, V>
+    for BplusTree<K, V>
+{
+    fn insert(&mut self, key: K, value: V) {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                let root = self.allocate(BplusNode::Leaf {
+                    keys: vec![],
+                    values: vec![],
+                    prev: None,
+                    next: None,
+                });
+                self.root = Some(root);
+                root
+            }
+        };
+        if let Some((separator, right)) = self.insert_into(root, key, value) {
+            let new_root = self.allocate(BplusNode::Internal {
+                keys: vec![separator],
+                children: vec![root, right],
+            });
+            self.root = Some(new_root);
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Delete<K>
+    for BplusTree<K, V>
+{
+    fn delete(&mut self, key: &K) {
+        let Some(root) = self.root else {
+            return;
+        };
+        self.delete_from(root, key);
+
+        // 根の要素がなくなった場合は、唯一の子ノードを新しい根にする
+        if self.nodes[root].current_count() == 0 {
+            self.root = match &self.nodes[root] {
+                BplusNode::Internal { children, .. } => Some(children[0]),
+                BplusNode::Leaf { .. } => None,
+            };
+            self.release(root);
+        }
+    }
+}
+
+impl<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BplusRange<'a, K, V> {
+    /// 葉の末尾に達していれば次の葉の先頭に進める
+    fn normalize_front(&mut self) {
+        while let Some((id, index)) = self.front {
+            let BplusNode::Leaf { keys, next, .. } = &self.tree.nodes[id] else {
+                unreachable!("cursor must point to a leaf");
+            };
+            if index < keys.len() {
+                return;
+            }
+            self.front = next.map(|next| (next, 0));
+        }
+    }
+
+    /// 葉の先頭に達していれば前の葉の末尾に戻す
+    fn normalize_back(&mut self) {
+        while let Some((id, index)) = self.back {
+            if index > 0 {
+                return;
+            }
+            let BplusNode::Leaf { prev, .. } = &self.tree.nodes[id] else {
+                unreachable!("cursor must point to a leaf");
+            };
+            self.back = prev.map(|prev| (prev, self.tree.nodes[prev].current_count()));
+        }
+    }
+
+    fn entry(&self, id: usize, index: usize) -> (&'a K, &'a V) {
+        let tree: &'a BplusTree<K, V> = self.tree;
+        match &tree.nodes[id] {
+            BplusNode::Leaf { keys, values, .. } => (&keys[index], &values[index]),
+            BplusNode::Internal { .. } => unreachable!("cursor must point to a leaf"),
+        }
+    }
+}
+
+impl<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Iterator
+    for BplusRange<'a, K, V>
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.normalize_front();
+        let (id, index) = self.front?;
+        let (key, value) = self.entry(id, index);
+
+        let in_range = match &self.end {
+            Bound::Unbounded => true,
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+        };
+        // 後ろから返したエントリと交差したら終わる
+        if !in_range || self.last_back.is_some_and(|last| key >= last) {
+            self.front = None;
+            return None;
+        }
+        self.front = Some((id, index + 1));
+        self.last_front = Some(key);
+        Some((key, value))
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> DoubleEndedIterator
+    for BplusRange<'_, K, V>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.normalize_back();
+        let (id, index) = self.back?;
+        let (key, value) = self.entry(id, index - 1);
+
+        let in_range = match &self.start {
+            Bound::Unbounded => true,
+            Bound::Included(start) => key >= start,
+            Bound::Excluded(start) => key > start,
+        };
+        if !in_range || self.last_front.is_some_and(|last| key <= last) {
+            self.back = None;
+            return None;
+        }
+        self.back = Some((id, index - 1));
+        self.last_back = Some(key);
+        Some((key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::test_util::XorShift;
+    use std::collections::BTreeMap;
+
+    /// 内部ノードの区切りキーと、葉の連結の順序を検証する
+    fn check_invariants(tree: &BplusTree<i32, i32>) {
+        fn check_node(
+            tree: &BplusTree<i32, i32>,
+            id: usize,
+            lower: Option<i32>,
+            upper: Option<i32>,
+            leaves: &mut Vec<usize>,
+        ) -> usize {
+            let node = &tree.nodes[id];
+            let keys = node.keys();
+            assert!(keys.len() < tree.max_count);
+            if Some(id) != tree.root {
+                assert!(keys.len() >= tree.min_count());
+            }
+            assert!(keys.windows(2).all(|w| w[0] < w[1]));
+            assert!(keys.iter().all(|k| lower.is_none_or(|l| l <= *k)));
+            assert!(keys.iter().all(|k| upper.is_none_or(|u| *k < u)));
+            match node {
+                BplusNode::Leaf { .. } => {
+                    leaves.push(id);
+                    0
+                }
+                BplusNode::Internal { children, .. } => {
+                    assert_eq!(children.len(), keys.len() + 1);
+                    let depths: Vec<usize> = (0..children.len())
+                        .map(|i| {
+                            let lower = if i == 0 { lower } else { Some(keys[i - 1]) };
+                            let upper = keys.get(i).copied().or(upper);
+                            check_node(tree, children[i], lower, upper, leaves)
+                        })
+                        .collect();
+                    assert!(depths.windows(2).all(|w| w[0] == w[1]));
+                    depths[0] + 1
+                }
+            }
+        }
+
+        let mut leaves = vec![];
+        if let Some(root) = tree.root {
+            check_node(tree, root, None, None, &mut leaves);
+        }
+        for (i, id) in leaves.iter().enumerate() {
+            let BplusNode::Leaf { prev, next, .. } = &tree.nodes[*id] else {
+                unreachable!();
+            };
+            assert_eq!(*prev, i.checked_sub(1).map(|p| leaves[p]));
+            assert_eq!(*next, leaves.get(i + 1).copied());
+        }
+    }
+
+    #[test]
+    fn test_insert_and_search() {
+        let mut tree = BplusTree::new(3);
+        for i in (1..=50).rev() {
+            tree.insert(i * 10, i * 100);
+        }
+        for i in 1..=50 {
+            assert_eq!(tree.search(&(i * 10)), Some((i * 10, i * 100)));
+        }
+        assert_eq!(tree.search(&15), None);
+        tree.insert(20, 201);
+        assert_eq!(tree.search(&20), Some((20, 201)));
+        check_invariants(&tree);
+    }
+
+    #[test]
+    fn test_random_operations() {
+        for max_count in 3..9 {
+            let mut tree = BplusTree::new(max_count);
+            let mut expected = BTreeMap::new();
+            let mut rng = XorShift::new(0x2545_F491_4F6C_DD1D);
+            for step in 0..4000 {
+                let seed = rng.next_u64();
+                let key = (seed % 300) as i32;
+                if seed.is_multiple_of(3) {
+                    tree.delete(&key);
+                    expected.remove(&key);
+                } else {
+                    tree.insert(key, step);
+                    expected.insert(key, step);
+                }
+                if step % 100 == 0 {
+                    check_invariants(&tree);
+                }
+            }
+            check_invariants(&tree);
+            assert!(tree.iter().eq(expected.iter()));
+            assert!(tree.iter().rev().eq(expected.iter().rev()));
+        }
+    }
+
+    #[test]
+    fn test_delete_all_reuses_nodes() {
+        let mut tree = BplusTree::new(4);
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        let allocated = tree.nodes.len();
+        for i in 0..100 {
+            tree.delete(&i);
+        }
+        assert_eq!(tree.root, None);
+        assert_eq!(tree.iter().next(), None);
+
+        // 解放したノードを使い回す
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        assert_eq!(tree.nodes.len(), allocated);
+        check_invariants(&tree);
+    }
+
+    #[test]
+    fn test_range() {
+        let mut tree = BplusTree::new(4);
+        for i in 0..100 {
+            tree.insert(i * 2, i);
+        }
+        let expected: BTreeMap<i32, i32> = (0..100).map(|i| (i * 2, i)).collect();
+
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+        let bounds = |k: i32| [Included(k), Excluded(k), Unbounded];
+        for start in -1..202 {
+            for end in [start, start + 1, start + 7] {
+                for start_bound in bounds(start) {
+                    for end_bound in bounds(end) {
+                        let range = (start_bound, end_bound);
+                        if start > end
+                            || (start == end && range != (Included(start), Included(end)))
+                        {
+                            continue;
+                        }
+                        assert!(tree.range(range).eq(expected.range(range)));
+                        assert!(tree.range(range).rev().eq(expected.range(range).rev()));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_from_both_ends() {
+        let mut tree = BplusTree::new(3);
+        for i in 0..10 {
+            tree.insert(i, i);
+        }
+        let mut range = tree.range(2..8);
+        assert_eq!(range.next(), Some((&2, &2)));
+        assert_eq!(range.next_back(), Some((&7, &7)));
+        assert_eq!(range.next_back(), Some((&6, &6)));
+        let rest: Vec<i32> = range.map(|(k, _)| *k).collect();
+        assert_eq!(rest, vec![3, 4, 5]);
+    }
+}
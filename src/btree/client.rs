@@ -0,0 +1,147 @@
+use crate::btree::resp::Value;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::ops::Bound;
+
+/// `Server`に接続してコマンドを送るクライアント
+///
+/// サーバーが返したエラーは`io::ErrorKind::Other`のエラーになる。
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+/// キーと値の組の列
+pub type Entries = Vec<(Vec<u8>, Vec<u8>)>;
+
+fn unexpected(reply: Value) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected reply {reply:?}"),
+    )
+}
+
+/// キーと値を交互に並べた配列を、組の列にする
+fn into_entries(reply: Value) -> io::Result<Entries> {
+    let Value::Array(items) = reply else {
+        return Err(unexpected(reply));
+    };
+    let mut entries = vec![];
+    let mut items = items.into_iter();
+    while let Some(key) = items.next() {
+        match (key, items.next()) {
+            (Value::Bulk(key), Some(Value::Bulk(value))) => entries.push((key, value)),
+            (key, _) => return Err(unexpected(key)),
+        }
+    }
+    Ok(entries)
+}
+
+/// `RANGE`の端を表す引数にする
+fn bound_arg(bound: Bound<&[u8]>, unbounded: &[u8]) -> Vec<u8> {
+    match bound {
+        Bound::Included(key) => [b"[".as_slice(), key].concat(),
+        Bound::Excluded(key) => [b"(".as_slice(), key].concat(),
+        Bound::Unbounded => unbounded.to_vec(),
+    }
+}
+
+impl Client {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Client {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    /// 任意のコマンドを送り、応答を返す
+    pub fn command(&mut self, args: &[&[u8]]) -> io::Result<Value> {
+        let request = Value::Array(args.iter().map(|arg| Value::Bulk(arg.to_vec())).collect());
+        request.write_to(&mut self.writer)?;
+        self.writer.flush()?;
+        match Value::read_from(&mut self.reader)? {
+            None => Err(io::ErrorKind::UnexpectedEof.into()),
+            Some(Value::Error(message)) => Err(io::Error::other(message)),
+            Some(reply) => Ok(reply),
+        }
+    }
+
+    pub fn ping(&mut self) -> io::Result<()> {
+        self.command(&[b"PING"]).map(drop)
+    }
+
+    pub fn get(&mut self, tree: &str, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        match self.command(&[b"GET", tree.as_bytes(), key])? {
+            Value::Bulk(value) => Ok(Some(value)),
+            Value::Null => Ok(None),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    pub fn set(&mut self, tree: &str, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.command(&[b"SET", tree.as_bytes(), key, value])
+            .map(drop)
+    }
+
+    /// キーを削除し、削除したかを返す
+    pub fn del(&mut self, tree: &str, key: &[u8]) -> io::Result<bool> {
+        match self.command(&[b"DEL", tree.as_bytes(), key])? {
+            Value::Integer(n) => Ok(n > 0),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    /// `start`から`end`までのエントリを、最大`limit`個返す
+    pub fn range(
+        &mut self,
+        tree: &str,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        limit: Option<usize>,
+    ) -> io::Result<Entries> {
+        let start = bound_arg(start, b"-");
+        let end = bound_arg(end, b"+");
+        let limit = limit.map(|n| n.to_string());
+        let mut args: Vec<&[u8]> = vec![b"RANGE", tree.as_bytes(), &start, &end];
+        if let Some(limit) = &limit {
+            args.extend([b"LIMIT".as_slice(), limit.as_bytes()]);
+        }
+        into_entries(self.command(&args)?)
+    }
+
+    /// `cursor`（最初は`b"0"`）から最大`count`個のエントリと、次のカーソルを返す
+    ///
+    /// 次のカーソルが`b"0"`なら最後まで読み終えている。
+    pub fn scan(
+        &mut self,
+        tree: &str,
+        cursor: &[u8],
+        count: usize,
+    ) -> io::Result<(Vec<u8>, Entries)> {
+        let count = count.to_string();
+        let reply =
+            self.command(&[b"SCAN", tree.as_bytes(), cursor, b"COUNT", count.as_bytes()])?;
+        let Value::Array(mut items) = reply else {
+            return Err(unexpected(reply));
+        };
+        match (items.pop(), items.pop(), items.pop()) {
+            (Some(page), Some(Value::Bulk(next)), None) => Ok((next, into_entries(page)?)),
+            _ => Err(unexpected(Value::Array(items))),
+        }
+    }
+
+    /// 木の名前を昇順に返す
+    pub fn trees(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        match self.command(&[b"TREES"])? {
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::Bulk(name) => Ok(name),
+                    item => Err(unexpected(item)),
+                })
+                .collect(),
+            reply => Err(unexpected(reply)),
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use crate::btree::iter::Range;
+use crate::btree::persist::Persist;
+use crate::btree::tree::Btree;
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+/// タプルのキーの先頭の要素だけを並べたもの
+///
+/// `(tenant, timestamp, id)`のキーに対して、`(tenant,)`や`(tenant, timestamp)`が前置部分になる。
+pub trait KeyPrefix<K> {
+    /// キーの先頭の要素と比べる（比べられない要素があれば`None`）
+    fn compare_prefix(&self, key: &K) -> Option<Ordering>;
+}
+
+macro_rules! impl_key_prefix {
+    (($($key:ident),+); $($prefix:ident $index:tt),+) => {
+        impl<$($key: PartialOrd),+> KeyPrefix<($($key,)+)> for ($($prefix,)+) {
+            fn compare_prefix(&self, key: &($($key,)+)) -> Option<Ordering> {
+                $(
+                    match self.$index.partial_cmp(&key.$index)? {
+                        Ordering::Equal => {}
+                        ordering => return Some(ordering),
+                    }
+                )+
+                Some(Ordering::Equal)
+            }
+        }
+    };
+}
+
+impl_key_prefix!((A, B); A 0);
+impl_key_prefix!((A, B); A 0, B 1);
+impl_key_prefix!((A, B, C); A 0);
+impl_key_prefix!((A, B, C); A 0, B 1);
+impl_key_prefix!((A, B, C); A 0, B 1, C 2);
+impl_key_prefix!((A, B, C, D); A 0);
+impl_key_prefix!((A, B, C, D); A 0, B 1);
+impl_key_prefix!((A, B, C, D); A 0, B 1, C 2);
+impl_key_prefix!((A, B, C, D); A 0, B 1, C 2, D 3);
+
+/// 前置部分の範囲に入るエントリを、キーの昇順にたどるイテレータ
+pub struct CompositeRange<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, P> {
+    inner: Range<'a, K, V>,
+    end: Bound<P>,
+    finished: bool,
+}
+
+impl<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, P: KeyPrefix<K>> Iterator
+    for CompositeRange<'a, K, V, P>
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let (key, value) = self.inner.next()?;
+        let in_range = match &self.end {
+            Bound::Unbounded => true,
+            Bound::Included(end) => end.compare_prefix(key) != Some(Ordering::Less),
+            Bound::Excluded(end) => end.compare_prefix(key) == Some(Ordering::Greater),
+        };
+        if !in_range {
+            // これ以降のキーもすべて範囲の外にある
+            self.finished = true;
+            return None;
+        }
+        Some((key, value))
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    /// 先頭の要素が`range`に入るエントリを、キーの昇順にたどる
+    ///
+    /// `(tenant, t1)..(tenant, t2)`のように、前置部分の範囲で指定する。
+    pub fn prefix_range<P: KeyPrefix<K> + Clone, R: RangeBounds<P>>(
+        &self,
+        range: R,
+    ) -> CompositeRange<'_, K, V, P> {
+        let inner = match range.start_bound() {
+            Bound::Unbounded => self.iter(),
+            Bound::Included(start) => Range::starting_after(self.root(), |key| {
+                start.compare_prefix(key) == Some(Ordering::Greater)
+            }),
+            Bound::Excluded(start) => Range::starting_after(self.root(), |key| {
+                start.compare_prefix(key) != Some(Ordering::Less)
+            }),
+        };
+        CompositeRange {
+            inner,
+            end: range.end_bound().cloned(),
+            finished: false,
+        }
+    }
+
+    /// 先頭の要素が`prefix`に一致するエントリを、キーの昇順にたどる
+    pub fn scan_prefix<P: KeyPrefix<K> + Clone>(&self, prefix: P) -> CompositeRange<'_, K, V, P> {
+        self.prefix_range((Bound::Included(&prefix), Bound::Included(&prefix)))
+    }
+}
+
+/// 大小関係を保ったままバイト列に変換できる値
+///
+/// 変換したバイト列を辞書順に比べると、元の値を比べたのと同じ結果になる。
+/// タプルは要素ごとの変換を並べたものなので、前置部分の変換は元のキーの変換の先頭と一致する。
+pub trait OrderedKey: Sized {
+    fn encode_ordered(&self, out: &mut Vec<u8>);
+    /// `encode_ordered`の結果の先頭から値を1つ読み、読んだ分だけ`input`を進める
+    fn decode_ordered(input: &mut &[u8]) -> Option<Self>;
+
+    fn to_ordered_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.encode_ordered(&mut out);
+        out
+    }
+}
+
+fn take_array<const N: usize>(input: &mut &[u8]) -> Option<[u8; N]> {
+    let (bytes, rest) = input.split_first_chunk::<N>()?;
+    *input = rest;
+    Some(*bytes)
+}
+
+macro_rules! impl_ordered_unsigned {
+    ($($ty:ty),+) => {
+        $(
+            impl OrderedKey for $ty {
+                fn encode_ordered(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_be_bytes());
+                }
+                fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+                    take_array(input).map(<$ty>::from_be_bytes)
+                }
+            }
+        )+
+    };
+}
+
+// 符号ビットを反転すると、負の数が正の数より前に並ぶ
+macro_rules! impl_ordered_signed {
+    ($($ty:ty => $unsigned:ty),+) => {
+        $(
+            impl OrderedKey for $ty {
+                fn encode_ordered(&self, out: &mut Vec<u8>) {
+                    (*self as $unsigned ^ (1 << (<$ty>::BITS - 1))).encode_ordered(out);
+                }
+                fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+                    <$unsigned>::decode_ordered(input)
+                        .map(|n| (n ^ (1 << (<$ty>::BITS - 1))) as $ty)
+                }
+            }
+        )+
+    };
+}
+
+impl_ordered_unsigned!(u8, u16, u32, u64);
+impl_ordered_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64);
+
+/// バイト列の`0x00`は`0x00 0xFF`に置き換え、末尾に`0x00 0x01`を置く
+///
+/// 短い方のバイト列が、それで始まる長いバイト列より前に並ぶ。
+const ESCAPE: u8 = 0x00;
+const END: u8 = 0x01;
+const ESCAPED_ZERO: u8 = 0xFF;
+
+impl OrderedKey for Vec<u8> {
+    fn encode_ordered(&self, out: &mut Vec<u8>) {
+        for &byte in self {
+            match byte {
+                ESCAPE => out.extend_from_slice(&[ESCAPE, ESCAPED_ZERO]),
+                byte => out.push(byte),
+            }
+        }
+        out.extend_from_slice(&[ESCAPE, END]);
+    }
+
+    fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+        let mut bytes = vec![];
+        loop {
+            match take_array(input)? {
+                [ESCAPE] => match take_array(input)? {
+                    [END] => return Some(bytes),
+                    [ESCAPED_ZERO] => bytes.push(ESCAPE),
+                    _ => return None,
+                },
+                [byte] => bytes.push(byte),
+            }
+        }
+    }
+}
+
+impl OrderedKey for String {
+    fn encode_ordered(&self, out: &mut Vec<u8>) {
+        // UTF-8のバイト列の辞書順は、文字列の順序と一致する
+        self.as_bytes().to_vec().encode_ordered(out);
+    }
+    fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+        String::from_utf8(Vec::decode_ordered(input)?).ok()
+    }
+}
+
+macro_rules! impl_ordered_tuple {
+    ($(($($name:ident $index:tt),+))+) => {
+        $(
+            impl<$($name: OrderedKey),+> OrderedKey for ($($name,)+) {
+                fn encode_ordered(&self, out: &mut Vec<u8>) {
+                    $(self.$index.encode_ordered(out);)+
+                }
+                fn decode_ordered(input: &mut &[u8]) -> Option<Self> {
+                    Some(($($name::decode_ordered(input)?,)+))
+                }
+            }
+
+            /// タプルは大小関係を保つ形式で保存する
+            impl<$($name: OrderedKey),+> Persist for ($($name,)+) {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    self.encode_ordered(out);
+                }
+                fn decode(mut bytes: &[u8]) -> Option<Self> {
+                    let value = Self::decode_ordered(&mut bytes)?;
+                    bytes.is_empty().then_some(value)
+                }
+            }
+        )+
+    };
+}
+
+impl_ordered_tuple!((A 0) (A 0, B 1) (A 0, B 1, C 2) (A 0, B 1, C 2, D 3));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::Insert;
+
+    type Key = (String, u64, u32);
+
+    fn key(tenant: &str, timestamp: u64, id: u32) -> Key {
+        (tenant.to_string(), timestamp, id)
+    }
+
+    fn sample() -> Btree<Key, u32> {
+        let mut tree = Btree::new(3);
+        for (i, tenant) in ["acme", "globex", "initech"].iter().enumerate() {
+            for timestamp in 0..10 {
+                for id in 0..3 {
+                    tree.insert(key(tenant, timestamp, id), i as u32);
+                }
+            }
+        }
+        tree
+    }
+
+    #[test]
+    fn test_scan_prefix() {
+        let tree = sample();
+        let rows: Vec<_> = tree.scan_prefix(("globex".to_string(),)).collect();
+        assert_eq!(rows.len(), 30);
+        assert!(rows.iter().all(|(_, tenant)| **tenant == 1));
+        assert!(rows.is_sorted());
+
+        let rows: Vec<_> = tree
+            .scan_prefix(("acme".to_string(), 4))
+            .map(|(key, _)| key.clone())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![key("acme", 4, 0), key("acme", 4, 1), key("acme", 4, 2)]
+        );
+        assert_eq!(tree.scan_prefix(("hooli".to_string(),)).count(), 0);
+        assert_eq!(tree.scan_prefix(("acme".to_string(), 4, 2)).count(), 1);
+    }
+
+    #[test]
+    fn test_prefix_range() {
+        let tree = sample();
+        let tenant = "initech".to_string();
+        let keys: Vec<_> = tree
+            .prefix_range((tenant.clone(), 3)..(tenant.clone(), 5))
+            .map(|(key, _)| (key.1, key.2))
+            .collect();
+        assert_eq!(keys, vec![(3, 0), (3, 1), (3, 2), (4, 0), (4, 1), (4, 2)]);
+
+        let after: Vec<_> = tree
+            .prefix_range((Bound::Excluded(("acme".to_string(),)), Bound::Unbounded))
+            .collect();
+        assert_eq!(after.len(), 60);
+        assert_eq!(tree.prefix_range(..=("acme".to_string(), 0)).count(), 3);
+    }
+
+    #[test]
+    fn test_prefix_range_matches_filter() {
+        let mut tree = Btree::new(4);
+        // 前置部分が抜けている組や、最後の要素が端の値の組も入れる
+        for a in 0..7 {
+            for b in 0..5u64 {
+                if (a + b as i32) % 3 == 0 {
+                    continue;
+                }
+                for c in [0, 1, u8::MAX] {
+                    tree.insert((a, b, c), ());
+                }
+            }
+        }
+        for a in -1..8 {
+            for b in 0..6 {
+                let expected: Vec<_> = tree
+                    .iter()
+                    .filter(|(key, _)| (key.0, key.1) == (a, b))
+                    .collect();
+                assert_eq!(tree.scan_prefix((a, b)).collect::<Vec<_>>(), expected);
+                let expected: Vec<_> = tree.iter().filter(|(key, _)| key.0 >= a).collect();
+                assert_eq!(tree.prefix_range((a,)..).collect::<Vec<_>>(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ordered_encoding_preserves_order() {
+        let mut keys = vec![];
+        for tenant in ["", "a", "a\0", "a\0b", "ab", "b"] {
+            for timestamp in [i64::MIN, -1, 0, 1, i64::MAX] {
+                keys.push((tenant.to_string(), timestamp, vec![0u8, 255]));
+                keys.push((tenant.to_string(), timestamp, vec![]));
+            }
+        }
+        keys.sort();
+        let encoded: Vec<Vec<u8>> = keys.iter().map(OrderedKey::to_ordered_bytes).collect();
+        assert!(encoded.is_sorted());
+        for (key, bytes) in keys.iter().zip(&encoded) {
+            let mut input = bytes.as_slice();
+            assert_eq!(OrderedKey::decode_ordered(&mut input).as_ref(), Some(key));
+            assert!(input.is_empty());
+            assert_eq!(<(String, i64, Vec<u8>)>::decode(bytes).as_ref(), Some(key));
+        }
+        // 前置部分の変換は、キーの変換の先頭と一致する
+        let prefix = ("a\0".to_string(), -1i64).to_ordered_bytes();
+        assert!(
+            encoded[keys.iter().position(|k| k.0 == "a\0" && k.1 == -1).unwrap()]
+                .starts_with(&prefix)
+        );
+
+        assert_eq!(<(u32, u8)>::decode(&[0, 0, 0, 1, 2, 3]), None);
+        assert_eq!(<(String,)>::decode(&[b'a', 0x00, 0x02]), None);
+        assert_eq!(<(String,)>::decode(b"a"), None);
+    }
+
+    #[test]
+    fn test_save_and_load_composite_keys() {
+        let mut tree: Btree<(String, u64), String> = Btree::new(3);
+        for i in 0..50 {
+            tree.insert((format!("t{}", i % 3), i), i.to_string());
+        }
+        let mut bytes = vec![];
+        tree.save(&mut bytes).unwrap();
+        let loaded: Btree<(String, u64), String> = Btree::load(bytes.as_slice()).unwrap();
+        assert!(loaded.iter().eq(tree.iter()));
+        assert_eq!(loaded.scan_prefix(("t1".to_string(),)).count(), 17);
+    }
+}
@@ -0,0 +1,495 @@
+use crate::btree::BinarySearch;
+use crate::btree::node::{
+    DeleteFromChildOperation, NodeStorage, merge_into, rotate_left, rotate_right,
+};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+type Latch<K, V> = Arc<RwLock<ConcurrentNode<K, V>>>;
+
+fn latch<K, V>(node: ConcurrentNode<K, V>) -> Latch<K, V> {
+    Arc::new(RwLock::new(node))
+}
+
+struct ConcurrentNode<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    children: Vec<Latch<K, V>>,
+}
+
+/// ノード単位のラッチで保護された、スレッド間で共有できるB木
+///
+/// 読み込みは親から子へ読み込みラッチを受け渡しながら（latch crabbing）降りていくため、
+/// 互いにブロックせず並行に進む。
+/// 書き込みは降りる前に子ノードを分割・再配置しておく（トップダウン方式）ため、
+/// 同時に保持するのは親と子（と兄弟）のラッチだけで、祖先のラッチはすぐに解放される。
+pub struct ConcurrentBtree<K, V> {
+    root: RwLock<Latch<K, V>>,
+    max_count: usize,
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> ConcurrentNode<K, V> {
+    fn new() -> Self {
+        Self {
+            keys: vec![],
+            values: vec![],
+            children: vec![],
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// 末尾半分を新しいノードとして切り出し、中央のエントリと一緒に返す
+    fn split_off(&mut self) -> ((K, V), ConcurrentNode<K, V>) {
+        let mid_index = self.keys.len() / 2;
+        let right = ConcurrentNode {
+            keys: self.keys.split_off(mid_index + 1),
+            values: self.values.split_off(mid_index + 1),
+            children: if self.is_leaf() {
+                vec![]
+            } else {
+                self.children.split_off(mid_index + 1)
+            },
+        };
+        let key = self.keys.pop().unwrap();
+        let value = self.values.pop().unwrap();
+        ((key, value), right)
+    }
+
+    /// 子ノード`index`と`index + 1`を、間のエントリを挟んで左側にまとめる
+    fn merge_children(&mut self, index: usize) {
+        let right = self.children.remove(index + 1);
+        let key = self.keys.remove(index);
+        let value = self.values.remove(index);
+
+        let mut left = self.children[index].write().unwrap();
+        merge_into(&mut *left, (key, value), &mut *right.write().unwrap());
+    }
+
+    /// 子ノード`index`が最小数より多くのエントリを持つようにし、降りるべき子の位置を返す
+    fn fill_child(&mut self, index: usize, min_count: usize) -> usize {
+        let has_spare = |i: usize| self.children[i].read().unwrap().entry_count() > min_count;
+        if has_spare(index) {
+            return index;
+        }
+        match DeleteFromChildOperation::fill(index, self.children.len(), has_spare) {
+            DeleteFromChildOperation::None => index,
+            DeleteFromChildOperation::RotateRight => {
+                let mut left = self.children[index - 1].write().unwrap();
+                let mut child = self.children[index].write().unwrap();
+                let separator = (&mut self.keys[index - 1], &mut self.values[index - 1]);
+                rotate_right(separator, &mut *left, &mut *child);
+                index
+            }
+            DeleteFromChildOperation::RotateLeft => {
+                let mut child = self.children[index].write().unwrap();
+                let mut right = self.children[index + 1].write().unwrap();
+                let separator = (&mut self.keys[index], &mut self.values[index]);
+                rotate_left(separator, &mut *child, &mut *right);
+                index
+            }
+            DeleteFromChildOperation::MergeToLeft => {
+                self.merge_children(index - 1);
+                index - 1
+            }
+            DeleteFromChildOperation::MergeToRight => {
+                self.merge_children(index);
+                index
+            }
+        }
+    }
+}
+
+impl<K, V> NodeStorage for ConcurrentNode<K, V> {
+    type Key = K;
+    type Value = V;
+    type Child = Latch<K, V>;
+    type Keys = Vec<K>;
+    type Values = Vec<V>;
+    type Children = Vec<Latch<K, V>>;
+
+    fn parts(&self) -> (&Vec<K>, &Vec<V>, &Vec<Latch<K, V>>) {
+        (&self.keys, &self.values, &self.children)
+    }
+
+    fn parts_mut(&mut self) -> (&mut Vec<K>, &mut Vec<V>, &mut Vec<Latch<K, V>>) {
+        (&mut self.keys, &mut self.values, &mut self.children)
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> ConcurrentBtree<K, V> {
+    pub fn new(max_count: usize) -> Self {
+        assert!(max_count >= 3, "max_count must be at least 3");
+        ConcurrentBtree {
+            root: RwLock::new(latch(ConcurrentNode::new())),
+            max_count,
+        }
+    }
+
+    fn min_count(&self) -> usize {
+        (self.max_count - 1) / 2
+    }
+
+    pub fn search(&self, key: &K) -> Option<(K, V)> {
+        let root = self.root.read().unwrap();
+        let node = Arc::clone(&root);
+        let guard = node.read().unwrap();
+        drop(root);
+        Self::search_from(guard, key)
+    }
+
+    fn search_from(guard: RwLockReadGuard<'_, ConcurrentNode<K, V>>, key: &K) -> Option<(K, V)> {
+        match guard.keys.binary_lookup(key) {
+            Ok(i) => Some((guard.keys[i].clone(), guard.values[i].clone())),
+            Err(i) => {
+                if guard.is_leaf() {
+                    return None;
+                }
+                // 子のラッチを取ってから親のラッチを解放する
+                let child = Arc::clone(&guard.children[i]);
+                let child_guard = child.read().unwrap();
+                drop(guard);
+                Self::search_from(child_guard, key)
+            }
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut root = self.root.write().unwrap();
+        {
+            let mut guard = root.write().unwrap();
+            if guard.entry_count() >= self.max_count {
+                let ((key, value), right) = guard.split_off();
+                drop(guard);
+                let left = Arc::clone(&root);
+                *root = latch(ConcurrentNode {
+                    keys: vec![key],
+                    values: vec![value],
+                    children: vec![left, latch(right)],
+                });
+            }
+        }
+        let node = Arc::clone(&root);
+        let guard = node.write().unwrap();
+        drop(root);
+        self.insert_from(guard, key, value);
+    }
+
+    fn insert_from(&self, mut guard: RwLockWriteGuard<'_, ConcurrentNode<K, V>>, key: K, value: V) {
+        let mut index = match guard.keys.binary_lookup(&key) {
+            Ok(i) => {
+                guard.keys[i] = key;
+                guard.values[i] = value;
+                return;
+            }
+            Err(i) => i,
+        };
+        if guard.is_leaf() {
+            guard.keys.insert(index, key);
+            guard.values.insert(index, value);
+            return;
+        }
+
+        // 満杯の子は降りる前に分割しておくので、子から親へ分割が伝播することはない
+        let split = {
+            let mut child = guard.children[index].write().unwrap();
+            if child.entry_count() >= self.max_count {
+                Some(child.split_off())
+            } else {
+                None
+            }
+        };
+        if let Some(((mid_key, mid_value), right)) = split {
+            guard.keys.insert(index, mid_key);
+            guard.values.insert(index, mid_value);
+            guard.children.insert(index + 1, latch(right));
+
+            if key == guard.keys[index] {
+                guard.keys[index] = key;
+                guard.values[index] = value;
+                return;
+            }
+            if key > guard.keys[index] {
+                index += 1;
+            }
+        }
+
+        let child = Arc::clone(&guard.children[index]);
+        let child_guard = child.write().unwrap();
+        drop(guard);
+        self.insert_from(child_guard, key, value);
+    }
+
+    pub fn delete(&self, key: &K) {
+        let mut root = self.root.write().unwrap();
+        {
+            let mut guard = root.write().unwrap();
+            if guard.entry_count() == 1 && !guard.is_leaf() {
+                let min_count = self.min_count();
+                let can_collapse = guard
+                    .children
+                    .iter()
+                    .all(|child| child.read().unwrap().entry_count() <= min_count);
+                if can_collapse {
+                    // 根の唯一のエントリと2つの子をまとめ、木の高さを1つ下げる
+                    guard.merge_children(0);
+                    let child = guard.children.pop().unwrap();
+                    drop(guard);
+                    *root = child;
+                }
+            }
+        }
+        let node = Arc::clone(&root);
+        let guard = node.write().unwrap();
+        drop(root);
+        self.delete_from(guard, key);
+    }
+
+    fn delete_from(&self, mut guard: RwLockWriteGuard<'_, ConcurrentNode<K, V>>, key: &K) {
+        let min_count = self.min_count();
+        let index = match guard.keys.binary_lookup(key) {
+            Ok(i) if guard.is_leaf() => {
+                // 葉ノードの場合はそのまま削除
+                guard.keys.remove(i);
+                guard.values.remove(i);
+                return;
+            }
+            Ok(i) => {
+                // 内部ノードの場合は前後のエントリで置き換える
+                if guard.children[i].read().unwrap().entry_count() > min_count {
+                    let child = Arc::clone(&guard.children[i]);
+                    let (key, value) = self.pop_max_from(child.write().unwrap());
+                    guard.keys[i] = key;
+                    guard.values[i] = value;
+                    return;
+                }
+                if guard.children[i + 1].read().unwrap().entry_count() > min_count {
+                    let child = Arc::clone(&guard.children[i + 1]);
+                    let (key, value) = self.pop_min_from(child.write().unwrap());
+                    guard.keys[i] = key;
+                    guard.values[i] = value;
+                    return;
+                }
+                // どちらの部分木も余分な要素をもっていないので、まとめた子から削除する
+                guard.merge_children(i);
+                i
+            }
+            Err(_) if guard.is_leaf() => return,
+            Err(i) => guard.fill_child(i, min_count),
+        };
+
+        let child = Arc::clone(&guard.children[index]);
+        let child_guard = child.write().unwrap();
+        drop(guard);
+        self.delete_from(child_guard, key);
+    }
+
+    fn pop_max_from(&self, mut guard: RwLockWriteGuard<'_, ConcurrentNode<K, V>>) -> (K, V) {
+        if guard.is_leaf() {
+            return (guard.keys.pop().unwrap(), guard.values.pop().unwrap());
+        }
+        let last = guard.children.len() - 1;
+        let index = guard.fill_child(last, self.min_count());
+        let child = Arc::clone(&guard.children[index]);
+        let child_guard = child.write().unwrap();
+        drop(guard);
+        self.pop_max_from(child_guard)
+    }
+
+    fn pop_min_from(&self, mut guard: RwLockWriteGuard<'_, ConcurrentNode<K, V>>) -> (K, V) {
+        if guard.is_leaf() {
+            return (guard.keys.remove(0), guard.values.remove(0));
+        }
+        let index = guard.fill_child(0, self.min_count());
+        let child = Arc::clone(&guard.children[index]);
+        let child_guard = child.write().unwrap();
+        drop(guard);
+        self.pop_min_from(child_guard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::test_util::XorShift;
+    use std::collections::BTreeMap;
+    use std::thread;
+
+    /// 木の不変条件を検証し、葉までの深さを返す
+    fn check_node(
+        node: &Latch<i64, i64>,
+        min_count: usize,
+        max_count: usize,
+        is_root: bool,
+        lower: Option<i64>,
+        upper: Option<i64>,
+    ) -> usize {
+        let node = node.read().unwrap();
+        assert!(node.entry_count() <= max_count);
+        if !is_root {
+            assert!(node.entry_count() >= min_count);
+        }
+        assert!(node.keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(node.keys.iter().all(|k| lower.is_none_or(|l| l < *k)));
+        assert!(node.keys.iter().all(|k| upper.is_none_or(|u| *k < u)));
+        if node.is_leaf() {
+            return 0;
+        }
+        assert_eq!(node.children.len(), node.entry_count() + 1);
+        let depths: Vec<usize> = (0..node.children.len())
+            .map(|i| {
+                let lower = if i == 0 {
+                    lower
+                } else {
+                    Some(node.keys[i - 1])
+                };
+                let upper = node.keys.get(i).copied().or(upper);
+                check_node(&node.children[i], min_count, max_count, false, lower, upper)
+            })
+            .collect();
+        assert!(depths.windows(2).all(|w| w[0] == w[1]));
+        depths[0] + 1
+    }
+
+    fn check_invariants(tree: &ConcurrentBtree<i64, i64>) {
+        let root = tree.root.read().unwrap();
+        check_node(&root, tree.min_count(), tree.max_count, true, None, None);
+    }
+
+    /// 各スレッドが自分の担当キー（`key % threads == id`）に対して挿入・削除・検索を繰り返し、
+    /// 最後にスレッドごとの期待値と木の内容を突き合わせる
+    fn run_stress(max_count: usize, threads: u64, operations: usize) {
+        let tree = ConcurrentBtree::new(max_count);
+
+        let expected: Vec<BTreeMap<i64, i64>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|id| {
+                    let tree = &tree;
+                    scope.spawn(move || {
+                        let mut rng = XorShift::new(0x9E37_79B9_7F4A_7C15 ^ (id + 1));
+                        let mut model = BTreeMap::new();
+                        for step in 0..operations {
+                            let key = ((rng.next_u64() % 512) * threads + id) as i64;
+                            match rng.next_u64() % 4 {
+                                0 => {
+                                    tree.delete(&key);
+                                    model.remove(&key);
+                                }
+                                1 => {
+                                    let found = tree.search(&key).map(|(_, v)| v);
+                                    assert_eq!(found, model.get(&key).copied());
+                                }
+                                _ => {
+                                    tree.insert(key, step as i64);
+                                    model.insert(key, step as i64);
+                                }
+                            }
+                        }
+                        model
+                    })
+                })
+                .collect();
+
+            // 書き込みと並行して、担当を持たない読み込み専用スレッドも走らせる
+            let readers: Vec<_> = (0..2)
+                .map(|id| {
+                    let tree = &tree;
+                    scope.spawn(move || {
+                        let mut rng = XorShift::new(0xD1B5_4A32_D192_ED03 ^ (id + 1));
+                        for _ in 0..operations {
+                            let key = (rng.next_u64() % (512 * threads)) as i64;
+                            if let Some((k, _)) = tree.search(&key) {
+                                assert_eq!(k, key);
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            for reader in readers {
+                reader.join().unwrap();
+            }
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+
+        check_invariants(&tree);
+        for (id, model) in expected.iter().enumerate() {
+            for i in 0..512 {
+                let key = (i * threads + id as u64) as i64;
+                assert_eq!(tree.search(&key).map(|(_, v)| v), model.get(&key).copied());
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_and_search() {
+        let tree = ConcurrentBtree::new(3);
+        for i in 1..=100 {
+            tree.insert(i * 10, i * 100);
+        }
+        for i in 1..=100 {
+            assert_eq!(tree.search(&(i * 10)), Some((i * 10, i * 100)));
+        }
+        assert_eq!(tree.search(&15), None);
+        check_invariants(&tree);
+    }
+
+    #[test]
+    fn test_insert_same_key() {
+        let tree = ConcurrentBtree::new(3);
+        for i in 0..20 {
+            tree.insert(i, i);
+        }
+        // 分割の中央に上がったキーも上書きできる
+        for i in 0..20 {
+            tree.insert(i, i * 2);
+        }
+        for i in 0..20 {
+            assert_eq!(tree.search(&i), Some((i, i * 2)));
+        }
+    }
+
+    #[test]
+    fn test_delete_all_keys() {
+        for max_count in 3..8 {
+            let tree = ConcurrentBtree::new(max_count);
+            for i in 0..200 {
+                tree.insert(i, i);
+            }
+            for i in (0..200).step_by(2) {
+                tree.delete(&i);
+                check_invariants(&tree);
+            }
+            for i in 0..200 {
+                let expected = if i % 2 == 0 { None } else { Some((i, i)) };
+                assert_eq!(tree.search(&i), expected);
+            }
+            for i in (1..200).rev().step_by(2) {
+                tree.delete(&i);
+            }
+            check_invariants(&tree);
+            assert!(tree.root.read().unwrap().read().unwrap().keys.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_delete_nonexistent_key() {
+        let tree = ConcurrentBtree::new(3);
+        tree.insert(10, 100);
+        tree.insert(20, 200);
+        tree.delete(&30);
+        assert_eq!(tree.search(&10), Some((10, 100)));
+        assert_eq!(tree.search(&20), Some((20, 200)));
+    }
+
+    #[test]
+    fn test_stress_small_nodes() {
+        run_stress(3, 8, 4_000);
+    }
+
+    #[test]
+    fn test_stress_large_nodes() {
+        run_stress(16, 8, 4_000);
+    }
+}
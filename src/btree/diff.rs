@@ -0,0 +1,238 @@
+use crate::btree::Insert;
+use crate::btree::iter::Range;
+use crate::btree::tree::Btree;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+
+/// 2つの木で異なるエントリ
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffEntry<'a, K, V> {
+    /// 比べる相手にだけあるエントリ
+    Added { key: &'a K, value: &'a V },
+    /// 元の木にだけあるエントリ
+    Removed { key: &'a K, value: &'a V },
+    /// 両方にあって値が異なるエントリ
+    Changed { key: &'a K, old: &'a V, new: &'a V },
+}
+
+/// `Btree::diff`が返す、異なるエントリをキーの昇順にたどるイテレータ
+pub struct Diff<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    old: Peekable<Range<'a, K, V>>,
+    new: Peekable<Range<'a, K, V>>,
+}
+
+impl<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone + PartialEq> Iterator
+    for Diff<'a, K, V>
+{
+    type Item = DiffEntry<'a, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let ordering = match (self.old.peek(), self.new.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((old, _)), Some((new, _))) => old.partial_cmp(new).unwrap_or(Ordering::Equal),
+            };
+            match ordering {
+                Ordering::Less => {
+                    let (key, value) = self.old.next()?;
+                    return Some(DiffEntry::Removed { key, value });
+                }
+                Ordering::Greater => {
+                    let (key, value) = self.new.next()?;
+                    return Some(DiffEntry::Added { key, value });
+                }
+                Ordering::Equal => {
+                    let (key, old) = self.old.next()?;
+                    let (_, new) = self.new.next()?;
+                    if old != new {
+                        return Some(DiffEntry::Changed { key, old, new });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 3方向の併合で、両方が同じキーを異なるように変えた場合の各版の値（`None`はキーが無いことを表す）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Conflict<'a, V> {
+    pub base: Option<&'a V>,
+    pub ours: Option<&'a V>,
+    pub theirs: Option<&'a V>,
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone + PartialEq, const B: usize>
+    Btree<K, V, B>
+{
+    /// この木を`other`に変えるのに必要な差分を、キーの昇順に返す
+    ///
+    /// 2つの木を同時に昇順にたどって比べる。ノードは木ごとに別々に持つので、
+    /// 共通の部分木を読み飛ばすことはできず、両方の全エントリを1回ずつ読む。
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Diff<'a, K, V> {
+        Diff {
+            old: self.iter().peekable(),
+            new: other.iter().peekable(),
+        }
+    }
+
+    /// `base`から分かれた`ours`と`theirs`の変更を併せた木を作る
+    ///
+    /// 片方だけが変えたキーはその変更を、両方が同じように変えたキーはその値を使う。
+    /// 両方が異なるように変えたキーは`resolver`に渡し、返した値（`None`なら削除）を使う。
+    /// 作った木のノードの最大要素数は`ours`と同じになる。
+    pub fn merge3(
+        base: &Self,
+        ours: &Self,
+        theirs: &Self,
+        mut resolver: impl FnMut(&K, Conflict<'_, V>) -> Option<V>,
+    ) -> Self {
+        let mut merged = Btree::with_max_count(ours.max_count());
+        let mut base = base.iter().peekable();
+        let mut ours = ours.iter().peekable();
+        let mut theirs = theirs.iter().peekable();
+        loop {
+            // 3つの木の先頭のうち、最も小さいキー
+            let key = [base.peek(), ours.peek(), theirs.peek()]
+                .into_iter()
+                .flatten()
+                .map(|(key, _)| *key)
+                .reduce(|min, key| if key < min { key } else { min });
+            let Some(key) = key else {
+                return merged;
+            };
+            let (b, o, t) = (
+                take(&mut base, key),
+                take(&mut ours, key),
+                take(&mut theirs, key),
+            );
+            let value = if o == t || t == b {
+                o.cloned()
+            } else if o == b {
+                t.cloned()
+            } else {
+                let conflict = Conflict {
+                    base: b,
+                    ours: o,
+                    theirs: t,
+                };
+                resolver(key, conflict)
+            };
+            if let Some(value) = value {
+                merged.insert(key.clone(), value);
+            }
+        }
+    }
+}
+
+/// 先頭のキーが`key`なら、そのエントリを進めて値を返す
+fn take<'a, K: 'a + PartialEq, V: 'a>(
+    iter: &mut Peekable<impl Iterator<Item = (&'a K, &'a V)>>,
+    key: &K,
+) -> Option<&'a V> {
+    iter.next_if(|(head, _)| *head == key)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(entries: &[(i32, char)]) -> Btree<i32, char> {
+        let mut tree = Btree::new(3);
+        tree.extend(entries.iter().copied());
+        tree
+    }
+
+    fn entries(tree: &Btree<i32, char>) -> Vec<(i32, char)> {
+        tree.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = tree(&[(1, 'a'), (2, 'b'), (3, 'c'), (5, 'e')]);
+        let new = tree(&[(2, 'b'), (3, 'x'), (4, 'd'), (5, 'e')]);
+        assert_eq!(
+            old.diff(&new).collect::<Vec<_>>(),
+            vec![
+                DiffEntry::Removed {
+                    key: &1,
+                    value: &'a'
+                },
+                DiffEntry::Changed {
+                    key: &3,
+                    old: &'c',
+                    new: &'x'
+                },
+                DiffEntry::Added {
+                    key: &4,
+                    value: &'d'
+                },
+            ]
+        );
+        assert_eq!(old.diff(&old).count(), 0);
+        assert_eq!(tree(&[]).diff(&new).count(), 4);
+    }
+
+    #[test]
+    fn test_merge3() {
+        let base = tree(&[(1, 'a'), (2, 'b'), (3, 'c'), (4, 'd')]);
+        // 1は両方が同じに変え、2は片方だけが消し、3は両方が違うように変え、5と6は片方ずつが足す
+        let ours = tree(&[(1, 'z'), (3, 'o'), (4, 'd'), (5, 'e')]);
+        let theirs = tree(&[(1, 'z'), (2, 'b'), (3, 't'), (4, 'd'), (6, 'f')]);
+        let mut conflicts = vec![];
+        let merged = Btree::merge3(&base, &ours, &theirs, |key, conflict| {
+            conflicts.push((*key, conflict.base.copied(), conflict.ours.copied()));
+            conflict.theirs.copied()
+        });
+        assert_eq!(
+            entries(&merged),
+            vec![(1, 'z'), (3, 't'), (4, 'd'), (5, 'e'), (6, 'f')]
+        );
+        assert_eq!(conflicts, vec![(3, Some('c'), Some('o'))]);
+
+        // 片方が消し、もう片方が変えたキーも衝突になる
+        let ours = tree(&[(2, 'b')]);
+        let theirs = tree(&[(1, 'y'), (2, 'b')]);
+        let merged = Btree::merge3(&base, &ours, &theirs, |_, conflict| {
+            assert_eq!(conflict.ours, None);
+            None
+        });
+        assert_eq!(entries(&merged), vec![(2, 'b')]);
+    }
+
+    #[test]
+    fn test_diff_across_shapes_and_empty_base() {
+        // ノードの大きさが違っても、キーの順に突き合わせる
+        let mut old: Btree<i32, char> = Btree::new(3);
+        let mut new: Btree<i32, char> = Btree::new(8);
+        for key in 0..100 {
+            old.insert(key, 'a');
+            if key % 10 != 0 {
+                new.insert(key, if key % 10 == 5 { 'b' } else { 'a' });
+            }
+        }
+        let removed: Vec<i32> = (0..100).step_by(10).collect();
+        let changed: Vec<i32> = (5..100).step_by(10).collect();
+        let (mut actual_removed, mut actual_changed) = (vec![], vec![]);
+        for entry in old.diff(&new) {
+            match entry {
+                DiffEntry::Removed { key, .. } => actual_removed.push(*key),
+                DiffEntry::Changed { key, .. } => actual_changed.push(*key),
+                DiffEntry::Added { key, .. } => panic!("unexpected key {key}"),
+            }
+        }
+        assert_eq!(actual_removed, removed);
+        assert_eq!(actual_changed, changed);
+
+        // 両方が同じキーを消したり足したりしても衝突にならない
+        let base = tree(&[(1, 'a'), (2, 'b')]);
+        let ours = tree(&[(2, 'b'), (3, 'c')]);
+        let theirs = tree(&[(3, 'c')]);
+        let merged = Btree::merge3(&base, &ours, &theirs, |_, _| unreachable!());
+        assert_eq!(entries(&merged), vec![(3, 'c')]);
+        let merged = Btree::merge3(&tree(&[]), &ours, &ours, |_, _| unreachable!());
+        assert_eq!(entries(&merged), entries(&ours));
+    }
+}
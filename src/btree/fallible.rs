@@ -0,0 +1,97 @@
+use crate::btree::node::BtreeNode;
+use crate::btree::tree::Btree;
+use std::alloc::Layout;
+use std::collections::TryReserveError;
+use std::fmt;
+
+/// メモリの確保に失敗したことを表すエラー
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("memory allocation failed")
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+impl From<TryReserveError> for AllocError {
+    fn from(_: TryReserveError) -> Self {
+        AllocError
+    }
+}
+
+/// `Box::new`と同じ領域を確保し、失敗した場合はパニックせずにエラーを返す
+pub(crate) fn try_box<T>(value: T) -> Result<Box<T>, AllocError> {
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        return Ok(Box::new(value));
+    }
+    // SAFETY: `layout`の大きさは0でない
+    let ptr = unsafe { std::alloc::alloc(layout) }.cast::<T>();
+    if ptr.is_null() {
+        return Err(AllocError);
+    }
+    // SAFETY: `ptr`はグローバルアロケータから`T`のレイアウトで確保した領域なので、`Box`が所有できる
+    unsafe {
+        ptr.write(value);
+        Ok(Box::from_raw(ptr))
+    }
+}
+
+/// `root`への挿入に必要な領域を確保し、分割に使うノードを使う順に返す
+#[allow(clippy::vec_box)]
+fn try_reserve_root<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone>(
+    root: &mut BtreeNode<K, V>,
+    key: &K,
+    max_count: usize,
+) -> Result<Vec<Box<BtreeNode<K, V>>>, AllocError> {
+    let mut spares = vec![];
+    if root.try_reserve_insert(key, max_count, &mut spares)? {
+        // 根の分割には左側・右側・新しい根の3つを使う
+        spares.try_reserve(3)?;
+        spares.push(BtreeNode::try_node(0, 0)?);
+        spares.push(root.try_spare()?);
+        spares.push(BtreeNode::try_node(1, 2)?);
+    }
+    Ok(spares)
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    /// `insert`と同じだが、メモリの確保に失敗した場合はパニックせず、木を変えずにエラーを返す
+    ///
+    /// 挿入で増える要素の領域と分割で作るノードを、木を変える前に全て確保する。
+    /// キーや値の`clone`（履歴を有効にしている場合）が行う確保は対象外。
+    /// `subscribe`した購読者への通知（変更の`clone`、チャネルへの送信、コールバック）も対象外で、
+    /// 通知は木を変えた後に行うので、そこで確保に失敗した場合は木が変わった状態になる。
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), AllocError> {
+        self.try_reserve_edit()?;
+
+        let max_count = self.max_count();
+        let mut root = self.root_mut().take().unwrap_or(BtreeNode::new());
+        let spares = match try_reserve_root(&mut root, &key, max_count) {
+            Ok(spares) => spares,
+            Err(error) => {
+                // 空の木に仮に作った根は戻さない
+                if !root.is_empty() {
+                    *self.root_mut() = Some(root);
+                }
+                return Err(error);
+            }
+        };
+        *self.root_mut() = Some(root);
+
+        let edit = self.prepare_edit(&key, Some(&value));
+        let mut spares = spares.into_iter();
+        self.insert_with(key, value, &mut || {
+            spares.next().expect("分割に使うノードは確保済み")
+        });
+        if let Some(edit) = edit {
+            self.push_edit(edit);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+use crate::btree::Search;
+use crate::btree::tree::Btree;
+use std::collections::{TryReserveError, VecDeque};
+
+/// 1回の書き込みで変わる前と後の値（`None`はキーが存在しないことを表す）
+#[derive(Clone)]
+pub(crate) struct Edit<K: 'static + Clone, V: 'static + Clone> {
+    sequence: u64,
+    key: K,
+    before: Option<V>,
+    after: Option<V>,
+}
+
+/// `Btree`の書き込み履歴
+///
+/// `insert`は上書き前の値（またはキーが無かったこと）を、`delete`は削除したエントリを記録する。
+/// 取り消しは記録した変更前の値を書き戻すことで行う。
+/// 同じ番号の変更は1回の取り消しの単位で、`Transaction::commit`の書き込みはすべて同じ番号になる。
+#[derive(Clone)]
+pub(crate) struct History<K: 'static + Clone, V: 'static + Clone> {
+    undo: VecDeque<Edit<K, V>>,
+    redo: Vec<Edit<K, V>>,
+    limit: usize,
+    next_sequence: u64,
+    /// 変更をまとめている間は、変更ごとに番号を進めない
+    grouping: bool,
+}
+
+/// `Btree::checkpoint`を呼んだ時点の履歴上の位置
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint(u64);
+
+impl<K: 'static + Clone, V: 'static + Clone> History<K, V> {
+    fn new(limit: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: vec![],
+            limit,
+            next_sequence: 0,
+            grouping: false,
+        }
+    }
+
+    /// 記録が`limit`を超えたら古い変更から捨てる
+    ///
+    /// まとめた変更は途中から取り消せないので、同じ番号の変更も一緒に捨てる。
+    /// 最後の単位だけは、`limit`より多くの変更をまとめていても残す。
+    fn trim(&mut self) {
+        while self.undo.len() > self.limit {
+            let (Some(front), Some(back)) = (self.undo.front(), self.undo.back()) else {
+                break;
+            };
+            if front.sequence == back.sequence {
+                break;
+            }
+            let Some(dropped) = self.undo.pop_front() else {
+                break;
+            };
+            while self
+                .undo
+                .front()
+                .is_some_and(|edit| edit.sequence == dropped.sequence)
+            {
+                self.undo.pop_front();
+            }
+        }
+    }
+
+    fn pop_undo(&mut self, sequence: u64) -> Option<Edit<K, V>> {
+        if self.undo.back()?.sequence != sequence {
+            return None;
+        }
+        self.undo.pop_back()
+    }
+
+    fn pop_redo(&mut self, sequence: u64) -> Option<Edit<K, V>> {
+        if self.redo.last()?.sequence != sequence {
+            return None;
+        }
+        self.redo.pop()
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    /// 書き込み履歴の記録を始める（`limit`は取り消せる書き込みの最大数）
+    pub fn enable_history(&mut self, limit: usize) {
+        *self.history_mut() = Some(History::new(limit));
+    }
+
+    /// 書き込み履歴の記録をやめ、記録済みの履歴を捨てる
+    pub fn disable_history(&mut self) {
+        *self.history_mut() = None;
+    }
+
+    pub(crate) fn record(&mut self, key: &K, after: Option<&V>) {
+        if let Some(edit) = self.prepare_edit(key, after) {
+            self.push_edit(edit);
+        }
+    }
+
+    /// 記録する変更を作る（履歴が無効か、何も変わらない場合は`None`）
+    pub(crate) fn prepare_edit(&self, key: &K, after: Option<&V>) -> Option<Edit<K, V>> {
+        let history = self.history()?;
+        let before = self.search(key).map(|(_, value)| value);
+        if before.is_none() && after.is_none() {
+            // 存在しないキーの削除は何も変えない
+            return None;
+        }
+        Some(Edit {
+            sequence: history.next_sequence,
+            key: key.clone(),
+            before,
+            after: after.cloned(),
+        })
+    }
+
+    pub(crate) fn push_edit(&mut self, edit: Edit<K, V>) {
+        if let Some(history) = self.history_mut() {
+            history.redo.clear();
+            history.undo.push_back(edit);
+            if !history.grouping {
+                history.next_sequence += 1;
+                history.trim();
+            }
+        }
+    }
+
+    /// `f`の中の書き込みを、1回の`undo`でまとめて取り消せるように記録する
+    pub(crate) fn record_as_one(&mut self, f: impl FnOnce(&mut Self)) {
+        if let Some(history) = self.history_mut() {
+            history.grouping = true;
+        }
+        f(self);
+        if let Some(history) = self.history_mut() {
+            history.grouping = false;
+            let sequence = history.next_sequence;
+            if history
+                .undo
+                .back()
+                .is_some_and(|edit| edit.sequence == sequence)
+            {
+                history.next_sequence += 1;
+            }
+            history.trim();
+        }
+    }
+
+    /// 変更を1つ記録できる領域を先に確保する
+    pub(crate) fn try_reserve_edit(&mut self) -> Result<(), TryReserveError> {
+        match self.history_mut() {
+            Some(history) => history.undo.try_reserve(1),
+            None => Ok(()),
+        }
+    }
+
+    fn apply(&mut self, key: K, value: Option<V>) {
+        match value {
+            Some(value) => self.insert_into_root(key, value),
+            None => self.delete_from_root(&key),
+        }
+    }
+
+    /// 直前の書き込みを取り消す（取り消せる書き込みがなければ`false`）
+    ///
+    /// `Transaction::commit`で反映した書き込みは、まとめて取り消す。
+    pub fn undo(&mut self) -> bool {
+        let Some(sequence) = self
+            .history()
+            .and_then(|h| h.undo.back())
+            .map(|e| e.sequence)
+        else {
+            return false;
+        };
+        while let Some(edit) = self
+            .history_mut()
+            .as_mut()
+            .and_then(|h| h.pop_undo(sequence))
+        {
+            self.apply(edit.key.clone(), edit.before.clone());
+            if let Some(history) = self.history_mut() {
+                history.redo.push(edit);
+            }
+        }
+        true
+    }
+
+    /// 直前に取り消した書き込みをやり直す（やり直せる書き込みがなければ`false`）
+    pub fn redo(&mut self) -> bool {
+        let Some(sequence) = self
+            .history()
+            .and_then(|h| h.redo.last())
+            .map(|e| e.sequence)
+        else {
+            return false;
+        };
+        while let Some(mut edit) = self
+            .history_mut()
+            .as_mut()
+            .and_then(|h| h.pop_redo(sequence))
+        {
+            self.apply(edit.key.clone(), edit.after.clone());
+            if let Some(history) = self.history_mut() {
+                // やり直しは新しい書き込みとして、後から取った`checkpoint`より後の位置に置く
+                edit.sequence = history.next_sequence;
+                history.undo.push_back(edit);
+            }
+        }
+        if let Some(history) = self.history_mut() {
+            history.next_sequence += 1;
+            history.trim();
+        }
+        true
+    }
+
+    /// 現在の位置を記録する
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.history().map_or(0, |history| history.next_sequence))
+    }
+
+    /// `checkpoint`以降の書き込みをすべて取り消し、取り消した回数（`commit`はまとめて1回）を返す
+    pub fn undo_to(&mut self, checkpoint: Checkpoint) -> usize {
+        let mut count = 0;
+        while self
+            .history()
+            .and_then(|history| history.undo.back())
+            .is_some_and(|edit| edit.sequence >= checkpoint.0)
+        {
+            self.undo();
+            count += 1;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{Delete, Insert};
+
+    fn entries(tree: &Btree<i32, i32>) -> Vec<(i32, i32)> {
+        tree.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    #[test]
+    fn test_undo_insert() {
+        let mut tree = Btree::new(3);
+        tree.enable_history(100);
+
+        tree.insert(10, 100);
+        tree.insert(10, 200);
+        assert!(tree.undo());
+        // 上書き前の値に戻る
+        assert_eq!(tree.search(&10), Some((10, 100)));
+        assert!(tree.undo());
+        // キーが存在しなかった状態に戻る
+        assert_eq!(tree.search(&10), None);
+        assert!(!tree.undo());
+    }
+
+    #[test]
+    fn test_undo_delete() {
+        let mut tree = Btree::new(3);
+        tree.enable_history(100);
+        for i in 0..10 {
+            tree.insert(i, i * 10);
+        }
+        tree.delete(&3);
+        tree.delete(&100); // 存在しないキーは記録しない
+        assert!(tree.undo());
+        assert_eq!(tree.search(&3), Some((3, 30)));
+        assert!(tree.undo());
+        assert_eq!(tree.search(&9), None);
+    }
+
+    #[test]
+    fn test_redo() {
+        let mut tree = Btree::new(3);
+        tree.enable_history(100);
+        for i in 0..20 {
+            tree.insert(i, i);
+        }
+        for i in 0..20 {
+            tree.delete(&i);
+        }
+        for _ in 0..40 {
+            assert!(tree.undo());
+        }
+        assert!(entries(&tree).is_empty());
+        for _ in 0..30 {
+            assert!(tree.redo());
+        }
+        assert_eq!(entries(&tree), (10..20).map(|i| (i, i)).collect::<Vec<_>>());
+
+        // 新しい書き込みをするとやり直しの履歴は消える
+        tree.insert(100, 100);
+        assert!(!tree.redo());
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut tree = Btree::new(3);
+        tree.enable_history(3);
+        for i in 0..5 {
+            tree.insert(i, i);
+        }
+        let mut count = 0;
+        while tree.undo() {
+            count += 1;
+        }
+        assert_eq!(count, 3);
+        assert_eq!(entries(&tree), vec![(0, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn test_undo_to_checkpoint() {
+        let mut tree = Btree::new(4);
+        tree.enable_history(100);
+        tree.insert(1, 1);
+        tree.insert(2, 2);
+        let checkpoint = tree.checkpoint();
+        tree.insert(3, 3);
+        tree.insert(1, 10);
+        tree.delete(&2);
+
+        assert_eq!(tree.undo_to(checkpoint), 3);
+        assert_eq!(entries(&tree), vec![(1, 1), (2, 2)]);
+        assert_eq!(tree.undo_to(checkpoint), 0);
+    }
+
+    #[test]
+    fn test_undo_to_checkpoint_after_redo() {
+        let mut tree = Btree::new(4);
+        tree.enable_history(100);
+        tree.insert(1, 1);
+        tree.undo();
+        let checkpoint = tree.checkpoint();
+        tree.redo();
+
+        // 取った後にやり直した書き込みも取り消す
+        assert_eq!(tree.undo_to(checkpoint), 1);
+        assert_eq!(entries(&tree), vec![]);
+    }
+
+    #[test]
+    fn test_history_disabled() {
+        let mut tree = Btree::new(3);
+        tree.insert(1, 1);
+        assert!(!tree.undo());
+
+        tree.enable_history(10);
+        tree.insert(2, 2);
+        tree.disable_history();
+        assert!(!tree.undo());
+        assert_eq!(entries(&tree), vec![(1, 1), (2, 2)]);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::btree::tree::Btree;
+use crate::btree::{Delete, Insert, Search};
+use std::any::Any;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+/// 行から取り出したキーで主キーを引く索引
+///
+/// 同じキーを持つ行が複数あり得るので、`(キー, 主キー)`の組を値なしで持つ。
+/// 行の追加と削除は、組を1つ挿入か削除するだけで済む。
+struct SecondaryIndex<
+    PK: 'static + Clone + PartialEq + PartialOrd,
+    Row,
+    SK: 'static + Clone + PartialEq + PartialOrd,
+> {
+    extract: Box<dyn Fn(&Row) -> SK>,
+    tree: Btree<(SK, PK), ()>,
+}
+
+/// 型の異なる索引を、まとめて`IndexedTable`に持たせるためのトレイト
+trait AnyIndex<PK, Row> {
+    fn add(&mut self, pk: &PK, row: &Row);
+    fn remove(&mut self, pk: &PK, row: &Row);
+    /// `old`から`new`に変わった行の索引を付け直す
+    fn replace(&mut self, pk: &PK, old: &Row, new: &Row);
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<
+    PK: 'static + Clone + PartialEq + PartialOrd,
+    Row: 'static,
+    SK: 'static + Clone + PartialEq + PartialOrd,
+> AnyIndex<PK, Row> for SecondaryIndex<PK, Row, SK>
+{
+    fn add(&mut self, pk: &PK, row: &Row) {
+        self.tree.insert(((self.extract)(row), pk.clone()), ());
+    }
+
+    fn remove(&mut self, pk: &PK, row: &Row) {
+        self.tree.delete(&((self.extract)(row), pk.clone()));
+    }
+
+    fn replace(&mut self, pk: &PK, old: &Row, new: &Row) {
+        // キーが変わらなければ索引はそのままでよい
+        if (self.extract)(old) != (self.extract)(new) {
+            self.remove(pk, old);
+            self.add(pk, new);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// `IndexedTable::add_index`が返す、索引を指定するための値
+///
+/// 索引のキーの型を覚えているので、`range`に渡す範囲の型が合わなければコンパイルエラーになる。
+pub struct IndexId<SK> {
+    index: usize,
+    key: PhantomData<fn() -> SK>,
+}
+
+impl<SK> Clone for IndexId<SK> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<SK> Copy for IndexId<SK> {}
+
+/// 主キーで行を持つ`Btree`と、行から取り出したキーで引く副索引の`Btree`をまとめた表
+///
+/// `insert`・`update`・`delete`のたびにすべての索引を付け直すので、索引は常に主の木と一致する。
+pub struct IndexedTable<PK: 'static + Clone + PartialEq + PartialOrd, Row: 'static + Clone> {
+    primary: Btree<PK, Row>,
+    indexes: Vec<Box<dyn AnyIndex<PK, Row>>>,
+    max_count: usize,
+}
+
+impl<PK: 'static + Clone + PartialEq + PartialOrd, Row: 'static + Clone> IndexedTable<PK, Row> {
+    /// 主の木と索引の木を、1ノードに最大`max_count`個のキーを持つ木で作る
+    ///
+    /// # Panics
+    ///
+    /// `max_count`が3未満の場合（`Btree::new`と同じ）
+    pub fn new(max_count: usize) -> Self {
+        IndexedTable {
+            primary: Btree::new(max_count),
+            indexes: vec![],
+            max_count,
+        }
+    }
+
+    /// `extract`で行から取り出したキーの索引を追加する（既にある行も索引に入る）
+    pub fn add_index<SK: 'static + Clone + PartialEq + PartialOrd>(
+        &mut self,
+        extract: impl Fn(&Row) -> SK + 'static,
+    ) -> IndexId<SK> {
+        let mut index = SecondaryIndex {
+            extract: Box::new(extract),
+            tree: Btree::new(self.max_count),
+        };
+        for (pk, row) in self.primary.iter() {
+            index.add(pk, row);
+        }
+        self.indexes.push(Box::new(index));
+        IndexId {
+            index: self.indexes.len() - 1,
+            key: PhantomData,
+        }
+    }
+
+    pub fn get(&self, pk: &PK) -> Option<Row> {
+        self.primary.search(pk).map(|(_, row)| row)
+    }
+
+    /// 行を追加するか置き換え、置き換えた場合は元の行を返す
+    pub fn insert(&mut self, pk: PK, row: Row) -> Option<Row> {
+        let old = self.get(&pk);
+        for index in &mut self.indexes {
+            match &old {
+                Some(old) => index.replace(&pk, old, &row),
+                None => index.add(&pk, &row),
+            }
+        }
+        self.primary.insert(pk, row);
+        old
+    }
+
+    /// 行を`f`で書き換え、行があったかを返す
+    pub fn update(&mut self, pk: &PK, f: impl FnOnce(&mut Row)) -> bool {
+        let Some(mut row) = self.get(pk) else {
+            return false;
+        };
+        f(&mut row);
+        self.insert(pk.clone(), row);
+        true
+    }
+
+    /// 行を削除し、削除した行を返す
+    pub fn delete(&mut self, pk: &PK) -> Option<Row> {
+        let old = self.get(pk)?;
+        for index in &mut self.indexes {
+            index.remove(pk, &old);
+        }
+        self.primary.delete(pk);
+        Some(old)
+    }
+
+    /// 主キーの昇順にすべての行をたどる
+    pub fn iter(&self) -> impl Iterator<Item = (&PK, &Row)> {
+        self.primary.iter()
+    }
+
+    /// 索引のキーが`range`に入る行を、索引のキー、主キーの順に昇順で返す
+    ///
+    /// # Panics
+    ///
+    /// `index`が別の表の`add_index`で作られたものだと、パニックすることがある。
+    pub fn range<SK: 'static + Clone + PartialEq + PartialOrd, R: RangeBounds<SK>>(
+        &self,
+        index: IndexId<SK>,
+        range: R,
+    ) -> impl Iterator<Item = (&PK, &Row)> {
+        // 索引のキーだけを前置部分として、組の範囲に直す
+        let prefix = |bound: Bound<&SK>| bound.cloned().map(|key| (key,));
+        self.secondary(index)
+            .tree
+            .prefix_range((prefix(range.start_bound()), prefix(range.end_bound())))
+            .filter_map(|((_, pk), _)| self.primary.range(pk..=pk).next())
+    }
+
+    /// 索引のキーが`key`に一致する行を、主キーの昇順で返す
+    pub fn find<SK: 'static + Clone + PartialEq + PartialOrd>(
+        &self,
+        index: IndexId<SK>,
+        key: &SK,
+    ) -> impl Iterator<Item = (&PK, &Row)> {
+        self.range(index, key..=key)
+    }
+
+    fn secondary<SK: 'static + Clone + PartialEq + PartialOrd>(
+        &self,
+        index: IndexId<SK>,
+    ) -> &SecondaryIndex<PK, Row, SK> {
+        self.indexes
+            .get(index.index)
+            .and_then(|index| index.as_any().downcast_ref())
+            .expect("index belongs to another table")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct User {
+        name: String,
+        age: u32,
+    }
+
+    fn user(name: &str, age: u32) -> User {
+        User {
+            name: name.to_string(),
+            age,
+        }
+    }
+
+    fn ids<'a>(rows: impl Iterator<Item = (&'a u32, &'a User)>) -> Vec<u32> {
+        rows.map(|(pk, _)| *pk).collect()
+    }
+
+    #[test]
+    #[should_panic(expected = "max_count must be at least 3")]
+    fn test_max_count_too_small() {
+        IndexedTable::<u32, User>::new(2);
+    }
+
+    #[test]
+    fn test_range_on_secondary_key() {
+        let mut table = IndexedTable::new(3);
+        let by_age = table.add_index(|user: &User| user.age);
+        let by_name = table.add_index(|user: &User| user.name.clone());
+        table.insert(1, user("carol", 30));
+        table.insert(2, user("alice", 25));
+        table.insert(3, user("bob", 30));
+        table.insert(4, user("dave", 40));
+
+        assert_eq!(ids(table.range(by_age, 26..=40)), vec![1, 3, 4]);
+        assert_eq!(ids(table.find(by_age, &30)), vec![1, 3]);
+        assert_eq!(ids(table.range(by_name, "b".to_string()..)), vec![3, 1, 4]);
+        assert_eq!(ids(table.find(by_age, &99)), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_update_and_delete_keep_indexes() {
+        let mut table = IndexedTable::new(3);
+        let by_age = table.add_index(|user: &User| user.age);
+        table.insert(1, user("alice", 25));
+        table.insert(2, user("bob", 25));
+
+        assert!(table.update(&1, |user| user.age = 26));
+        assert!(!table.update(&9, |user| user.age = 0));
+        assert_eq!(ids(table.find(by_age, &25)), vec![2]);
+        assert_eq!(ids(table.find(by_age, &26)), vec![1]);
+
+        assert_eq!(table.insert(2, user("bob", 27)), Some(user("bob", 25)));
+        assert_eq!(ids(table.find(by_age, &25)), Vec::<u32>::new());
+
+        assert_eq!(table.delete(&1), Some(user("alice", 26)));
+        assert_eq!(table.delete(&1), None);
+        assert_eq!(ids(table.range(by_age, ..)), vec![2]);
+    }
+
+    #[test]
+    fn test_add_index_to_existing_rows() {
+        let mut table = IndexedTable::new(3);
+        for i in 0..20 {
+            table.insert(i, user(&format!("user{i}"), i % 4));
+        }
+        let by_age = table.add_index(|user: &User| user.age);
+        assert_eq!(ids(table.find(by_age, &1)), vec![1, 5, 9, 13, 17]);
+    }
+
+    #[test]
+    fn test_many_rows_with_same_key() {
+        let mut table = IndexedTable::new(3);
+        let by_age = table.add_index(|user: &User| user.age);
+        for i in 0..100 {
+            table.insert(i, user("x", 20 + i % 2));
+        }
+        // 同じキーの組が多くのノードにまたがっても、主キーの昇順で引ける
+        let odd: Vec<u32> = (0..100).filter(|i| i % 2 == 1).collect();
+        assert_eq!(ids(table.find(by_age, &21)), odd);
+        assert_eq!(
+            ids(table.range(by_age, (Bound::Excluded(20), Bound::Unbounded))),
+            odd
+        );
+        assert_eq!(table.range(by_age, ..21).count(), 50);
+        assert_eq!(
+            table
+                .range(by_age, (Bound::Excluded(20), Bound::Excluded(21)))
+                .count(),
+            0
+        );
+
+        for i in 0..100 {
+            table.delete(&i);
+        }
+        assert_eq!(table.range(by_age, ..).count(), 0);
+        assert_eq!(table.secondary(by_age).tree.iter().count(), 0);
+    }
+}
@@ -0,0 +1,486 @@
+use crate::btree::node::{
+    DeleteFromChildOperation, NodeStorage, NodeVec, merge_into, rotate_left, rotate_right,
+};
+use crate::btree::{BinarySearch, Delete, Insert, Search};
+use std::mem::MaybeUninit;
+use std::ptr;
+
+/// 容量`N`の配列をノードの中に直接持つ可変長の列
+///
+/// 先頭の`len`個だけが初期化されている。
+pub(crate) struct InlineVec<T, const N: usize> {
+    len: usize,
+    items: [MaybeUninit<T>; N],
+}
+
+impl<T, const N: usize> InlineVec<T, N> {
+    pub(crate) fn new() -> Self {
+        InlineVec {
+            len: 0,
+            items: [const { MaybeUninit::uninit() }; N],
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn as_slice(&self) -> &[T] {
+        // SAFETY: 先頭の`len`個は初期化済み
+        unsafe { std::slice::from_raw_parts(self.items.as_ptr().cast::<T>(), self.len) }
+    }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: 先頭の`len`個は初期化済み
+        unsafe { std::slice::from_raw_parts_mut(self.items.as_mut_ptr().cast::<T>(), self.len) }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self.items.as_mut_ptr().cast::<T>()
+    }
+
+    pub(crate) fn push(&mut self, item: T) {
+        assert!(self.len < N, "InlineVec is full");
+        self.items[self.len].write(item);
+        self.len += 1;
+    }
+
+    pub(crate) fn insert(&mut self, index: usize, item: T) {
+        assert!(self.len < N, "InlineVec is full");
+        assert!(index <= self.len, "insertion index out of bounds");
+        // SAFETY: 容量を超えない範囲で、`index`以降の初期化済みの要素を1つ後ろにずらす
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            ptr::copy(p, p.add(1), self.len - index);
+            ptr::write(p, item);
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "removal index out of bounds");
+        // SAFETY: `index`の要素を読み出してから、後ろの要素を詰める
+        unsafe {
+            let p = self.as_mut_ptr().add(index);
+            let item = ptr::read(p);
+            ptr::copy(p.add(1), p, self.len - index - 1);
+            self.len -= 1;
+            item
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        // SAFETY: 末尾の要素は初期化済みで、`len`を減らしたので二重に解放されない
+        Some(unsafe { self.items[self.len].assume_init_read() })
+    }
+
+    /// `at`以降の要素を新しい列に移す
+    pub(crate) fn split_off(&mut self, at: usize) -> Self {
+        assert!(at <= self.len, "split index out of bounds");
+        let mut other = InlineVec::new();
+        // SAFETY: 移した要素は`self`の長さから外すので、所有権は`other`だけが持つ
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_mut_ptr().add(at), other.as_mut_ptr(), self.len - at);
+        }
+        other.len = self.len - at;
+        self.len = at;
+        other
+    }
+
+    /// `other`の要素をすべて末尾に移す
+    pub(crate) fn append(&mut self, other: &mut Self) {
+        assert!(self.len + other.len <= N, "InlineVec is full");
+        // SAFETY: 移した要素は`other`の長さから外すので、所有権は`self`だけが持つ
+        unsafe {
+            ptr::copy_nonoverlapping(
+                other.as_mut_ptr(),
+                self.as_mut_ptr().add(self.len),
+                other.len,
+            );
+        }
+        self.len += other.len;
+        other.len = 0;
+    }
+}
+
+impl<T, const N: usize> Drop for InlineVec<T, N> {
+    fn drop(&mut self) {
+        // SAFETY: 初期化済みの要素だけを解放する
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+impl<T, const N: usize> NodeVec<T> for InlineVec<T, N> {
+    fn as_slice(&self) -> &[T] {
+        InlineVec::as_slice(self)
+    }
+
+    fn push(&mut self, item: T) {
+        InlineVec::push(self, item)
+    }
+
+    fn insert(&mut self, index: usize, item: T) {
+        InlineVec::insert(self, index, item)
+    }
+
+    fn remove(&mut self, index: usize) -> T {
+        InlineVec::remove(self, index)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        InlineVec::pop(self)
+    }
+
+    fn append(&mut self, other: &mut Self) {
+        InlineVec::append(self, other)
+    }
+}
+
+/// キー・値・子ノードをそれぞれ固定容量の配列でノード内に持つB木のノード
+///
+/// `B`は子ノードの最大数で、キーは最大`B - 1`個まで持つ。
+/// 配列の最後の1枠は、分割する直前に一時的に`B`個目のキーを置くために使う。
+struct InlineNode<K, V, const B: usize> {
+    keys: InlineVec<K, B>,
+    values: InlineVec<V, B>,
+    children: InlineVec<Box<InlineNode<K, V, B>>, B>,
+}
+
+/// 分割で親に追加する中央のエントリと右のノード
+type InlineSplit<K, V, const B: usize> = (K, V, Box<InlineNode<K, V, B>>);
+
+/// ノードをヒープ上の`Vec`ではなく固定容量の配列で持つB木
+///
+/// 探索でたどるキーがノード内に連続して並ぶため、1階層あたりのキャッシュミスが少ない。
+pub struct InlineBtree<K, V, const B: usize> {
+    root: Option<Box<InlineNode<K, V, B>>>,
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    InlineNode<K, V, B>
+{
+    fn new() -> Self {
+        InlineNode {
+            keys: InlineVec::new(),
+            values: InlineVec::new(),
+            children: InlineVec::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn min_count() -> usize {
+        (B - 1) / 2
+    }
+
+    fn lookup(&self, key: &K) -> Result<usize, usize> {
+        self.keys.as_slice().binary_lookup(key)
+    }
+
+    fn search(&self, key: &K) -> Option<(K, V)> {
+        match self.lookup(key) {
+            Ok(i) => Some((
+                self.keys.as_slice()[i].clone(),
+                self.values.as_slice()[i].clone(),
+            )),
+            Err(i) => {
+                if self.is_leaf() {
+                    None
+                } else {
+                    self.children.as_slice()[i].search(key)
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<InlineSplit<K, V, B>> {
+        let index = match self.lookup(&key) {
+            Ok(i) => {
+                self.keys.as_mut_slice()[i] = key;
+                self.values.as_mut_slice()[i] = value;
+                return None;
+            }
+            Err(i) => i,
+        };
+        if self.is_leaf() {
+            self.keys.insert(index, key);
+            self.values.insert(index, value);
+            return self
+                .split_keys_if_full()
+                .map(|(key, value, right)| (key, value, Box::new(right)));
+        }
+
+        let (key, value, right) = self.children.as_mut_slice()[index].insert(key, value)?;
+        self.keys.insert(index, key);
+        self.values.insert(index, value);
+        if self.keys.len() < B {
+            self.children.insert(index + 1, right);
+            return None;
+        }
+
+        // 子ノードの配列には空きがないので、先に分割してから新しい子を入れる
+        let mid_index = B / 2;
+        let (mid_key, mid_value, mut sibling) = self.split_keys_if_full()?;
+        if index < mid_index {
+            sibling.children = self.children.split_off(mid_index);
+            self.children.insert(index + 1, right);
+        } else {
+            sibling.children = self.children.split_off(mid_index + 1);
+            sibling.children.insert(index - mid_index, right);
+        }
+        Some((mid_key, mid_value, Box::new(sibling)))
+    }
+
+    /// キーが`B`個になっていれば、中央のエントリと右半分のキー・値を切り出す
+    fn split_keys_if_full(&mut self) -> Option<(K, V, InlineNode<K, V, B>)> {
+        if self.keys.len() < B {
+            return None;
+        }
+        let mid_index = B / 2;
+        let mut right = InlineNode::new();
+        right.keys = self.keys.split_off(mid_index + 1);
+        right.values = self.values.split_off(mid_index + 1);
+        let key = self.keys.pop()?;
+        let value = self.values.pop()?;
+        Some((key, value, right))
+    }
+
+    fn delete(&mut self, key: &K) {
+        match self.lookup(key) {
+            Ok(i) => {
+                if self.is_leaf() {
+                    self.keys.remove(i);
+                    self.values.remove(i);
+                } else {
+                    // 左の部分木の最大のエントリで置き換える
+                    let (key, value) = self.children.as_mut_slice()[i].pop_max();
+                    self.keys.as_mut_slice()[i] = key;
+                    self.values.as_mut_slice()[i] = value;
+                    self.rebalance_child(i);
+                }
+            }
+            Err(i) => {
+                if self.is_leaf() {
+                    return;
+                }
+                self.children.as_mut_slice()[i].delete(key);
+                self.rebalance_child(i);
+            }
+        }
+    }
+
+    fn pop_max(&mut self) -> (K, V) {
+        if self.is_leaf() {
+            return (self.keys.pop().unwrap(), self.values.pop().unwrap());
+        }
+        let index = self.children.len() - 1;
+        let entry = self.children.as_mut_slice()[index].pop_max();
+        self.rebalance_child(index);
+        entry
+    }
+
+    /// 削除で子ノードが最小数を下回った場合に、兄弟からの回転または併合で補う
+    fn rebalance_child(&mut self, index: usize) {
+        let children = self.children.as_slice();
+        if children[index].entry_count() >= Self::min_count() {
+            return;
+        }
+        let has_spare = |i: usize| children[i].entry_count() > Self::min_count();
+        match DeleteFromChildOperation::fill(index, children.len(), has_spare) {
+            DeleteFromChildOperation::None => {}
+            DeleteFromChildOperation::RotateRight => {
+                let (left, right) = self.children.as_mut_slice().split_at_mut(index);
+                let separator = (
+                    &mut self.keys.as_mut_slice()[index - 1],
+                    &mut self.values.as_mut_slice()[index - 1],
+                );
+                rotate_right(separator, &mut *left[index - 1], &mut *right[0]);
+            }
+            DeleteFromChildOperation::RotateLeft => {
+                let (left, right) = self.children.as_mut_slice().split_at_mut(index + 1);
+                let separator = (
+                    &mut self.keys.as_mut_slice()[index],
+                    &mut self.values.as_mut_slice()[index],
+                );
+                rotate_left(separator, &mut *left[index], &mut *right[0]);
+            }
+            DeleteFromChildOperation::MergeToLeft => self.merge_children(index - 1),
+            DeleteFromChildOperation::MergeToRight => self.merge_children(index),
+        }
+    }
+
+    /// 子ノード`index`と`index + 1`を、間のエントリを挟んで一つにまとめる
+    fn merge_children(&mut self, index: usize) {
+        let separator = (self.keys.remove(index), self.values.remove(index));
+        let mut right = self.children.remove(index + 1);
+        merge_into(
+            &mut *self.children.as_mut_slice()[index],
+            separator,
+            &mut right,
+        );
+    }
+}
+
+impl<K, V, const B: usize> NodeStorage for InlineNode<K, V, B> {
+    type Key = K;
+    type Value = V;
+    type Child = Box<Self>;
+    type Keys = InlineVec<K, B>;
+    type Values = InlineVec<V, B>;
+    type Children = InlineVec<Box<Self>, B>;
+
+    fn parts(&self) -> (&InlineVec<K, B>, &InlineVec<V, B>, &InlineVec<Box<Self>, B>) {
+        (&self.keys, &self.values, &self.children)
+    }
+
+    fn parts_mut(
+        &mut self,
+    ) -> (
+        &mut InlineVec<K, B>,
+        &mut InlineVec<V, B>,
+        &mut InlineVec<Box<Self>, B>,
+    ) {
+        (&mut self.keys, &mut self.values, &mut self.children)
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    InlineBtree<K, V, B>
+{
+    const VALID_CAPACITY: () = assert!(B >= 3, "B must be at least 3");
+
+    pub fn new() -> Self {
+        let () = Self::VALID_CAPACITY;
+        InlineBtree { root: None }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Default
+    for InlineBtree<K, V, B>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Search<K, V>
+    for InlineBtree<K, V, B>
+{
+    fn search(&self, key: &K) -> Option<(K, V)> {
+        self.root.as_ref()?.search(key)
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Insert<K, V>
+    for InlineBtree<K, V, B>
+{
+    fn insert(&mut self, key: K, value: V) {
+        let root = self.root.get_or_insert_with(|| Box::new(InlineNode::new()));
+        if let Some((key, value, right)) = root.insert(key, value) {
+            let left = self.root.take().unwrap();
+            let mut root = InlineNode::new();
+            root.keys.push(key);
+            root.values.push(value);
+            root.children.push(left);
+            root.children.push(right);
+            self.root = Some(Box::new(root));
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Delete<K>
+    for InlineBtree<K, V, B>
+{
+    fn delete(&mut self, key: &K) {
+        let Some(root) = self.root.as_mut() else {
+            return;
+        };
+        root.delete(key);
+        if root.keys.is_empty() {
+            // 根の要素がなくなった場合は、唯一の子ノードを新しい根にする
+            self.root = root.children.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::test_util;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_inline_vec() {
+        let mut vec: InlineVec<i32, 8> = InlineVec::new();
+        for i in [1, 2, 4] {
+            vec.push(i);
+        }
+        vec.insert(2, 3);
+        vec.insert(0, 0);
+        assert_eq!(vec.as_slice(), &[0, 1, 2, 3, 4]);
+        assert_eq!(vec.remove(1), 1);
+        let mut tail = vec.split_off(2);
+        assert_eq!(vec.as_slice(), &[0, 2]);
+        assert_eq!(tail.as_slice(), &[3, 4]);
+        vec.append(&mut tail);
+        assert!(tail.is_empty());
+        assert_eq!(vec.pop(), Some(4));
+        assert_eq!(vec.as_slice(), &[0, 2, 3]);
+    }
+
+    #[test]
+    fn test_inline_vec_drops_items() {
+        let item = Rc::new(());
+        {
+            let mut vec: InlineVec<Rc<()>, 4> = InlineVec::new();
+            vec.push(Rc::clone(&item));
+            vec.push(Rc::clone(&item));
+            let _tail = vec.split_off(1);
+            assert_eq!(Rc::strong_count(&item), 3);
+        }
+        assert_eq!(Rc::strong_count(&item), 1);
+    }
+
+    fn random_operations<const B: usize>() {
+        let mut tree: InlineBtree<i32, i32, B> = InlineBtree::new();
+        test_util::random_operations(&mut tree);
+        if let Some(root) = &tree.root {
+            test_util::check_node(&**root, &|child| &**child, B, true);
+        }
+    }
+
+    #[test]
+    fn test_random_operations() {
+        random_operations::<3>();
+        random_operations::<4>();
+        random_operations::<5>();
+        random_operations::<8>();
+        random_operations::<16>();
+    }
+
+    #[test]
+    fn test_insert_and_delete_all() {
+        let mut tree: InlineBtree<String, i32, 4> = InlineBtree::new();
+        for i in 0..100 {
+            tree.insert(format!("{i:03}"), i);
+        }
+        assert_eq!(
+            tree.search(&"042".to_string()),
+            Some(("042".to_string(), 42))
+        );
+        for i in 0..100 {
+            tree.delete(&format!("{i:03}"));
+        }
+        assert!(tree.root.is_none());
+    }
+}
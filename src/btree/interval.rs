@@ -0,0 +1,270 @@
+use crate::btree::Search;
+use crate::btree::node::{BtreeNode, Summary};
+use crate::btree::stats::RestructureCounts;
+use std::ops::{Bound, ControlFlow, RangeBounds, RangeInclusive};
+
+/// 部分木に含まれる区間の終わりの最大値（空なら`None`）
+#[derive(Clone)]
+struct MaxEnd<T>(Option<T>);
+
+impl<T: 'static + Clone + PartialOrd, V: 'static + Clone> Summary<(T, T), V> for MaxEnd<T> {
+    fn summarize(keys: &[(T, T)], _: &[V], children: &[Box<BtreeNode<(T, T), V, Self>>]) -> Self {
+        let ends = keys.iter().map(|(_, end)| end).chain(
+            children
+                .iter()
+                .filter_map(|child| child.summary().0.as_ref()),
+        );
+        let max = ends.fold(None, |max: Option<&T>, end| match max {
+            Some(max) if end <= max => Some(max),
+            _ => Some(end),
+        });
+        MaxEnd(max.cloned())
+    }
+}
+
+type IntervalNode<T, V> = BtreeNode<(T, T), V, MaxEnd<T>>;
+
+/// 閉区間`[start, end]`をキーに持つB木
+///
+/// エントリは区間の始まり（同じなら終わり）の順に並べ、各ノードには部分木の区間の終わりの最大値を持たせる。
+/// 問い合わせでは、範囲より前に終わる区間しかない部分木と、範囲より後に始まる区間を読み飛ばす。
+pub struct IntervalTree<T: 'static + Clone + PartialOrd, V: 'static + Clone> {
+    root: Option<IntervalNode<T, V>>,
+    max_count: usize,
+}
+
+impl<T: 'static + Clone + PartialOrd, V: 'static + Clone> IntervalTree<T, V> {
+    /// ノードの最大要素数が`max_count`の木を作る
+    ///
+    /// # Panics
+    ///
+    /// `max_count`が3未満の場合
+    pub fn new(max_count: usize) -> Self {
+        assert!(max_count >= 3, "max_count must be at least 3");
+        IntervalTree {
+            root: None,
+            max_count,
+        }
+    }
+
+    /// 区間を挿入する（同じ区間が既にあれば値を置き換える）
+    ///
+    /// # Panics
+    ///
+    /// 区間の始まりが終わりより後にある場合
+    pub fn insert(&mut self, interval: RangeInclusive<T>, value: V) {
+        let (start, end) = interval.into_inner();
+        assert!(start <= end, "interval start must not be after its end");
+        let mut counts = RestructureCounts::default();
+        let new_node = &mut || Box::new(BtreeNode::new());
+        let mut root = self.root.take().unwrap_or(BtreeNode::new());
+        root.insert((start, end), value, self.max_count, &mut counts, new_node);
+        if root.is_full(self.max_count) {
+            root = root.split_root(new_node);
+        }
+        self.root = Some(root);
+    }
+
+    pub fn delete(&mut self, interval: &RangeInclusive<T>) {
+        let key = (interval.start().clone(), interval.end().clone());
+        let mut counts = RestructureCounts::default();
+        if let Some(mut root) = self.root.take() {
+            root.delete(&key, self.max_count, &mut counts);
+            // 根の要素がなくなった場合は、唯一の子ノードを新しい根にする
+            self.root = if root.is_empty() {
+                root.into_only_child()
+            } else {
+                Some(root)
+            };
+        }
+    }
+
+    /// 区間に対応する値を返す
+    pub fn get(&self, interval: &RangeInclusive<T>) -> Option<V> {
+        let key = (interval.start().clone(), interval.end().clone());
+        self.root.as_ref()?.search(&key).map(|(_, value)| value)
+    }
+
+    /// `range`と1点でも重なる区間を、始まりの昇順に返す
+    pub fn overlapping<R: RangeBounds<T>>(&self, range: R) -> Vec<(RangeInclusive<T>, &V)> {
+        let mut found = vec![];
+        self.visit(range, |interval, value| {
+            found.push((interval, value));
+            ControlFlow::Continue(())
+        });
+        found
+    }
+
+    /// `point`を含む区間を、始まりの昇順に返す
+    pub fn containing(&self, point: &T) -> Vec<(RangeInclusive<T>, &V)> {
+        self.overlapping(point..=point)
+    }
+
+    /// `point`を含む区間があれば、そのうち始まりが最も小さいものを返す
+    ///
+    /// 最初の1つが見つかった時点で探索を終えるので、`containing`より速い。
+    pub fn stab(&self, point: &T) -> Option<(RangeInclusive<T>, &V)> {
+        let mut found = None;
+        self.visit(point..=point, |interval, value| {
+            found = Some((interval, value));
+            ControlFlow::Break(())
+        });
+        found
+    }
+
+    /// `range`と重なる区間を始まりの昇順に`f`へ渡す（`f`が`Break`を返したら止める）
+    fn visit<'a, R: RangeBounds<T>>(
+        &'a self,
+        range: R,
+        mut f: impl FnMut(RangeInclusive<T>, &'a V) -> ControlFlow<()>,
+    ) {
+        // 区間の始まりが範囲の終わり以前で、終わりが範囲の始まり以降なら重なる
+        let starts_in_time = |start: &T| match range.end_bound() {
+            Bound::Included(end) => start <= end,
+            Bound::Excluded(end) => start < end,
+            Bound::Unbounded => true,
+        };
+        let ends_in_time = |end: &T| match range.start_bound() {
+            Bound::Included(start) => end >= start,
+            Bound::Excluded(start) => end > start,
+            Bound::Unbounded => true,
+        };
+        if let Some(root) = &self.root {
+            let _ = visit_node(root, &starts_in_time, &ends_in_time, &mut f);
+        }
+    }
+}
+
+fn visit_node<'a, T: 'static + Clone + PartialOrd, V: 'static + Clone>(
+    node: &'a IntervalNode<T, V>,
+    starts_in_time: &impl Fn(&T) -> bool,
+    ends_in_time: &impl Fn(&T) -> bool,
+    f: &mut impl FnMut(RangeInclusive<T>, &'a V) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    // 部分木のどの区間も範囲より前に終わっている
+    if !node.summary().0.as_ref().is_some_and(ends_in_time) {
+        return ControlFlow::Continue(());
+    }
+    for (i, ((start, end), value)) in node.keys().iter().zip(node.values()).enumerate() {
+        if let Some(child) = node.children().get(i) {
+            visit_node(child, starts_in_time, ends_in_time, f)?;
+        }
+        // ここから右の区間は、すべて範囲より後に始まる
+        if !starts_in_time(start) {
+            return ControlFlow::Continue(());
+        }
+        if ends_in_time(end) {
+            f(start.clone()..=end.clone(), value)?;
+        }
+    }
+    match node.children().last() {
+        Some(child) => visit_node(child, starts_in_time, ends_in_time, f),
+        None => ControlFlow::Continue(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::test_util::XorShift;
+
+    /// 各ノードの最大値が、部分木を数え直した値と一致するか調べ、部分木の最大値を返す
+    fn check_max_end(node: &IntervalNode<i32, i32>) -> Option<i32> {
+        let max = node
+            .keys()
+            .iter()
+            .map(|&(_, end)| Some(end))
+            .chain(node.children().iter().map(|child| check_max_end(child)))
+            .max()
+            .flatten();
+        assert_eq!(node.summary().0, max);
+        max
+    }
+
+    fn intervals(found: Vec<(RangeInclusive<i32>, &i32)>) -> Vec<RangeInclusive<i32>> {
+        found.into_iter().map(|(interval, _)| interval).collect()
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut tree = IntervalTree::new(3);
+        for (i, interval) in [0..=10, 5..=6, 8..=20, 12..=14, 15..=15, 30..=40]
+            .into_iter()
+            .enumerate()
+        {
+            tree.insert(interval, i as i32);
+        }
+        assert_eq!(
+            intervals(tree.overlapping(6..=12)),
+            vec![0..=10, 5..=6, 8..=20, 12..=14]
+        );
+        assert_eq!(
+            intervals(tree.overlapping(6..12)),
+            vec![0..=10, 5..=6, 8..=20]
+        );
+        assert_eq!(
+            intervals(tree.overlapping(21..30)),
+            Vec::<RangeInclusive<i32>>::new()
+        );
+        assert_eq!(intervals(tree.overlapping(35..)), vec![30..=40]);
+        assert_eq!(intervals(tree.containing(&15)), vec![8..=20, 15..=15]);
+        assert_eq!(tree.stab(&15), Some((8..=20, &2)));
+        assert_eq!(tree.stab(&25), None);
+        assert_eq!(tree.get(&(12..=14)), Some(3));
+
+        tree.delete(&(8..=20));
+        assert_eq!(intervals(tree.containing(&15)), vec![15..=15]);
+        assert_eq!(tree.get(&(8..=20)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "interval start must not be after its end")]
+    fn test_reversed_interval() {
+        let mut tree = IntervalTree::new(3);
+        #[allow(clippy::reversed_empty_ranges)]
+        tree.insert(5..=1, ());
+    }
+
+    #[test]
+    #[should_panic(expected = "max_count must be at least 3")]
+    fn test_max_count_too_small() {
+        IntervalTree::<i32, ()>::new(2);
+    }
+
+    #[test]
+    fn test_random_operations() {
+        let mut tree = IntervalTree::new(4);
+        let mut expected = std::collections::BTreeMap::new();
+        let mut rng = XorShift::default();
+        for _ in 0..3000 {
+            let seed = rng.next_u64();
+            let start = (seed % 200) as i32;
+            let end = start + ((seed >> 8) % 30) as i32;
+            if seed.is_multiple_of(3) {
+                tree.delete(&(start..=end));
+                expected.remove(&(start, end));
+            } else {
+                tree.insert(start..=end, start * end);
+                expected.insert((start, end), start * end);
+            }
+            if let Some(root) = &tree.root {
+                check_max_end(root);
+            }
+
+            let (low, high) = (((seed >> 16) % 230) as i32, ((seed >> 24) % 20) as i32);
+            let want: Vec<_> = expected
+                .iter()
+                .filter(|&(&(start, end), _)| start < low + high && end >= low)
+                .map(|(&(start, end), value)| (start..=end, value))
+                .collect();
+            assert_eq!(tree.overlapping(low..low + high), want);
+            assert_eq!(
+                tree.stab(&low),
+                expected
+                    .iter()
+                    .find(|&(&(start, end), _)| start <= low && low <= end)
+                    .map(|(&(start, end), value)| (start..=end, value))
+            );
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::btree::BinarySearch;
+use crate::btree::node::BtreeNode;
+use std::ops::{Bound, RangeBounds};
+
+/// キーの昇順にエントリをたどるイテレータ
+///
+/// スタックの各要素は、ノードと次に返すエントリの位置を表す。
+/// その位置より左の子ノードはすでにたどり終えているか、スタックの上に積まれている。
+pub struct Range<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    stack: Vec<(&'a BtreeNode<K, V>, usize)>,
+    end: Bound<K>,
+}
+
+impl<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Range<'a, K, V> {
+    pub(crate) fn new<R: RangeBounds<K>>(root: Option<&'a BtreeNode<K, V>>, range: R) -> Self {
+        let mut iter = Range {
+            stack: vec![],
+            end: range.end_bound().cloned(),
+        };
+        if let Some(root) = root {
+            iter.seek(root, range.start_bound());
+        }
+        iter
+    }
+
+    /// `is_before`が真になるキーを読み飛ばし、それ以降のエントリを終わりまでたどる
+    ///
+    /// `is_before`は、キーの昇順に真が続いた後に偽が続くものでなければならない。
+    pub(crate) fn starting_after(
+        root: Option<&'a BtreeNode<K, V>>,
+        is_before: impl Fn(&K) -> bool,
+    ) -> Self {
+        let mut iter = Range {
+            stack: vec![],
+            end: Bound::Unbounded,
+        };
+        let mut node = root;
+        while let Some(current) = node {
+            let index = current.keys().partition_point(&is_before);
+            iter.stack.push((current, index));
+            node = current.children().get(index).map(|child| &**child);
+        }
+        iter
+    }
+
+    /// 開始位置より前のエントリを読み飛ばしながら、最初に返すエントリまで降りる
+    fn seek(&mut self, root: &'a BtreeNode<K, V>, start: Bound<&K>) {
+        let mut node = root;
+        loop {
+            let index = match start {
+                Bound::Unbounded => 0,
+                Bound::Included(key) => match node.keys().binary_lookup(key) {
+                    Ok(i) => {
+                        // 開始キーそのものから始まるので、左の子は読まない
+                        self.stack.push((node, i));
+                        return;
+                    }
+                    Err(i) => i,
+                },
+                Bound::Excluded(key) => match node.keys().binary_lookup(key) {
+                    Ok(i) => {
+                        // 開始キーの右の部分木はすべて範囲に含まれる
+                        self.stack.push((node, i + 1));
+                        if let Some(child) = node.children().get(i + 1) {
+                            self.push_leftmost(child);
+                        }
+                        return;
+                    }
+                    Err(i) => i,
+                },
+            };
+            self.stack.push((node, index));
+            match node.children().get(index) {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+    }
+
+    fn push_leftmost(&mut self, node: &'a BtreeNode<K, V>) {
+        let mut node = node;
+        loop {
+            self.stack.push((node, 0));
+            match node.children().first() {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+    }
+
+    fn is_past_end(&self, key: &K) -> bool {
+        match &self.end {
+            Bound::Unbounded => false,
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+        }
+    }
+}
+
+impl<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Iterator
+    for Range<'a, K, V>
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, index) = self.stack.last_mut()?;
+            let node: &'a BtreeNode<K, V> = node;
+            if *index >= node.keys().len() {
+                self.stack.pop();
+                continue;
+            }
+            let current = *index;
+            *index += 1;
+
+            let key = &node.keys()[current];
+            if self.is_past_end(key) {
+                self.stack.clear();
+                return None;
+            }
+            if let Some(child) = node.children().get(current + 1) {
+                self.push_leftmost(child);
+            }
+            return Some((key, &node.values()[current]));
+        }
+    }
+}
@@ -1,34 +1,65 @@
+pub(crate) mod arena;
+pub(crate) mod blink;
+pub(crate) mod bounded;
+pub(crate) mod bplus;
+#[cfg(feature = "server")]
+pub(crate) mod client;
+pub(crate) mod composite;
+pub(crate) mod concurrent;
+pub(crate) mod diff;
+pub(crate) mod fallible;
+pub(crate) mod history;
+pub(crate) mod index;
+pub(crate) mod inline;
+pub(crate) mod interval;
+pub(crate) mod iter;
+pub(crate) mod mvcc;
 mod node;
+pub(crate) mod observe;
+pub(crate) mod persist;
+pub(crate) mod prefix;
+pub(crate) mod render;
+#[cfg(feature = "server")]
+pub(crate) mod resp;
+#[cfg(feature = "server")]
+pub(crate) mod server;
+mod simd;
+pub(crate) mod stats;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub(crate) mod transaction;
 pub(crate) mod tree;
+pub(crate) mod ttl;
+pub(crate) mod verify;
 
-pub(crate) trait Search<K, V> {
+pub trait Search<K, V> {
     fn search(&self, key: &K) -> Option<(K, V)>;
 }
 
-pub(crate) trait Insert<K, V> {
+pub trait Insert<K, V> {
     fn insert(&mut self, key: K, value: V);
 }
 
-pub(crate) trait Delete<K> {
+pub trait Delete<K> {
     fn delete(&mut self, key: &K);
 }
 
-pub(crate) trait Merge {
-    fn merge(self, other: Self) -> Self;
-}
-
-impl<T: Merge> Merge for Box<T> {
-    fn merge(self, other: Self) -> Self {
-        Box::new((*self).merge(*other))
-    }
-}
-
 pub(crate) trait BinarySearch<T> {
     fn binary_lookup(&self, key: &T) -> Result<usize, usize>;
 }
 
 impl<T: 'static + PartialEq + PartialOrd> BinarySearch<T> for Vec<T> {
     fn binary_lookup(&self, key: &T) -> Result<usize, usize> {
+        self.as_slice().binary_lookup(key)
+    }
+}
+
+impl<T: 'static + PartialEq + PartialOrd> BinarySearch<T> for [T] {
+    fn binary_lookup(&self, key: &T) -> Result<usize, usize> {
+        // 整数のキーは複数のキーをまとめて比較する
+        if let Some(result) = simd::lookup_integer(self, key) {
+            return result;
+        }
         for (index, node) in self.iter().enumerate() {
             if key == node {
                 return Ok(index);
@@ -0,0 +1,401 @@
+use crate::btree::tree::Btree;
+use crate::btree::{Delete, Insert, Search};
+use std::collections::VecDeque;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, Mutex};
+
+/// 範囲イテレータが一度にロックを取って読み出すエントリ数
+const SCAN_BATCH_SIZE: usize = 64;
+
+/// ある時点で書き込まれた値（`None`は削除を表す）
+#[derive(Clone)]
+struct Version<V: 'static + Clone> {
+    timestamp: u64,
+    value: Option<V>,
+}
+
+struct MvccState<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    /// キーごとに古い順に並んだ版
+    tree: Btree<K, Vec<Version<V>>>,
+    clock: u64,
+    /// 有効なスナップショットのタイムスタンプと、その参照数
+    snapshots: Btree<u64, usize>,
+}
+
+/// 版付きの値を持つB木
+///
+/// 書き込みのたびに時刻を1つ進め、値を上書きせずに新しい版として追加する。
+/// `snapshot`で取得した読み込みビューは、それ以降の書き込みの影響を受けない。
+/// どのスナップショットからも見えなくなった古い版は、同じキーへの書き込み時か`gc`で削除される。
+pub struct MvccBtree<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    state: Arc<Mutex<MvccState<K, V>>>,
+}
+
+/// `MvccBtree::snapshot`を呼んだ時点の読み込みビュー
+pub struct Snapshot<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    state: Arc<Mutex<MvccState<K, V>>>,
+    timestamp: u64,
+}
+
+/// スナップショットに対する範囲イテレータ
+///
+/// ロックは数十件ずつ読み出す間だけ保持するので、走査が長くかかっても書き込みを妨げない。
+pub struct SnapshotRange<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    state: Arc<Mutex<MvccState<K, V>>>,
+    timestamp: u64,
+    start: Bound<K>,
+    end: Bound<K>,
+    buffer: VecDeque<(K, V)>,
+    finished: bool,
+}
+
+/// `timestamp`の時点で見える値
+fn visible_at<V: 'static + Clone>(versions: &[Version<V>], timestamp: u64) -> Option<&V> {
+    versions
+        .iter()
+        .rev()
+        .find(|version| version.timestamp <= timestamp)
+        .and_then(|version| version.value.as_ref())
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> MvccState<K, V> {
+    /// 最も古いスナップショットの時刻（スナップショットがなければ現在時刻）
+    fn watermark(&self) -> u64 {
+        self.snapshots
+            .iter()
+            .next()
+            .map_or(self.clock, |(timestamp, _)| *timestamp)
+    }
+
+    fn retain_snapshot(&mut self, timestamp: u64) {
+        let count = self.snapshots.search(&timestamp).map_or(0, |(_, c)| c);
+        self.snapshots.insert(timestamp, count + 1);
+    }
+
+    fn release_snapshot(&mut self, timestamp: u64) {
+        match self.snapshots.search(&timestamp) {
+            Some((_, count)) if count > 1 => self.snapshots.insert(timestamp, count - 1),
+            _ => self.snapshots.delete(&timestamp),
+        }
+    }
+
+    fn write(&mut self, key: K, value: Option<V>) {
+        self.clock += 1;
+        let version = Version {
+            timestamp: self.clock,
+            value,
+        };
+        let mut versions = self
+            .tree
+            .search(&key)
+            .map(|(_, versions)| versions)
+            .unwrap_or_default();
+        versions.push(version);
+        self.store(key, versions);
+    }
+
+    /// 不要な版を取り除いてから書き戻す
+    fn store(&mut self, key: K, mut versions: Vec<Version<V>>) {
+        let watermark = self.watermark();
+        // watermark以前の版は、その中で最新のもの以外どのスナップショットからも見えない
+        let visible = versions
+            .iter()
+            .rposition(|version| version.timestamp <= watermark);
+        if let Some(index) = visible {
+            versions.drain(..index);
+            if versions.len() == 1 && versions[0].value.is_none() {
+                versions.clear();
+            }
+        }
+
+        if versions.is_empty() {
+            self.tree.delete(&key);
+        } else {
+            self.tree.insert(key, versions);
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> MvccBtree<K, V> {
+    pub fn new(max_count: usize) -> Self {
+        MvccBtree {
+            state: Arc::new(Mutex::new(MvccState {
+                tree: Btree::new(max_count),
+                clock: 0,
+                snapshots: Btree::new(max_count),
+            })),
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        self.state.lock().unwrap().write(key, Some(value));
+    }
+
+    pub fn delete(&self, key: &K) {
+        let mut state = self.state.lock().unwrap();
+        if state.tree.get(key).is_some() {
+            state.write(key.clone(), None);
+        }
+    }
+
+    /// 最新の値を読む
+    pub fn search(&self, key: &K) -> Option<(K, V)> {
+        let state = self.state.lock().unwrap();
+        let value = visible_at(state.tree.get(key)?, u64::MAX)?.clone();
+        Some((key.clone(), value))
+    }
+
+    /// 現時点の読み込みビューを作る
+    pub fn snapshot(&self) -> Snapshot<K, V> {
+        let mut state = self.state.lock().unwrap();
+        let timestamp = state.clock;
+        state.retain_snapshot(timestamp);
+        Snapshot {
+            state: Arc::clone(&self.state),
+            timestamp,
+        }
+    }
+
+    /// どのスナップショットからも見えない版を、すべてのキーについて取り除く
+    pub fn gc(&self) {
+        let mut state = self.state.lock().unwrap();
+        let entries: Vec<(K, Vec<Version<V>>)> = state
+            .tree
+            .iter()
+            .map(|(key, versions)| (key.clone(), versions.clone()))
+            .collect();
+        for (key, versions) in entries {
+            state.store(key, versions);
+        }
+    }
+
+    /// 保持している版の総数
+    pub fn version_count(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.tree.iter().map(|(_, versions)| versions.len()).sum()
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Snapshot<K, V> {
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn search(&self, key: &K) -> Option<(K, V)> {
+        let state = self.state.lock().unwrap();
+        let value = visible_at(state.tree.get(key)?, self.timestamp)?.clone();
+        Some((key.clone(), value))
+    }
+
+    pub fn iter(&self) -> SnapshotRange<K, V> {
+        self.range(..)
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> SnapshotRange<K, V> {
+        // 範囲イテレータもスナップショットと同じ時刻の版を参照するので、参照数を増やしておく
+        self.state.lock().unwrap().retain_snapshot(self.timestamp);
+        SnapshotRange {
+            state: Arc::clone(&self.state),
+            timestamp: self.timestamp,
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+            buffer: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Drop for Snapshot<K, V> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.release_snapshot(self.timestamp);
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Drop for SnapshotRange<K, V> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.release_snapshot(self.timestamp);
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> SnapshotRange<K, V> {
+    fn fill_buffer(&mut self) {
+        let state = self.state.lock().unwrap();
+        let range = state.tree.range((self.start.clone(), self.end.clone()));
+        let mut scanned = 0;
+        for (key, versions) in range {
+            scanned += 1;
+            self.start = Bound::Excluded(key.clone());
+            if let Some(value) = visible_at(versions, self.timestamp) {
+                self.buffer.push_back((key.clone(), value.clone()));
+            }
+            if scanned == SCAN_BATCH_SIZE {
+                return;
+            }
+        }
+        self.finished = true;
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Iterator
+    for SnapshotRange<K, V>
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.is_empty() && !self.finished {
+            self.fill_buffer();
+        }
+        self.buffer.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_insert_and_search() {
+        let tree = MvccBtree::new(3);
+        tree.insert(10, 100);
+        tree.insert(20, 200);
+        tree.insert(10, 101);
+        assert_eq!(tree.search(&10), Some((10, 101)));
+        assert_eq!(tree.search(&20), Some((20, 200)));
+        tree.delete(&20);
+        assert_eq!(tree.search(&20), None);
+    }
+
+    #[test]
+    fn test_snapshot_ignores_later_writes() {
+        let tree = MvccBtree::new(3);
+        for i in 0..10 {
+            tree.insert(i, i * 10);
+        }
+        let snapshot = tree.snapshot();
+
+        // スナップショット後の更新・削除・挿入
+        tree.insert(3, 333);
+        tree.delete(&5);
+        tree.insert(100, 1000);
+
+        assert_eq!(snapshot.search(&3), Some((3, 30)));
+        assert_eq!(snapshot.search(&5), Some((5, 50)));
+        assert_eq!(snapshot.search(&100), None);
+        let entries: Vec<(i32, i32)> = snapshot.iter().collect();
+        assert_eq!(entries, (0..10).map(|i| (i, i * 10)).collect::<Vec<_>>());
+
+        assert_eq!(tree.search(&3), Some((3, 333)));
+        assert_eq!(tree.search(&5), None);
+        assert_eq!(tree.search(&100), Some((100, 1000)));
+    }
+
+    #[test]
+    fn test_snapshot_range() {
+        let tree = MvccBtree::new(4);
+        for i in 0..200 {
+            tree.insert(i, i);
+        }
+        let snapshot = tree.snapshot();
+        for i in 0..200 {
+            tree.delete(&i);
+        }
+        let keys: Vec<i32> = snapshot.range(50..60).map(|(k, _)| k).collect();
+        assert_eq!(keys, (50..60).collect::<Vec<_>>());
+        assert_eq!(tree.snapshot().iter().next(), None);
+    }
+
+    #[test]
+    fn test_range_stable_while_writing() {
+        let tree = MvccBtree::new(4);
+        for i in 0..500 {
+            tree.insert(i, 0);
+        }
+        let snapshot = tree.snapshot();
+        let mut range = snapshot.iter();
+
+        // 走査の途中で書き込んでも、走査結果は変わらない
+        let mut seen = vec![];
+        for (step, entry) in range.by_ref().take(100).enumerate() {
+            seen.push(entry);
+            tree.insert(step as i32 + 200, 1);
+            tree.delete(&(step as i32 + 300));
+        }
+        seen.extend(range);
+        assert_eq!(seen, (0..500).map(|i| (i, 0)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_gc_keeps_versions_visible_to_snapshots() {
+        let tree = MvccBtree::new(3);
+        tree.insert(1, 10);
+        let first = tree.snapshot();
+        tree.insert(1, 11);
+        let second = tree.snapshot();
+        tree.insert(1, 12);
+        tree.gc();
+        assert_eq!(tree.version_count(), 3);
+
+        drop(first);
+        tree.gc();
+        assert_eq!(tree.version_count(), 2);
+        assert_eq!(second.search(&1), Some((1, 11)));
+
+        drop(second);
+        tree.gc();
+        assert_eq!(tree.version_count(), 1);
+        assert_eq!(tree.search(&1), Some((1, 12)));
+
+        // 削除済みのキーは、見えるスナップショットがなくなれば版ごと消える
+        tree.delete(&1);
+        assert_eq!(tree.version_count(), 0);
+    }
+
+    #[test]
+    fn test_gc_waits_for_open_range() {
+        let tree = MvccBtree::new(3);
+        tree.insert(1, 10);
+        let snapshot = tree.snapshot();
+        let mut range = snapshot.iter();
+        drop(snapshot);
+
+        tree.insert(1, 11);
+        tree.gc();
+        assert_eq!(range.next(), Some((1, 10)));
+        drop(range);
+        tree.gc();
+        assert_eq!(tree.version_count(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_scan_and_writes() {
+        let tree = MvccBtree::new(8);
+        for i in 0..1000 {
+            tree.insert(i, 0);
+        }
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for round in 1..20 {
+                    for i in 0..1000 {
+                        tree.insert(i, round);
+                    }
+                }
+            });
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..5 {
+                        // 1回の走査で見える値は全て同じ時点のもの
+                        let snapshot = tree.snapshot();
+                        let values: Vec<i32> = snapshot.iter().map(|(_, v)| v).collect();
+                        assert_eq!(values.len(), 1000);
+                        assert!(values.windows(2).all(|w| w[0] >= w[1]));
+                    }
+                });
+            }
+        });
+    }
+}
@@ -1,54 +1,93 @@
-use crate::btree::{BinarySearch, Delete, Insert, Merge, Search};
+use crate::btree::fallible::{AllocError, try_box};
+use crate::btree::stats::{MemoryUsage, RestructureCounts};
+use crate::btree::{BinarySearch, Search};
+
+/// ノードごとに、部分木全体から計算して持っておく値
+///
+/// エントリや子ノードが変わるたびに、変わったノードで子ノードの値から計算し直される。
+pub(crate) trait Summary<K: 'static + Clone, V: 'static + Clone>: 'static + Clone {
+    #[allow(clippy::vec_box)]
+    fn summarize(keys: &[K], values: &[V], children: &[Box<BtreeNode<K, V, Self>>]) -> Self;
+}
 
-#[derive(Clone)]
-pub(crate) struct BtreeNode<K: 'static + Clone, V: 'static + Clone> {
-    keys: Vec<K>,
-    values: Vec<V>,
-    children: Vec<Box<BtreeNode<K, V>>>,
-    max_count: usize,
+/// 何も持たない（通常の`Btree`が使う）
+impl<K: 'static + Clone, V: 'static + Clone> Summary<K, V> for () {
+    fn summarize(_: &[K], _: &[V], _: &[Box<BtreeNode<K, V, Self>>]) -> Self {}
 }
 
+/// B木のノード
+///
+/// ノードの最大要素数は持たず、挿入や削除のたびに木から渡される。
 #[derive(Clone)]
-pub(crate) struct BtreeNodeEntry<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+#[allow(clippy::vec_box)]
+pub(crate) struct BtreeNode<K: 'static + Clone, V: 'static + Clone, S = ()> {
     keys: Vec<K>,
     values: Vec<V>,
+    children: Vec<Box<BtreeNode<K, V, S>>>,
+    summary: S,
 }
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BtreeNode<K, V> {
-    pub(crate) fn new(max_count: usize) -> Self {
-        Self {
-            keys: vec![],
-            values: vec![],
-            children: vec![],
-            max_count,
-        }
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, S: Summary<K, V>>
+    BtreeNode<K, V, S>
+{
+    pub(crate) fn new() -> Self {
+        Self::from(vec![], vec![], vec![])
     }
 
-    pub(crate) fn from(
-        keys: Vec<K>,
-        values: Vec<V>,
-        children: Vec<Box<BtreeNode<K, V>>>,
-        max_count: usize,
-    ) -> Self {
+    #[allow(clippy::vec_box)]
+    pub(crate) fn from(keys: Vec<K>, values: Vec<V>, children: Vec<Box<Self>>) -> Self {
+        let summary = S::summarize(&keys, &values, &children);
         Self {
             keys,
             values,
             children,
-            max_count,
+            summary,
         }
     }
 }
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BtreeNode<K, V> {
-    fn is_leaf(&self) -> bool {
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, S: Summary<K, V>>
+    BtreeNode<K, V, S>
+{
+    pub(crate) fn is_leaf(&self) -> bool {
         self.children.is_empty()
     }
 
+    pub(crate) fn keys(&self) -> &Vec<K> {
+        &self.keys
+    }
+
+    pub(crate) fn values(&self) -> &[V] {
+        &self.values
+    }
+
+    pub(crate) fn children(&self) -> &[Box<Self>] {
+        &self.children
+    }
+
+    pub(crate) fn summary(&self) -> &S {
+        &self.summary
+    }
+
+    fn update_summary(&mut self) {
+        self.summary = S::summarize(&self.keys, &self.values, &self.children);
+    }
+
+    /// このノードが確保しているヒープ上の領域（ノード本体を含む）
+    pub(crate) fn memory(&self) -> MemoryUsage {
+        MemoryUsage {
+            keys: self.keys.capacity() * std::mem::size_of::<K>(),
+            values: self.values.capacity() * std::mem::size_of::<V>(),
+            nodes: std::mem::size_of::<Self>()
+                + self.children.capacity() * std::mem::size_of::<Box<Self>>(),
+        }
+    }
+
     fn current_count(&self) -> usize {
         self.keys.len()
     }
-    fn min_count(&self) -> usize {
-        self.max_count / 2
+    pub(crate) fn min_count(max_count: usize) -> usize {
+        (max_count - 1) / 2
     }
 
     fn push_kv(&mut self, pair: (K, V)) {
@@ -63,9 +102,9 @@ impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BtreeNode<
         self.values.insert(index, value);
     }
 
-    fn remove_head_entry(&mut self) -> (K, V) {
-        let key = self.keys.remove(0);
-        let value = self.values.remove(0);
+    fn remove_entry(&mut self, index: usize) -> (K, V) {
+        let key = self.keys.remove(index);
+        let value = self.values.remove(index);
         (key, value)
     }
 
@@ -79,61 +118,59 @@ impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BtreeNode<
         self.keys.is_empty()
     }
 
-    pub(crate) fn is_full(&self) -> bool {
-        self.keys.len() >= self.max_count
+    pub(crate) fn is_full(&self, max_count: usize) -> bool {
+        self.keys.len() >= max_count
     }
 
-    pub(crate) fn is_more_than_min_count(&self) -> bool {
-        self.current_count() > self.min_count()
+    /// エントリを持たない内部ノードを、唯一の子ノードで置き換える
+    pub(crate) fn into_only_child(self) -> Option<Self> {
+        if !self.is_empty() || self.children.len() != 1 {
+            return None;
+        }
+        self.children.into_iter().next().map(|child| *child)
     }
 
-    pub(crate) fn has_key(&self, key: &K) -> bool {
-        self.keys.contains(&key)
+    pub(crate) fn is_more_than_min_count(&self, max_count: usize) -> bool {
+        self.current_count() > Self::min_count(max_count)
     }
 
-    pub(crate) fn split_node(&self) -> ((K, V), (BtreeNode<K, V>, BtreeNode<K, V>)) {
+    /// 後半のエントリと子ノードを`right`に移し、中央のエントリと`right`を返す
+    ///
+    /// このノードは前半だけを持つ左側のノードになる。
+    fn split_into(&mut self, mut right: Box<Self>) -> ((K, V), Box<Self>) {
         let mid_index = self.keys.len() / 2;
-        let split = {
-            let left = BtreeNode::from(
-                self.keys[..mid_index].to_vec(),
-                self.values[..mid_index].to_vec(),
-                if self.children.is_empty() {
-                    vec![]
-                } else {
-                    self.children[..=mid_index].to_vec()
-                },
-                self.max_count,
-            );
-            let right = BtreeNode::from(
-                self.keys[mid_index + 1..].to_vec(),
-                self.values[mid_index + 1..].to_vec(),
-                if self.children.is_empty() {
-                    vec![]
-                } else {
-                    self.children[mid_index + 1..].to_vec()
-                },
-                self.max_count,
-            );
-            (left, right)
-        };
-        let key = self.keys[mid_index].clone();
-        let value = self.values[mid_index].clone();
-        ((key, value), split)
+        right.keys.extend(self.keys.drain(mid_index + 1..));
+        right.values.extend(self.values.drain(mid_index + 1..));
+        if !self.is_leaf() {
+            right.children.extend(self.children.drain(mid_index + 1..));
+        }
+        let entry = self.remove_tail_entry();
+        self.update_summary();
+        right.update_summary();
+        (entry, right)
     }
 
-    pub(crate) fn entry(self) -> BtreeNodeEntry<K, V> {
-        BtreeNodeEntry {
-            keys: self.keys,
-            values: self.values,
-        }
+    /// 分割した根の左右を子ノードに持つ、新しい根を作る
+    ///
+    /// `new_node`は左側・右側・新しい根の順に呼ばれる。
+    pub(crate) fn split_root(self, new_node: &mut impl FnMut() -> Box<Self>) -> Self {
+        let mut left = new_node();
+        *left = self;
+        let (entry, right) = left.split_into(new_node());
+        let mut root = new_node();
+        root.push_kv(entry);
+        root.children.push(left);
+        root.children.push(right);
+        root.update_summary();
+        *root
     }
 }
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Search<K, V>
-    for BtreeNode<K, V>
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, S: Summary<K, V>> Search<K, V>
+    for BtreeNode<K, V, S>
 {
     fn search(&self, key: &K) -> Option<(K, V)> {
-        match self.keys.binary_lookup(&key) {
+        match self.keys.binary_lookup(key) {
             // key found in this node
             Ok(i) => Some((self.keys[i].clone(), self.values[i].clone())),
             // key not found, recurse into appropriate child node
@@ -148,34 +185,20 @@ impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Search<K,
     }
 }
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BtreeNode<K, V> {
-    fn pop_min(&mut self) -> Option<(K, V)> {
-        if !self.is_leaf() {
-            return self.children[0].pop_min();
-        }
-        if self.is_more_than_min_count() {
-            let entry = self.remove_head_entry();
-            return Some(entry);
-        }
-        None
-    }
-    fn pop_max(&mut self) -> Option<(K, V)> {
-        if !self.is_leaf() {
-            return self.children[0].pop_max();
-        }
-
-        if self.is_more_than_min_count() {
-            let entry = self.remove_tail_entry();
-            return Some(entry);
-        }
-        None
-    }
-}
-
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Insert<K, V>
-    for BtreeNode<K, V>
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, S: Summary<K, V>>
+    BtreeNode<K, V, S>
 {
-    fn insert(&mut self, key: K, value: V) {
+    /// `key`を挿入し、いっぱいになった子ノードを分割する
+    ///
+    /// 分割で右側になるノードは`new_node`から受け取る。
+    pub(crate) fn insert(
+        &mut self,
+        key: K,
+        value: V,
+        max_count: usize,
+        counts: &mut RestructureCounts,
+        new_node: &mut impl FnMut() -> Box<Self>,
+    ) {
         match self.keys.binary_lookup(&key) {
             Ok(i) => {
                 self.keys[i] = key;
@@ -186,281 +209,331 @@ impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Insert<K,
                     self.keys.insert(i, key);
                     self.values.insert(i, value);
                 } else {
-                    self.children[i].insert(key, value);
-
-                    if self.children[i].is_full() {
-                        let ((key, value), (left, right)) = self.children[i].split_node();
+                    self.children[i].insert(key, value, max_count, counts, new_node);
 
-                        self.keys.insert(i, key);
-                        self.values.insert(i, value);
+                    if self.children[i].is_full(max_count) {
+                        let (entry, right) = self.children[i].split_into(new_node());
+                        counts.splits += 1;
 
-                        self.children[i] = Box::new(left);
-                        self.children.insert(i + 1, Box::new(right));
+                        self.insert_entry(i, entry);
+                        self.children.insert(i + 1, right);
                     }
                 }
             }
         }
+        self.update_summary();
     }
-}
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Delete<K>
-    for BtreeNode<K, V>
-{
-    fn delete(&mut self, key: &K) {
-        match self.keys.binary_lookup(&key) {
+    /// `key`の挿入で増える要素の領域を挿入経路上のノードに確保し、
+    /// 分割で右側になるノードを分割する順に`spares`へ追加する
+    ///
+    /// このノード自身が分割されるかを返す。失敗しても木の内容は変わらない。
+    #[allow(clippy::vec_box)]
+    pub(crate) fn try_reserve_insert(
+        &mut self,
+        key: &K,
+        max_count: usize,
+        spares: &mut Vec<Box<Self>>,
+    ) -> Result<bool, AllocError> {
+        let i = match self.keys.binary_lookup(key) {
+            // 上書きは領域を使わない
+            Ok(_) => return Ok(false),
+            Err(i) => i,
+        };
+        if !self.is_leaf() {
+            if !self.children[i].try_reserve_insert(key, max_count, spares)? {
+                return Ok(false);
+            }
+            spares.try_reserve(1)?;
+            spares.push(self.children[i].try_spare()?);
+            self.children.try_reserve(1)?;
+        }
+        self.keys.try_reserve(1)?;
+        self.values.try_reserve(1)?;
+        Ok(self.keys.len() + 1 >= max_count)
+    }
+
+    /// 要素が1つ増えてから分割されたときに右側になるノードを、必要な容量を確保して作る
+    pub(crate) fn try_spare(&self) -> Result<Box<Self>, AllocError> {
+        let count = self.keys.len() - self.keys.len().div_ceil(2);
+        Self::try_node(count, if self.is_leaf() { 0 } else { count + 1 })
+    }
+
+    /// 指定した容量を確保した空のノードを作る
+    pub(crate) fn try_node(keys: usize, children: usize) -> Result<Box<Self>, AllocError> {
+        let mut node = Self::new();
+        node.keys.try_reserve_exact(keys)?;
+        node.values.try_reserve_exact(keys)?;
+        node.children.try_reserve_exact(children)?;
+        try_box(node)
+    }
+
+    pub(crate) fn delete(&mut self, key: &K, max_count: usize, counts: &mut RestructureCounts) {
+        match self.keys.binary_lookup(key) {
             Ok(i) => {
                 if self.is_leaf() {
                     // 葉ノードの場合はそのまま削除
                     self.keys.remove(i);
                     self.values.remove(i);
                 } else {
-                    // 内部ノードの場合
-                    let operation = self.resolve_delete_from_self_operation();
-                    self.apply_delete_from_self_operation(i, operation);
+                    // 内部ノードの場合は、左の部分木の最大のエントリで置き換える
+                    let (key, value) = self.children[i].pop_max(max_count, counts);
+                    self.keys[i] = key;
+                    self.values[i] = value;
+                    self.rebalance_child(i, max_count, counts);
                 }
             }
             Err(i) => {
-                let operation = self.get_delete_from_child_operation(&key, i);
-                self.apply_delete_from_child_operation(key, i, operation);
+                if self.is_leaf() {
+                    // 葉ノードにkeyが存在しない
+                    return;
+                }
+                self.children[i].delete(key, max_count, counts);
+                self.rebalance_child(i, max_count, counts);
             }
         }
+        self.update_summary();
     }
 }
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Merge for BtreeNode<K, V> {
-    fn merge(self, other: Self) -> Self {
-        let keys = [self.keys, other.keys].concat();
-        let values = [self.values, other.values].concat();
-        let children = [self.children, other.children].concat();
-        let max_count = self.max_count;
-        Self {
-            keys,
-            values,
-            children,
-            max_count,
-        }
-    }
-}
-
-enum DeleteFromSelfOperation<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
-    Replace((K, V)),
-    Merge,
-    MergeToSelf,
-}
-enum DeleteFromChildOperation {
+/// 削除で最小数を下回った子ノードを補う方法
+pub(crate) enum DeleteFromChildOperation {
     None,
-    Delegate,
     RotateLeft,
     RotateRight,
     MergeToLeft,
     MergeToRight,
-    MergeToSelf,
 }
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BtreeNode<K, V> {
-    fn resolve_delete_from_self_operation(&mut self) -> DeleteFromSelfOperation<K, V> {
-        if let Some(entry) = self.children[0].pop_max() {
-            // 一番左の部分木が余分な要素を持っている
-            return DeleteFromSelfOperation::Replace(entry);
+impl DeleteFromChildOperation {
+    /// 子ノード`index`を補う方法を、兄弟が余分な要素を持つか（`has_spare`）から決める
+    ///
+    /// 左の兄弟からの回転、右の兄弟からの回転、左との併合、右との併合の順に選ぶ。
+    pub(crate) fn fill(
+        index: usize,
+        children: usize,
+        has_spare: impl Fn(usize) -> bool,
+    ) -> DeleteFromChildOperation {
+        if index > 0 && has_spare(index - 1) {
+            // 一つ左が十分な要素を持っている
+            return DeleteFromChildOperation::RotateRight;
         }
-        if let Some(entry) = self.children[1].pop_min() {
-            // 一番左から、一つ右の部分木が余分な要素を持っている
-            return DeleteFromSelfOperation::Replace(entry);
+        if index + 1 < children && has_spare(index + 1) {
+            // 一つ右が十分な要素を持っている
+            return DeleteFromChildOperation::RotateLeft;
         }
-        if self.current_count() != 1 {
-            // どちらの部分木も余分な要素をもっていない
-            return DeleteFromSelfOperation::Merge;
+        if index > 0 {
+            // どちらの兄弟も余分な要素をもっていないので、一つ左とまとめる
+            return DeleteFromChildOperation::MergeToLeft;
         }
-        // どちらの部分木も余分な要素をもっていないかつ、自身も余分な要素をもっていない
-        DeleteFromSelfOperation::MergeToSelf
+        // 子ノードが一番左なので、一つ右とまとめる
+        DeleteFromChildOperation::MergeToRight
     }
+}
 
-    fn apply_delete_from_self_operation(
-        &mut self,
-        index: usize,
-        operation: DeleteFromSelfOperation<K, V>,
-    ) {
-        match operation {
-            DeleteFromSelfOperation::Replace((key, value)) => {
-                self.keys[index] = key;
-                self.values[index] = value;
-            }
-            DeleteFromSelfOperation::Merge => {
-                self.keys.remove(index);
-                self.values.remove(index);
+/// ノードのキー・値・子ノードを入れる列（`Vec`か`InlineVec`）
+pub(crate) trait NodeVec<T> {
+    fn as_slice(&self) -> &[T];
+    fn push(&mut self, item: T);
+    fn insert(&mut self, index: usize, item: T);
+    fn remove(&mut self, index: usize) -> T;
+    fn pop(&mut self) -> Option<T>;
+    fn append(&mut self, other: &mut Self);
+}
 
-                let merged = {
-                    let left = self.children.remove(index);
-                    let right = self.children.remove(index);
-                    left.merge(right)
-                };
+impl<T> NodeVec<T> for Vec<T> {
+    fn as_slice(&self) -> &[T] {
+        self
+    }
 
-                self.children.insert(index, merged);
-            }
-            DeleteFromSelfOperation::MergeToSelf => {
-                self.keys.remove(index);
-                self.values.remove(index);
-
-                let merged = {
-                    let left = self.children.remove(index);
-                    let right = self.children.remove(index);
-                    left.merge(right)
-                };
-
-                self.keys = merged.keys;
-                self.values = merged.values;
-                self.children = merged.children;
-                self.max_count = merged.max_count;
-            }
-        }
+    fn push(&mut self, item: T) {
+        Vec::push(self, item)
+    }
+
+    fn insert(&mut self, index: usize, item: T) {
+        Vec::insert(self, index, item)
+    }
+
+    fn remove(&mut self, index: usize) -> T {
+        Vec::remove(self, index)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        Vec::pop(self)
+    }
+
+    fn append(&mut self, other: &mut Self) {
+        Vec::append(self, other)
     }
 }
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BtreeNode<K, V> {
-    fn get_delete_from_child_operation(&self, key: &K, index: usize) -> DeleteFromChildOperation {
-        if self.is_leaf() {
-            // 葉ノードにkeyが存在しない
-            return DeleteFromChildOperation::None;
-        }
-        if !self.children[index].is_leaf() {
-            // 子ノードが内部ノード
-            return DeleteFromChildOperation::Delegate;
-        }
+/// 回転と併合で、兄弟の間でエントリと子ノードを移せるノード
+///
+/// 列の型や子ノードの持ち方（`Box`、ラッチ、配列上の位置）はノードの種類ごとに違うが、移し方は同じなので
+/// `rotate_right`・`rotate_left`・`merge_into`を共有する。
+pub(crate) trait NodeStorage {
+    type Key;
+    type Value;
+    type Child;
+    type Keys: NodeVec<Self::Key>;
+    type Values: NodeVec<Self::Value>;
+    type Children: NodeVec<Self::Child>;
+
+    fn parts(&self) -> (&Self::Keys, &Self::Values, &Self::Children);
+    fn parts_mut(&mut self) -> (&mut Self::Keys, &mut Self::Values, &mut Self::Children);
+
+    fn entry_count(&self) -> usize {
+        self.parts().0.as_slice().len()
+    }
+}
 
-        if !self.children[index].has_key(key) {
-            // 子ノードが葉ノードかつ、keyが存在しない
-            return DeleteFromChildOperation::None;
-        }
+/// 左の兄弟`left`の最後のエントリを親の区切り`separator`に上げ、元の区切りを`child`の先頭に下ろす
+pub(crate) fn rotate_right<N: NodeStorage>(
+    separator: (&mut N::Key, &mut N::Value),
+    left: &mut N,
+    child: &mut N,
+) {
+    let (left_keys, left_values, left_children) = left.parts_mut();
+    let (keys, values, children) = child.parts_mut();
+    keys.insert(0, std::mem::replace(separator.0, left_keys.pop().unwrap()));
+    values.insert(
+        0,
+        std::mem::replace(separator.1, left_values.pop().unwrap()),
+    );
+    if let Some(grandchild) = left_children.pop() {
+        children.insert(0, grandchild);
+    }
+}
 
-        if self.children[index].is_more_than_min_count() {
-            // 子ノードが葉ノードかつ、minCountより大きいなら再起的に処理する
-            return DeleteFromChildOperation::Delegate;
-        }
-        if index == self.children.len() - 1 && self.children[index - 1].is_more_than_min_count() {
-            // 子ノードが一番右かつ、一つ左が十分な要素を持っている
-            return DeleteFromChildOperation::RotateRight;
-        }
-        if index < self.children.len() - 1 && self.children[index + 1].is_more_than_min_count() {
-            // 子ノードが一番右以外かつ、一つ右が十分な要素を持っている
-            return DeleteFromChildOperation::RotateLeft;
-        }
-        if self.current_count() != 1 && index == self.children.len() - 1 {
-            // 子ノードが一番右かつ、一つ左が十分な要素を持っていない
-            return DeleteFromChildOperation::MergeToLeft;
+/// 右の兄弟`right`の先頭のエントリを親の区切り`separator`に上げ、元の区切りを`child`の末尾に下ろす
+pub(crate) fn rotate_left<N: NodeStorage>(
+    separator: (&mut N::Key, &mut N::Value),
+    child: &mut N,
+    right: &mut N,
+) {
+    let (right_keys, right_values, right_children) = right.parts_mut();
+    let (keys, values, children) = child.parts_mut();
+    keys.push(std::mem::replace(separator.0, right_keys.remove(0)));
+    values.push(std::mem::replace(separator.1, right_values.remove(0)));
+    if !right_children.as_slice().is_empty() {
+        children.push(right_children.remove(0));
+    }
+}
+
+/// 親から外した区切り`separator`と`right`のエントリ・子ノードを、`left`の末尾に移す
+pub(crate) fn merge_into<N: NodeStorage>(
+    left: &mut N,
+    separator: (N::Key, N::Value),
+    right: &mut N,
+) {
+    let (right_keys, right_values, right_children) = right.parts_mut();
+    let (keys, values, children) = left.parts_mut();
+    keys.push(separator.0);
+    values.push(separator.1);
+    keys.append(right_keys);
+    values.append(right_values);
+    children.append(right_children);
+}
+
+impl<K: 'static + Clone, V: 'static + Clone, S> NodeStorage for BtreeNode<K, V, S> {
+    type Key = K;
+    type Value = V;
+    type Child = Box<Self>;
+    type Keys = Vec<K>;
+    type Values = Vec<V>;
+    type Children = Vec<Box<Self>>;
+
+    fn parts(&self) -> (&Vec<K>, &Vec<V>, &Vec<Box<Self>>) {
+        (&self.keys, &self.values, &self.children)
+    }
+
+    fn parts_mut(&mut self) -> (&mut Vec<K>, &mut Vec<V>, &mut Vec<Box<Self>>) {
+        (&mut self.keys, &mut self.values, &mut self.children)
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, S: Summary<K, V>>
+    BtreeNode<K, V, S>
+{
+    /// 部分木から最大のエントリを取り除いて返す
+    fn pop_max(&mut self, max_count: usize, counts: &mut RestructureCounts) -> (K, V) {
+        let entry = if self.is_leaf() {
+            self.remove_tail_entry()
+        } else {
+            let index = self.children.len() - 1;
+            let entry = self.children[index].pop_max(max_count, counts);
+            self.rebalance_child(index, max_count, counts);
+            entry
+        };
+        self.update_summary();
+        entry
+    }
+
+    /// 削除で子ノードが最小数を下回った場合に、兄弟からの回転または併合で補う
+    fn rebalance_child(&mut self, index: usize, max_count: usize, counts: &mut RestructureCounts) {
+        let operation = self.get_delete_from_child_operation(index, max_count);
+        match operation {
+            DeleteFromChildOperation::None => {}
+            DeleteFromChildOperation::RotateLeft | DeleteFromChildOperation::RotateRight => {
+                counts.rotations += 1
+            }
+            DeleteFromChildOperation::MergeToLeft | DeleteFromChildOperation::MergeToRight => {
+                counts.merges += 1
+            }
         }
-        if self.current_count() != 1 && index < self.children.len() - 1 {
-            // 子ノードが一番右以外かつ、一つ右が十分な要素を持っていない
-            return DeleteFromChildOperation::MergeToRight;
+        self.apply_delete_from_child_operation(index, operation);
+    }
+
+    fn get_delete_from_child_operation(
+        &self,
+        index: usize,
+        max_count: usize,
+    ) -> DeleteFromChildOperation {
+        if self.children[index].current_count() >= Self::min_count(max_count) {
+            // 子ノードが十分な要素を持っている
+            return DeleteFromChildOperation::None;
         }
-        // 自身の要素が１
-        DeleteFromChildOperation::MergeToSelf
+        DeleteFromChildOperation::fill(index, self.children.len(), |i| {
+            self.children[i].is_more_than_min_count(max_count)
+        })
     }
 
     fn apply_delete_from_child_operation(
         &mut self,
-        key: &K,
         index: usize,
         operation: DeleteFromChildOperation,
     ) {
         match operation {
             DeleteFromChildOperation::None => {}
-            DeleteFromChildOperation::Delegate => {
-                self.children[index].delete(key);
-            }
             DeleteFromChildOperation::RotateLeft => {
-                self.children[index].delete(key);
-
-                let head = self.remove_head_entry();
-                self.children[index].push_kv(head);
-
-                let head = self.children[index + 1].remove_head_entry();
-                self.insert_entry(0, head);
+                let (left, right) = self.children.split_at_mut(index + 1);
+                let separator = (&mut self.keys[index], &mut self.values[index]);
+                rotate_left(separator, &mut *left[index], &mut *right[0]);
+                self.children[index].update_summary();
+                self.children[index + 1].update_summary();
             }
             DeleteFromChildOperation::RotateRight => {
-                self.children[index].delete(key);
-
-                let tail = self.remove_tail_entry();
-                self.children[index].insert_entry(0, tail);
-
-                let tail = self.children[index - 1].remove_tail_entry();
-                self.push_kv(tail);
+                let (left, right) = self.children.split_at_mut(index);
+                let separator = (&mut self.keys[index - 1], &mut self.values[index - 1]);
+                rotate_right(separator, &mut *left[index - 1], &mut *right[0]);
+                self.children[index - 1].update_summary();
+                self.children[index].update_summary();
             }
             DeleteFromChildOperation::MergeToLeft => {
-                self.children[index].delete(key);
-
-                let tail = self.remove_tail_entry();
-                self.children[index - 1].push_kv(tail);
-
-                if let Some(child) = self.children.pop() {
-                    for entry in child.entry() {
-                        self.children[index - 1].push_kv(entry);
-                    }
-                }
+                self.merge_children(index - 1);
             }
             DeleteFromChildOperation::MergeToRight => {
-                self.children[index].delete(key);
-
-                let head = self.remove_head_entry();
-                self.children[index + 1].insert_entry(0, head);
-
-                let child = self.children.remove(0);
-                for entry in child.entry().rev() {
-                    self.children[index + 1].insert_entry(0, entry);
-                }
-            }
-            DeleteFromChildOperation::MergeToSelf => {
-                let (new_keys, new_values) = {
-                    let mut left = self.children.remove(0);
-                    let mut right = self.children.remove(0);
-
-                    if left.has_key(&key) {
-                        left.delete(key);
-                    }
-                    if right.has_key(&key) {
-                        right.delete(key);
-                    }
-                    let new_keys = {
-                        let mut new_keys = left.keys;
-                        new_keys.extend(self.keys.clone());
-                        new_keys.extend(right.keys);
-                        new_keys
-                    };
-
-                    let new_values = {
-                        let mut new_values = left.values;
-                        new_values.extend(self.values.clone());
-                        new_values.extend(right.values);
-                        new_values
-                    };
-                    (new_keys, new_values)
-                };
-
-                self.keys = new_keys;
-                self.values = new_values;
+                self.merge_children(index);
             }
         }
     }
-}
-
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Iterator
-    for BtreeNodeEntry<K, V>
-{
-    type Item = (K, V);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.keys.is_empty() {
-            return None;
-        }
-        Some((self.keys.remove(0), self.values.remove(0)))
-    }
-}
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> DoubleEndedIterator
-    for BtreeNodeEntry<K, V>
-{
-    fn next_back(&mut self) -> Option<Self::Item> {
-        let key = self.keys.pop()?;
-        let value = self.values.pop()?;
-        Some((key, value))
+    /// 子ノード`index`と`index + 1`を、間のエントリを挟んで一つにまとめる
+    fn merge_children(&mut self, index: usize) {
+        let separator = self.remove_entry(index);
+        let mut right = self.children.remove(index + 1);
+        let left = &mut self.children[index];
+        merge_into(&mut **left, separator, &mut right);
+        left.update_summary();
     }
 }
@@ -0,0 +1,269 @@
+use crate::btree::Search;
+use crate::btree::tree::Btree;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// `insert`や`delete`による変更
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChangeEvent<K, V> {
+    /// 無かったキーが追加された
+    Inserted { key: K, value: V },
+    /// 既にあったキーの値が置き換えられた
+    Updated { key: K, old: V, new: V },
+    /// キーが削除された
+    Removed { key: K, old: V },
+}
+
+impl<K, V> ChangeEvent<K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            ChangeEvent::Inserted { key, .. }
+            | ChangeEvent::Updated { key, .. }
+            | ChangeEvent::Removed { key, .. } => key,
+        }
+    }
+}
+
+/// `Btree::subscribe`が返す、購読を解除するための値
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubscriptionId(u64);
+
+type Callback<K, V> = Mutex<Box<dyn FnMut(&ChangeEvent<K, V>) + Send>>;
+
+enum Sink<K, V> {
+    Callback(Callback<K, V>),
+    Channel(Sender<ChangeEvent<K, V>>),
+}
+
+struct Subscription<K, V> {
+    id: SubscriptionId,
+    start: Bound<K>,
+    end: Bound<K>,
+    sink: Sink<K, V>,
+}
+
+impl<K: PartialOrd, V> Subscription<K, V> {
+    fn contains(&self, key: &K) -> bool {
+        (self.start.as_ref(), self.end.as_ref()).contains(key)
+    }
+}
+
+/// `Btree`の変更を購読している関数とチャネル
+///
+/// 木を複製しても購読は引き継がれない。
+pub(crate) struct Observers<K, V> {
+    subscriptions: Vec<Subscription<K, V>>,
+    next_id: u64,
+}
+
+impl<K, V> Default for Observers<K, V> {
+    fn default() -> Self {
+        Observers {
+            subscriptions: vec![],
+            next_id: 0,
+        }
+    }
+}
+
+impl<K, V> Clone for Observers<K, V> {
+    fn clone(&self) -> Self {
+        Observers::default()
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Observers<K, V> {
+    fn watches(&self, key: &K) -> bool {
+        self.subscriptions.iter().any(|s| s.contains(key))
+    }
+
+    /// 範囲にキーが入る購読者に変更を渡す（受け手のいなくなったチャネルは購読を解除する）
+    fn notify(&mut self, event: &ChangeEvent<K, V>) {
+        self.subscriptions.retain_mut(|subscription| {
+            if !subscription.contains(event.key()) {
+                return true;
+            }
+            match &mut subscription.sink {
+                Sink::Callback(callback) => {
+                    (callback.get_mut().unwrap())(event);
+                    true
+                }
+                Sink::Channel(sender) => sender.send(event.clone()).is_ok(),
+            }
+        });
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    /// キーが`range`に入る変更のたびに、`callback`を書き込みと同じスレッドで呼ぶ
+    pub fn subscribe<R: RangeBounds<K>>(
+        &mut self,
+        range: R,
+        callback: impl FnMut(&ChangeEvent<K, V>) + Send + 'static,
+    ) -> SubscriptionId {
+        self.add_subscription(range, Sink::Callback(Mutex::new(Box::new(callback))))
+    }
+
+    /// キーが`range`に入る変更を、順に受け取れるチャネルを返す
+    ///
+    /// 受け取るまで変更はチャネルに溜まる。`Receiver`を捨てると、次の変更で購読が解除される。
+    pub fn subscribe_channel<R: RangeBounds<K>>(
+        &mut self,
+        range: R,
+    ) -> (SubscriptionId, Receiver<ChangeEvent<K, V>>) {
+        let (sender, receiver) = mpsc::channel();
+        (
+            self.add_subscription(range, Sink::Channel(sender)),
+            receiver,
+        )
+    }
+
+    /// 購読を解除する（既に解除されていれば`false`）
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let subscriptions = &mut self.observers_mut().subscriptions;
+        let count = subscriptions.len();
+        subscriptions.retain(|subscription| subscription.id != id);
+        subscriptions.len() != count
+    }
+
+    fn add_subscription<R: RangeBounds<K>>(
+        &mut self,
+        range: R,
+        sink: Sink<K, V>,
+    ) -> SubscriptionId {
+        let observers = self.observers_mut();
+        let id = SubscriptionId(observers.next_id);
+        observers.next_id += 1;
+        observers.subscriptions.push(Subscription {
+            id,
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+            sink,
+        });
+        id
+    }
+
+    /// 書き込みの前に、通知する変更を作る（購読者がいないか、何も変わらない場合は`None`）
+    pub(crate) fn prepare_event(&self, key: &K, after: Option<&V>) -> Option<ChangeEvent<K, V>> {
+        if !self.observers().watches(key) {
+            return None;
+        }
+        let key = key.clone();
+        match (self.search(&key).map(|(_, value)| value), after.cloned()) {
+            (None, Some(value)) => Some(ChangeEvent::Inserted { key, value }),
+            (Some(old), Some(new)) => Some(ChangeEvent::Updated { key, old, new }),
+            (Some(old), None) => Some(ChangeEvent::Removed { key, old }),
+            (None, None) => None,
+        }
+    }
+
+    /// 書き込みの後に、変更を購読者に渡す
+    pub(crate) fn emit(&mut self, event: Option<ChangeEvent<K, V>>) {
+        if let Some(event) = event {
+            self.observers_mut().notify(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{Delete, Insert};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_callback_receives_changes() {
+        let mut tree = Btree::new(3);
+        let events = Arc::new(Mutex::new(vec![]));
+        let log = Arc::clone(&events);
+        tree.subscribe(.., move |event: &ChangeEvent<i32, &str>| {
+            log.lock().unwrap().push(event.clone())
+        });
+        tree.insert(1, "a");
+        tree.insert(1, "b");
+        tree.delete(&1);
+        // 存在しないキーの削除は何も変えない
+        tree.delete(&1);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ChangeEvent::Inserted { key: 1, value: "a" },
+                ChangeEvent::Updated {
+                    key: 1,
+                    old: "a",
+                    new: "b"
+                },
+                ChangeEvent::Removed { key: 1, old: "b" },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_channel_with_key_range() {
+        let mut tree = Btree::new(3);
+        let (id, receiver) = tree.subscribe_channel(10..20);
+        for i in 0..30 {
+            tree.insert(i, i * 10);
+        }
+        let keys: Vec<i32> = receiver.try_iter().map(|event| *event.key()).collect();
+        assert_eq!(keys, (10..20).collect::<Vec<_>>());
+
+        assert!(tree.unsubscribe(id));
+        assert!(!tree.unsubscribe(id));
+        tree.insert(15, 0);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_dropped_receiver_is_unsubscribed() {
+        let mut tree = Btree::new(3);
+        let (_, receiver) = tree.subscribe_channel(..);
+        drop(receiver);
+        tree.insert(1, 1);
+        assert!(tree.observers().subscriptions.is_empty());
+    }
+
+    #[test]
+    fn test_undo_and_clone() {
+        let mut tree = Btree::new(3);
+        tree.enable_history(10);
+        let (_, receiver) = tree.subscribe_channel(..);
+        tree.insert(1, 'a');
+        tree.undo();
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![
+                ChangeEvent::Inserted { key: 1, value: 'a' },
+                ChangeEvent::Removed { key: 1, old: 'a' },
+            ]
+        );
+
+        // 複製した木の変更は通知されない
+        let mut copy = tree.clone();
+        copy.insert(2, 'b');
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_overlapping_ranges_and_rebalancing() {
+        let mut tree = Btree::new(3);
+        let (_, low) = tree.subscribe_channel(..10);
+        let (_, high) = tree.subscribe_channel((Bound::Excluded(5), Bound::Included(20)));
+        for i in 0..30 {
+            tree.insert(i, i);
+        }
+        // 分割や併合が起きても、変わったキーごとにちょうど1回ずつ通知される
+        for i in 0..30 {
+            tree.delete(&i);
+        }
+        let keys = |receiver: &Receiver<ChangeEvent<i32, i32>>| -> Vec<i32> {
+            receiver.try_iter().map(|event| *event.key()).collect()
+        };
+        let expected: Vec<i32> = (0..10).chain(0..10).collect();
+        assert_eq!(keys(&low), expected);
+        let expected: Vec<i32> = (6..=20).chain(6..=20).collect();
+        assert_eq!(keys(&high), expected);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::btree::node::BtreeNode;
+use crate::btree::tree::{Btree, RUNTIME_CAPACITY};
+use std::io::{self, Read, Write};
+
+/// ファイルの先頭に置く識別子（末尾の1バイトは形式の版）
+const MAGIC: &[u8; 8] = b"BTREE\0\0\x01";
+
+/// 読み込むノードの深さの上限（壊れたファイルで再帰が深くなりすぎないようにする）
+const MAX_DEPTH: usize = 64;
+
+/// `Btree::save`と`Btree::load`でバイト列に変換できるキーと値
+pub trait Persist: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    /// `encode`の結果から値を戻す（形式が正しくなければ`None`）
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+impl Persist for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl Persist for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl Persist for u64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+impl Persist for i64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(i64::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_u32(writer: &mut impl Write, n: usize) -> io::Result<()> {
+    let n = u32::try_from(n).map_err(|_| invalid("length does not fit in u32"))?;
+    writer.write_all(&n.to_le_bytes())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<usize> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+fn write_item<T: Persist>(
+    writer: &mut impl Write,
+    item: &T,
+    buffer: &mut Vec<u8>,
+) -> io::Result<()> {
+    buffer.clear();
+    item.encode(buffer);
+    write_u32(writer, buffer.len())?;
+    writer.write_all(buffer)
+}
+
+fn read_item<T: Persist>(reader: &mut impl Read, buffer: &mut Vec<u8>) -> io::Result<T> {
+    let len = read_u32(reader)?;
+    buffer.clear();
+    // 長さが壊れていても、実際に読めた分しか確保しない
+    reader.take(len as u64).read_to_end(buffer)?;
+    if buffer.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    T::decode(buffer).ok_or_else(|| invalid("cannot decode key or value"))
+}
+
+/// ノードを前順（ノード、子ノードの順）に書き出す
+fn write_node<K, V>(
+    writer: &mut impl Write,
+    node: &BtreeNode<K, V>,
+    buffer: &mut Vec<u8>,
+) -> io::Result<()>
+where
+    K: 'static + Clone + PartialEq + PartialOrd + Persist,
+    V: 'static + Clone + Persist,
+{
+    write_u32(writer, node.keys().len())?;
+    write_u32(writer, node.children().len())?;
+    for (key, value) in node.keys().iter().zip(node.values()) {
+        write_item(writer, key, buffer)?;
+        write_item(writer, value, buffer)?;
+    }
+    for child in node.children() {
+        write_node(writer, child, buffer)?;
+    }
+    Ok(())
+}
+
+/// ノードを読み込み、部分木のキーの数を`count`に足す
+///
+/// 子ノードの数だけは再帰して読める形か確かめる。それ以外の条件は読み終えてから`Btree::verify`で調べる。
+fn read_node<K, V>(
+    reader: &mut impl Read,
+    depth: usize,
+    count: &mut u64,
+    buffer: &mut Vec<u8>,
+) -> io::Result<BtreeNode<K, V>>
+where
+    K: 'static + Clone + PartialEq + PartialOrd + Persist,
+    V: 'static + Clone + Persist,
+{
+    if depth > MAX_DEPTH {
+        return Err(invalid("tree is too deep"));
+    }
+    let key_count = read_u32(reader)?;
+    let child_count = read_u32(reader)?;
+    if child_count != 0 && child_count != key_count + 1 {
+        return Err(invalid("child count does not match key count"));
+    }
+    let mut keys = vec![];
+    let mut values = vec![];
+    for _ in 0..key_count {
+        keys.push(read_item(reader, buffer)?);
+        values.push(read_item(reader, buffer)?);
+    }
+    *count += key_count as u64;
+    let mut children = vec![];
+    for _ in 0..child_count {
+        children.push(Box::new(read_node(reader, depth + 1, count, buffer)?));
+    }
+    Ok(BtreeNode::from(keys, values, children))
+}
+
+impl<K, V, const B: usize> Btree<K, V, B>
+where
+    K: 'static + Clone + PartialEq + PartialOrd + Persist,
+    V: 'static + Clone + Persist,
+{
+    /// 木をノードの形のまま書き出す
+    ///
+    /// 形式は識別子、最大要素数（u32）、キーの数（u64）に続けて、各ノードを前順に並べたもの。
+    /// ノードはキーの数と子ノードの数（u32）と、長さ（u32）を前に付けたキーと値の組からなる。
+    /// 数値はすべてリトルエンディアン。
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u32(&mut writer, self.max_count())?;
+        writer.write_all(&(self.iter().count() as u64).to_le_bytes())?;
+        let mut buffer = vec![];
+        if let Some(root) = self.root() {
+            write_node(&mut writer, root, &mut buffer)?;
+        }
+        writer.flush()
+    }
+
+    /// `save`で書き出した木を読み込む
+    ///
+    /// `B`を指定した型で読む場合は、ファイルの最大要素数が`B`と一致している必要がある。
+    /// 読み込んだ木がB木の条件を満たさない場合は、`VerifyError`を包んだ`InvalidData`のエラーを返す。
+    pub fn load<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a btree file"));
+        }
+        let max_count = read_u32(&mut reader)?;
+        if max_count < 3 || (B != RUNTIME_CAPACITY && max_count != B) {
+            return Err(invalid("unsupported max_count"));
+        }
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes)?;
+        let expected = u64::from_le_bytes(bytes);
+
+        let mut tree = Btree::with_max_count(max_count);
+        if expected > 0 {
+            let mut count = 0;
+            let root = read_node(&mut reader, 0, &mut count, &mut vec![])?;
+            if count != expected {
+                return Err(invalid("key count does not match header"));
+            }
+            *tree.root_mut() = Some(root);
+        }
+        // 条件を満たさない木を変更するとパニックするので、ここで弾く
+        tree.verify()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::verify::VerifyError;
+    use crate::btree::{Insert, Search};
+
+    fn sample() -> Btree<String, u64> {
+        let mut tree = Btree::new(4);
+        for i in 0..100u64 {
+            tree.insert(format!("key{:03}", (i * 37) % 100), i);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tree = sample();
+        let mut file = vec![];
+        tree.save(&mut file).unwrap();
+
+        let loaded: Btree<String, u64> = Btree::load(file.as_slice()).unwrap();
+        assert_eq!(loaded.max_count(), 4);
+        assert_eq!(loaded.render(), tree.render());
+        assert_eq!(loaded.verify(), Ok(()));
+        assert_eq!(
+            loaded.search(&"key042".to_string()).map(|(_, v)| v),
+            tree.search(&"key042".to_string()).map(|(_, v)| v)
+        );
+
+        let empty: Btree<Vec<u8>, i64> = Btree::new(3);
+        let mut file = vec![];
+        empty.save(&mut file).unwrap();
+        let loaded: Btree<Vec<u8>, i64> = Btree::load(file.as_slice()).unwrap();
+        assert!(loaded.root().is_none());
+    }
+
+    #[test]
+    fn test_fixed_capacity_must_match() {
+        let mut file = vec![];
+        sample().save(&mut file).unwrap();
+        assert!(Btree::<String, u64, 4>::load(file.as_slice()).is_ok());
+        let error = Btree::<String, u64, 8>::load(file.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_corrupt_file() {
+        let mut file = vec![];
+        sample().save(&mut file).unwrap();
+
+        let mut bad_magic = file.clone();
+        bad_magic[0] = b'X';
+        assert!(Btree::<String, u64>::load(bad_magic.as_slice()).is_err());
+
+        // どこで切れても、パニックせずにエラーになる
+        for len in 0..file.len() {
+            assert!(Btree::<String, u64>::load(&file[..len]).is_err());
+        }
+
+        // 値の長さを壊すと、u64として読めない
+        let mut bad_value = file.clone();
+        let offset = MAGIC.len() + 4 + 8 + 4 + 4 + 4 + "key000".len();
+        bad_value[offset] = 3;
+        assert!(Btree::<String, u64>::load(bad_value.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_tree() {
+        let leaf = |keys: Vec<u64>| BtreeNode::from(keys.clone(), keys, vec![]);
+        // キーのない内部ノードを根に持つ木と、最大数を超えるキーを持つ葉だけの木
+        for root in [
+            BtreeNode::from(vec![], vec![], vec![Box::new(leaf(vec![1, 2]))]),
+            leaf((0..10).collect()),
+        ] {
+            let mut tree: Btree<u64, u64> = Btree::new(3);
+            *tree.root_mut() = Some(root);
+            let mut file = vec![];
+            tree.save(&mut file).unwrap();
+
+            let error = Btree::<u64, u64>::load(file.as_slice()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            let error = error.get_ref().unwrap().downcast_ref::<VerifyError>();
+            assert_eq!(error, tree.verify().err().as_ref());
+        }
+    }
+}
@@ -0,0 +1,608 @@
+use crate::btree::{Delete, Insert, Search};
+
+/// バイト列のキーを圧縮して持つB+木のノード
+///
+/// ノード内の全てのキーに共通する接頭辞を`prefix`に一度だけ持ち、各キーは残りの部分だけを持つ。
+/// 内部ノードの区切りキーは左右の部分木を区別できる最短の長さまで切り詰めてある。
+struct PrefixNode<V: 'static + Clone> {
+    prefix: Vec<u8>,
+    suffixes: Vec<Vec<u8>>,
+    body: PrefixBody<V>,
+}
+
+enum PrefixBody<V: 'static + Clone> {
+    Internal { children: Vec<usize> },
+    Leaf { values: Vec<V>, next: Option<usize> },
+}
+
+/// URLやパスのように長い接頭辞を共有するバイト列をキーにするB+木
+///
+/// ノードごとに共通の接頭辞をまとめ、内部ノードには切り詰めた区切りキーだけを置くので、
+/// キーが長くてもノードが小さく、一つの内部ノードから多くの子ノードに分岐できる。
+pub struct PrefixBtree<V: 'static + Clone> {
+    nodes: Vec<PrefixNode<V>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    max_count: usize,
+}
+
+/// `PrefixBtree`のエントリをキーの昇順にたどるイテレータ
+pub struct PrefixIter<'a, V: 'static + Clone> {
+    tree: &'a PrefixBtree<V>,
+    leaf: Option<usize>,
+    index: usize,
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+/// `left`より大きく`right`以下になる最も短いキー（`right`の先頭部分）
+fn shortest_separator(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let len = common_prefix_len(left, right) + 1;
+    right[..len.min(right.len())].to_vec()
+}
+
+impl<V: 'static + Clone> PrefixNode<V> {
+    fn leaf(next: Option<usize>) -> Self {
+        PrefixNode {
+            prefix: vec![],
+            suffixes: vec![],
+            body: PrefixBody::Leaf {
+                values: vec![],
+                next,
+            },
+        }
+    }
+
+    fn internal(children: Vec<usize>) -> Self {
+        PrefixNode {
+            prefix: vec![],
+            suffixes: vec![],
+            body: PrefixBody::Internal { children },
+        }
+    }
+
+    fn current_count(&self) -> usize {
+        self.suffixes.len()
+    }
+
+    fn key(&self, index: usize) -> Vec<u8> {
+        [self.prefix.as_slice(), &self.suffixes[index]].concat()
+    }
+
+    fn lookup(&self, key: &[u8]) -> Result<usize, usize> {
+        match key.strip_prefix(self.prefix.as_slice()) {
+            Some(rest) => self.suffixes.binary_search_by(|s| s.as_slice().cmp(rest)),
+            // 接頭辞が一致しない場合は、全てのキーより小さいか大きい
+            None if key < self.prefix.as_slice() => Err(0),
+            None => Err(self.current_count()),
+        }
+    }
+
+    /// `key`を含む子ノードの位置
+    fn child_index(&self, key: &[u8]) -> usize {
+        match self.lookup(key) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    /// キーを挿入する（接頭辞が一致しない場合は、一致する長さまで接頭辞を縮める）
+    fn insert_key(&mut self, index: usize, key: &[u8]) {
+        if !key.starts_with(&self.prefix) {
+            let len = common_prefix_len(&self.prefix, key);
+            let dropped = self.prefix.split_off(len);
+            for suffix in &mut self.suffixes {
+                suffix.splice(0..0, dropped.iter().copied());
+            }
+        }
+        self.suffixes
+            .insert(index, key[self.prefix.len()..].to_vec());
+    }
+
+    fn remove_key(&mut self, index: usize) -> Vec<u8> {
+        let suffix = self.suffixes.remove(index);
+        [self.prefix.as_slice(), &suffix].concat()
+    }
+
+    fn replace_key(&mut self, index: usize, key: &[u8]) -> Vec<u8> {
+        let old = self.remove_key(index);
+        self.insert_key(index, key);
+        old
+    }
+
+    /// 接頭辞を戻した全てのキーを取り出す
+    fn take_keys(&mut self) -> Vec<Vec<u8>> {
+        let prefix = std::mem::take(&mut self.prefix);
+        self.suffixes
+            .drain(..)
+            .map(|suffix| [prefix.as_slice(), &suffix].concat())
+            .collect()
+    }
+
+    /// 全てのキーを置き換え、共通の接頭辞を計算し直す
+    fn set_keys(&mut self, keys: Vec<Vec<u8>>) {
+        let len = match keys.first() {
+            Some(first) => keys
+                .iter()
+                .map(|key| common_prefix_len(first, key))
+                .min()
+                .unwrap_or(0),
+            None => 0,
+        };
+        self.prefix = keys.first().map_or(vec![], |first| first[..len].to_vec());
+        self.suffixes = keys.into_iter().map(|mut key| key.split_off(len)).collect();
+    }
+
+    fn values_mut(&mut self) -> &mut Vec<V> {
+        match &mut self.body {
+            PrefixBody::Leaf { values, .. } => values,
+            PrefixBody::Internal { .. } => unreachable!("internal node has no values"),
+        }
+    }
+
+    fn children(&self) -> &Vec<usize> {
+        match &self.body {
+            PrefixBody::Internal { children } => children,
+            PrefixBody::Leaf { .. } => unreachable!("leaf has no children"),
+        }
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<usize> {
+        match &mut self.body {
+            PrefixBody::Internal { children } => children,
+            PrefixBody::Leaf { .. } => unreachable!("leaf has no children"),
+        }
+    }
+}
+
+impl<V: 'static + Clone> PrefixBtree<V> {
+    pub fn new(max_count: usize) -> Self {
+        assert!(max_count >= 3, "max_count must be at least 3");
+        PrefixBtree {
+            nodes: vec![],
+            free: vec![],
+            root: None,
+            max_count,
+        }
+    }
+
+    /// キーを保持するために使っているバイト数（接頭辞と残りの部分の合計）
+    pub fn key_bytes(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|node| node.prefix.len() + node.suffixes.iter().map(Vec::len).sum::<usize>())
+            .sum()
+    }
+
+    /// 全てのエントリをキーの昇順にたどる
+    pub fn iter(&self) -> PrefixIter<'_, V> {
+        let mut leaf = self.root;
+        while let Some(id) = leaf {
+            match &self.nodes[id].body {
+                PrefixBody::Internal { children } => leaf = Some(children[0]),
+                PrefixBody::Leaf { .. } => break,
+            }
+        }
+        PrefixIter {
+            tree: self,
+            leaf,
+            index: 0,
+        }
+    }
+
+    fn min_count(&self) -> usize {
+        (self.max_count - 1) / 2
+    }
+
+    fn allocate(&mut self, node: PrefixNode<V>) -> usize {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// 使わなくなったノードを取り出し、位置を再利用できるようにする
+    fn release(&mut self, id: usize) -> PrefixNode<V> {
+        self.free.push(id);
+        std::mem::replace(&mut self.nodes[id], PrefixNode::internal(vec![]))
+    }
+
+    /// `key`が含まれるはずの葉ノード
+    fn find_leaf(&self, key: &[u8]) -> Option<usize> {
+        let mut id = self.root?;
+        while let PrefixBody::Internal { children } = &self.nodes[id].body {
+            id = children[self.nodes[id].child_index(key)];
+        }
+        Some(id)
+    }
+
+    /// ノードに挿入し、分割した場合は親に追加する区切りキーと右のノードを返す
+    fn insert_into(&mut self, id: usize, key: &[u8], value: V) -> Option<(Vec<u8>, usize)> {
+        let node = &mut self.nodes[id];
+        match &node.body {
+            PrefixBody::Leaf { .. } => {
+                match node.lookup(key) {
+                    Ok(i) => {
+                        node.values_mut()[i] = value;
+                        return None;
+                    }
+                    Err(i) => {
+                        node.insert_key(i, key);
+                        node.values_mut().insert(i, value);
+                    }
+                }
+                if node.current_count() < self.max_count {
+                    return None;
+                }
+                Some(self.split_leaf(id))
+            }
+            PrefixBody::Internal { children } => {
+                let index = node.child_index(key);
+                let child = children[index];
+                let (separator, right) = self.insert_into(child, key, value)?;
+                let node = &mut self.nodes[id];
+                node.insert_key(index, &separator);
+                node.children_mut().insert(index + 1, right);
+                if node.current_count() < self.max_count {
+                    return None;
+                }
+                Some(self.split_internal(id))
+            }
+        }
+    }
+
+    fn split_leaf(&mut self, id: usize) -> (Vec<u8>, usize) {
+        let node = &mut self.nodes[id];
+        let mut keys = node.take_keys();
+        let mid_index = keys.len() / 2;
+        let right_keys = keys.split_off(mid_index);
+        let separator = shortest_separator(&keys[mid_index - 1], &right_keys[0]);
+        let PrefixBody::Leaf { values, next } = &mut node.body else {
+            unreachable!("split_leaf on internal node");
+        };
+        let right_values = values.split_off(mid_index);
+        let mut right = PrefixNode::leaf(next.take());
+        node.set_keys(keys);
+
+        right.set_keys(right_keys);
+        *right.values_mut() = right_values;
+        let right = self.allocate(right);
+        if let PrefixBody::Leaf { next, .. } = &mut self.nodes[id].body {
+            *next = Some(right);
+        }
+        (separator, right)
+    }
+
+    fn split_internal(&mut self, id: usize) -> (Vec<u8>, usize) {
+        let node = &mut self.nodes[id];
+        let mut keys = node.take_keys();
+        let mid_index = keys.len() / 2;
+        let right_keys = keys.split_off(mid_index + 1);
+        let separator = keys.pop().unwrap();
+        let right_children = node.children_mut().split_off(mid_index + 1);
+        node.set_keys(keys);
+
+        let mut right = PrefixNode::internal(right_children);
+        right.set_keys(right_keys);
+        (separator, self.allocate(right))
+    }
+
+    fn delete_from(&mut self, id: usize, key: &[u8]) {
+        let node = &mut self.nodes[id];
+        match &node.body {
+            PrefixBody::Leaf { .. } => {
+                if let Ok(i) = node.lookup(key) {
+                    node.remove_key(i);
+                    node.values_mut().remove(i);
+                }
+            }
+            PrefixBody::Internal { children } => {
+                let index = node.child_index(key);
+                let child = children[index];
+                self.delete_from(child, key);
+                if self.nodes[child].current_count() < self.min_count() {
+                    self.rebalance_child(id, index);
+                }
+            }
+        }
+    }
+
+    /// 最小数を下回った子ノードを、兄弟からの移動または併合で補う
+    fn rebalance_child(&mut self, parent: usize, index: usize) {
+        let children = self.nodes[parent].children();
+        let count = |i: usize| self.nodes[children[i]].current_count();
+        if index > 0 && count(index - 1) > self.min_count() {
+            // 一つ左が十分な要素を持っている
+            self.borrow_from_left(parent, index);
+        } else if index + 1 < children.len() && count(index + 1) > self.min_count() {
+            // 一つ右が十分な要素を持っている
+            self.borrow_from_right(parent, index);
+        } else if index > 0 {
+            self.merge_children(parent, index - 1);
+        } else {
+            self.merge_children(parent, index);
+        }
+    }
+
+    fn borrow_from_left(&mut self, parent: usize, index: usize) {
+        let left = self.nodes[parent].children()[index - 1];
+        let child = self.nodes[parent].children()[index];
+        let last = self.nodes[left].current_count() - 1;
+        let key = self.nodes[left].remove_key(last);
+        match &mut self.nodes[left].body {
+            PrefixBody::Leaf { values, .. } => {
+                let value = values.pop().unwrap();
+                let separator = shortest_separator(&self.nodes[left].key(last - 1), &key);
+                self.nodes[parent].replace_key(index - 1, &separator);
+                self.nodes[child].insert_key(0, &key);
+                self.nodes[child].values_mut().insert(0, value);
+            }
+            PrefixBody::Internal { children } => {
+                let grandchild = children.pop().unwrap();
+                let separator = self.nodes[parent].replace_key(index - 1, &key);
+                self.nodes[child].insert_key(0, &separator);
+                self.nodes[child].children_mut().insert(0, grandchild);
+            }
+        }
+    }
+
+    fn borrow_from_right(&mut self, parent: usize, index: usize) {
+        let child = self.nodes[parent].children()[index];
+        let right = self.nodes[parent].children()[index + 1];
+        let key = self.nodes[right].remove_key(0);
+        let end = self.nodes[child].current_count();
+        match &mut self.nodes[right].body {
+            PrefixBody::Leaf { values, .. } => {
+                let value = values.remove(0);
+                let separator = shortest_separator(&key, &self.nodes[right].key(0));
+                self.nodes[parent].replace_key(index, &separator);
+                self.nodes[child].insert_key(end, &key);
+                self.nodes[child].values_mut().push(value);
+            }
+            PrefixBody::Internal { children } => {
+                let grandchild = children.remove(0);
+                let separator = self.nodes[parent].replace_key(index, &key);
+                self.nodes[child].insert_key(end, &separator);
+                self.nodes[child].children_mut().push(grandchild);
+            }
+        }
+    }
+
+    /// 子ノード`index`と`index + 1`を左側にまとめ、接頭辞を計算し直す
+    fn merge_children(&mut self, parent: usize, index: usize) {
+        let separator = self.nodes[parent].remove_key(index);
+        let left = self.nodes[parent].children()[index];
+        let right = self.nodes[parent].children_mut().remove(index + 1);
+
+        let mut right = self.release(right);
+        let left = &mut self.nodes[left];
+        let mut keys = left.take_keys();
+        match (&mut left.body, &mut right.body) {
+            (
+                PrefixBody::Leaf { values, next },
+                PrefixBody::Leaf {
+                    values: right_values,
+                    next: right_next,
+                },
+            ) => {
+                values.append(right_values);
+                *next = *right_next;
+            }
+            (
+                PrefixBody::Internal { children },
+                PrefixBody::Internal {
+                    children: right_children,
+                },
+            ) => {
+                keys.push(separator);
+                children.append(right_children);
+            }
+            _ => unreachable!("siblings must be on the same level"),
+        }
+        keys.append(&mut right.take_keys());
+        left.set_keys(keys);
+    }
+}
+
+impl<V: 'static + Clone> Search<Vec<u8>, V> for PrefixBtree<V> {
+    fn search(&self, key: &Vec<u8>) -> Option<(Vec<u8>, V)> {
+        let node = &self.nodes[self.find_leaf(key)?];
+        match (&node.body, node.lookup(key)) {
+            (PrefixBody::Leaf { values, .. }, Ok(i)) => Some((key.clone(), values[i].clone())),
+            _ => None,
+        }
+    }
+}
+
+impl<V: 'static + Clone> Insert<Vec<u8>, V> for PrefixBtree<V> {
+    fn insert(&mut self, key: Vec<u8>, value: V) {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                let root = self.allocate(PrefixNode::leaf(None));
+                self.root = Some(root);
+                root
+            }
+        };
+        if let Some((separator, right)) = self.insert_into(root, &key, value) {
+            let mut node = PrefixNode::internal(vec![root, right]);
+            node.set_keys(vec![separator]);
+            self.root = Some(self.allocate(node));
+        }
+    }
+}
+
+impl<V: 'static + Clone> Delete<Vec<u8>> for PrefixBtree<V> {
+    fn delete(&mut self, key: &Vec<u8>) {
+        let Some(root) = self.root else {
+            return;
+        };
+        self.delete_from(root, key);
+
+        // 根の要素がなくなった場合は、唯一の子ノードを新しい根にする
+        if self.nodes[root].current_count() == 0 {
+            self.root = match &self.nodes[root].body {
+                PrefixBody::Internal { children } => Some(children[0]),
+                PrefixBody::Leaf { .. } => None,
+            };
+            self.release(root);
+        }
+    }
+}
+
+impl<'a, V: 'static + Clone> Iterator for PrefixIter<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = &self.tree.nodes[self.leaf?];
+            let PrefixBody::Leaf { values, next } = &node.body else {
+                unreachable!("leaf chain contains internal node");
+            };
+            if self.index < values.len() {
+                self.index += 1;
+                return Some((node.key(self.index - 1), &values[self.index - 1]));
+            }
+            self.leaf = *next;
+            self.index = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::test_util::XorShift;
+    use std::collections::BTreeMap;
+
+    /// 区切りキーが左右の部分木を正しく分け、接頭辞が全てのキーに共通していることを検証する
+    fn check_invariants(tree: &PrefixBtree<u32>) {
+        fn check_node(
+            tree: &PrefixBtree<u32>,
+            id: usize,
+            lower: Option<&[u8]>,
+            upper: Option<&[u8]>,
+        ) {
+            let node = &tree.nodes[id];
+            let keys: Vec<Vec<u8>> = (0..node.current_count()).map(|i| node.key(i)).collect();
+            assert!(keys.windows(2).all(|w| w[0] < w[1]));
+            assert!(keys.iter().all(|k| lower.is_none_or(|l| l <= k.as_slice())));
+            assert!(keys.iter().all(|k| upper.is_none_or(|u| k.as_slice() < u)));
+            if let PrefixBody::Internal { children } = &node.body {
+                assert_eq!(children.len(), keys.len() + 1);
+                for (i, &child) in children.iter().enumerate() {
+                    let lower = if i == 0 {
+                        lower
+                    } else {
+                        Some(keys[i - 1].as_slice())
+                    };
+                    let upper = keys.get(i).map(Vec::as_slice).or(upper);
+                    check_node(tree, child, lower, upper);
+                }
+            }
+        }
+        if let Some(root) = tree.root {
+            check_node(tree, root, None, None);
+        }
+    }
+
+    fn url(i: u32) -> Vec<u8> {
+        format!("https://example.com/api/v1/users/{:05}/profile", i).into_bytes()
+    }
+
+    #[test]
+    fn test_shortest_separator() {
+        assert_eq!(shortest_separator(b"apple", b"banana"), b"b");
+        assert_eq!(
+            shortest_separator(b"users/0099", b"users/0100"),
+            b"users/01"
+        );
+        assert_eq!(shortest_separator(b"user", b"users"), b"users");
+    }
+
+    #[test]
+    fn test_insert_and_search() {
+        let mut tree = PrefixBtree::new(4);
+        for i in (0..200).rev() {
+            tree.insert(url(i), i);
+        }
+        check_invariants(&tree);
+        assert_eq!(tree.search(&url(42)), Some((url(42), 42)));
+        assert_eq!(tree.search(&b"https://example.com".to_vec()), None);
+        assert_eq!(tree.search(&b"z".to_vec()), None);
+        assert!(
+            tree.iter()
+                .map(|(k, v)| (k, *v))
+                .eq((0..200).map(|i| (url(i), i)))
+        );
+    }
+
+    #[test]
+    fn test_compresses_shared_prefixes() {
+        let mut tree = PrefixBtree::new(16);
+        let mut raw = 0;
+        for i in 0..1000 {
+            raw += url(i).len();
+            tree.insert(url(i), i);
+        }
+        // 共通の接頭辞はノードごとに一度しか持たず、区切りキーは数文字に切り詰められる
+        assert!(tree.key_bytes() * 3 < raw);
+        let root = &tree.nodes[tree.root.unwrap()];
+        assert!((0..root.current_count()).all(|i| root.key(i).len() < url(0).len()));
+    }
+
+    #[test]
+    fn test_random_operations() {
+        for max_count in [3, 4, 5, 8] {
+            let mut tree = PrefixBtree::new(max_count);
+            let mut expected = BTreeMap::new();
+            let mut rng = XorShift::new(0x2545_F491_4F6C_DD1D);
+            for step in 0..4000 {
+                let seed = rng.next_u64();
+                // 長さや接頭辞の揃わないキーも混ぜる
+                let key = match seed % 4 {
+                    0 => format!("/srv/data/{}", seed % 97).into_bytes(),
+                    1 => format!("/srv/{}", seed % 53).into_bytes(),
+                    _ => url((seed % 300) as u32),
+                };
+                if seed % 5 < 2 {
+                    tree.delete(&key);
+                    expected.remove(&key);
+                } else {
+                    tree.insert(key.clone(), step);
+                    expected.insert(key, step);
+                }
+            }
+            check_invariants(&tree);
+            assert!(tree.iter().map(|(k, v)| (k, *v)).eq(expected.clone()));
+            for key in expected.keys() {
+                assert_eq!(tree.search(key).map(|(_, v)| v), expected.get(key).copied());
+            }
+        }
+    }
+
+    #[test]
+    fn test_delete_all() {
+        let mut tree = PrefixBtree::new(4);
+        for i in 0..300 {
+            tree.insert(url(i), i);
+        }
+        for i in 0..300 {
+            tree.delete(&url(i));
+            if i % 50 == 0 {
+                check_invariants(&tree);
+            }
+        }
+        assert_eq!(tree.root, None);
+        assert_eq!(tree.key_bytes(), 0);
+        assert_eq!(tree.iter().next(), None);
+    }
+}
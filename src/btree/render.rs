@@ -0,0 +1,340 @@
+use crate::btree::BinarySearch;
+use crate::btree::node::BtreeNode;
+use crate::btree::tree::Btree;
+use std::fmt::{self, Debug, Write};
+
+/// `Btree::render_with`と`Btree::to_dot_with`の表示方法
+pub struct RenderOptions<K> {
+    /// この深さ（根が0）より下のノードは省略する
+    pub max_depth: Option<usize>,
+    /// 根からこのキーを探す経路を強調する
+    pub highlight: Option<K>,
+}
+
+impl<K> Default for RenderOptions<K> {
+    fn default() -> Self {
+        RenderOptions {
+            max_depth: None,
+            highlight: None,
+        }
+    }
+}
+
+/// 探索経路上のノードで、`key`が見つかった位置と次にたどる子ノードの位置
+fn path_step<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone>(
+    node: &BtreeNode<K, V>,
+    key: Option<&K>,
+) -> (Option<usize>, Option<usize>) {
+    match key.map(|key| node.keys().binary_lookup(key)) {
+        None => (None, None),
+        Some(Ok(i)) => (Some(i), None),
+        Some(Err(_)) if node.is_leaf() => (None, None),
+        Some(Err(i)) => (None, Some(i)),
+    }
+}
+
+/// ノードのキーを並べる（見つかったキーは`<>`で囲む）
+fn format_keys<K: Debug>(keys: &[K], found: Option<usize>) -> Vec<String> {
+    keys.iter()
+        .enumerate()
+        .map(|(i, key)| {
+            if found == Some(i) {
+                format!("<{key:?}>")
+            } else {
+                format!("{key:?}")
+            }
+        })
+        .collect()
+}
+
+/// DOTのレコードのラベルで特別な意味を持つ文字をエスケープする
+fn escape_record(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '\\' | '"' | '{' | '}' | '|' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+struct Renderer<'a, K> {
+    out: String,
+    /// ノードが持てるキーの最大数
+    capacity: usize,
+    options: &'a RenderOptions<K>,
+    next_id: usize,
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd + Debug> Renderer<'_, K> {
+    fn is_cut(&self, depth: usize) -> bool {
+        self.options.max_depth.is_some_and(|max| depth > max)
+    }
+
+    fn ascii_node<V: 'static + Clone>(
+        &mut self,
+        node: &BtreeNode<K, V>,
+        depth: usize,
+        prefix: &str,
+        connector: &str,
+        key: Option<&K>,
+    ) {
+        let (found, next) = path_step(node, key);
+        let count = node.keys().len();
+        let _ = writeln!(
+            self.out,
+            "{prefix}{connector}{}[{}]  level {depth}  {count}/{} ({}%)",
+            if key.is_some() { "* " } else { "" },
+            format_keys(node.keys(), found).join(", "),
+            self.capacity,
+            count * 100 / self.capacity,
+        );
+
+        let child_prefix = match connector {
+            "" => String::new(),
+            "`-- " => format!("{prefix}    "),
+            _ => format!("{prefix}|   "),
+        };
+        let children = node.children();
+        if children.is_empty() {
+            return;
+        }
+        if self.is_cut(depth + 1) {
+            let _ = writeln!(
+                self.out,
+                "{child_prefix}`-- ... {} subtrees",
+                children.len()
+            );
+            return;
+        }
+        for (i, child) in children.iter().enumerate() {
+            let connector = if i + 1 == children.len() {
+                "`-- "
+            } else {
+                "+-- "
+            };
+            let key = if next == Some(i) { key } else { None };
+            self.ascii_node(child, depth + 1, &child_prefix, connector, key);
+        }
+    }
+
+    /// ノードを出力して、DOT上の名前を返す
+    fn dot_node<V: 'static + Clone>(
+        &mut self,
+        node: &BtreeNode<K, V>,
+        depth: usize,
+        key: Option<&K>,
+    ) -> String {
+        let name = format!("n{}", self.next_id);
+        self.next_id += 1;
+        let (found, next) = path_step(node, key);
+
+        // 子ノードへのポート`<cN>`とキーを交互に並べる
+        let keys = format_keys(node.keys(), found);
+        let mut fields = vec![];
+        for (i, key) in keys.iter().enumerate() {
+            if !node.is_leaf() {
+                fields.push(format!("<c{i}> "));
+            }
+            fields.push(escape_record(key));
+        }
+        if !node.is_leaf() {
+            fields.push(format!("<c{}> ", keys.len()));
+        }
+        let style = if key.is_some() {
+            ", color=red, penwidth=2"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            self.out,
+            "    {name} [label=\"{}\"{style}];",
+            fields.join("|")
+        );
+
+        let children = node.children();
+        if children.is_empty() {
+            return name;
+        }
+        if self.is_cut(depth + 1) {
+            let cut = format!("n{}", self.next_id);
+            self.next_id += 1;
+            let _ = writeln!(
+                self.out,
+                "    {cut} [shape=plaintext, label=\"... {} subtrees\"];",
+                children.len()
+            );
+            let _ = writeln!(self.out, "    {name} -> {cut};");
+            return name;
+        }
+        for (i, child) in children.iter().enumerate() {
+            let on_path = next == Some(i);
+            let child_name = self.dot_node(child, depth + 1, if on_path { key } else { None });
+            let style = if on_path {
+                " [color=red, penwidth=2]"
+            } else {
+                ""
+            };
+            let _ = writeln!(self.out, "    {name}:c{i} -> {child_name}{style};");
+        }
+        name
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd + Debug, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    fn renderer<'a>(&self, options: &'a RenderOptions<K>) -> Renderer<'a, K> {
+        Renderer {
+            out: String::new(),
+            capacity: self.max_count() - 1,
+            options,
+            next_id: 0,
+        }
+    }
+
+    /// 木の構造を、階層ごとに字下げしたテキストで表す
+    ///
+    /// 各ノードはキーと深さ、キーの数と最大数に対する割合を表示する。
+    pub fn render(&self) -> String {
+        self.render_with(&RenderOptions::default())
+    }
+
+    pub fn render_with(&self, options: &RenderOptions<K>) -> String {
+        let mut renderer = self.renderer(options);
+        let mut height = 0;
+        let mut node = self.root();
+        while let Some(current) = node {
+            height += 1;
+            node = current.children().first().map(|child| &**child);
+        }
+        let _ = writeln!(
+            renderer.out,
+            "Btree (max_count = {}, height = {height}, keys = {})",
+            self.max_count(),
+            self.iter().count()
+        );
+        match self.root() {
+            Some(root) => renderer.ascii_node(root, 0, "", "", options.highlight.as_ref()),
+            None => renderer.out.push_str("(empty)\n"),
+        }
+        renderer.out
+    }
+
+    /// 木の構造をGraphvizのDOT形式で表す
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with(&RenderOptions::default())
+    }
+
+    pub fn to_dot_with(&self, options: &RenderOptions<K>) -> String {
+        let mut renderer = self.renderer(options);
+        renderer
+            .out
+            .push_str("digraph btree {\n    node [shape=record];\n");
+        if let Some(root) = self.root() {
+            renderer.dot_node(root, 0, options.highlight.as_ref());
+        }
+        renderer.out.push_str("}\n");
+        renderer.out
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd + Debug, V: 'static + Clone, const B: usize> Debug
+    for Btree<K, V, B>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::Insert;
+
+    fn sample_tree() -> Btree<i32, i32> {
+        let mut tree = Btree::new(3);
+        for i in 1..=7 {
+            tree.insert(i * 10, i);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            sample_tree().render(),
+            "\
+Btree (max_count = 3, height = 3, keys = 7)
+[40]  level 0  1/2 (50%)
++-- [20]  level 1  1/2 (50%)
+|   +-- [10]  level 2  1/2 (50%)
+|   `-- [30]  level 2  1/2 (50%)
+`-- [60]  level 1  1/2 (50%)
+    +-- [50]  level 2  1/2 (50%)
+    `-- [70]  level 2  1/2 (50%)
+"
+        );
+        assert_eq!(format!("{:?}", sample_tree()), sample_tree().render());
+
+        let empty: Btree<i32, i32> = Btree::new(3);
+        assert_eq!(
+            empty.render(),
+            "Btree (max_count = 3, height = 0, keys = 0)\n(empty)\n"
+        );
+    }
+
+    #[test]
+    fn test_render_with_options() {
+        let options = RenderOptions {
+            max_depth: Some(1),
+            highlight: Some(60),
+        };
+        assert_eq!(
+            sample_tree().render_with(&options),
+            "\
+Btree (max_count = 3, height = 3, keys = 7)
+* [40]  level 0  1/2 (50%)
++-- [20]  level 1  1/2 (50%)
+|   `-- ... 2 subtrees
+`-- * [<60>]  level 1  1/2 (50%)
+    `-- ... 2 subtrees
+"
+        );
+
+        // 存在しないキーは葉までの経路を強調する
+        let options = RenderOptions {
+            max_depth: None,
+            highlight: Some(35),
+        };
+        let rendered = sample_tree().render_with(&options);
+        let marked: Vec<&str> = rendered.lines().filter(|l| l.contains("* ")).collect();
+        assert_eq!(marked.len(), 3);
+        assert!(marked[2].contains("[30]"));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = sample_tree().to_dot();
+        assert!(dot.starts_with("digraph btree {\n"));
+        assert!(dot.contains("    n0 [label=\"<c0> |40|<c1> \"];\n"));
+        assert!(dot.contains("    n2 [label=\"10\"];\n"));
+        assert_eq!(dot.matches(" -> ").count(), 6);
+
+        let options = RenderOptions {
+            max_depth: Some(0),
+            highlight: Some(40),
+        };
+        let dot = sample_tree().to_dot_with(&options);
+        assert!(dot.contains("n0 [label=\"<c0> |\\<40\\>|<c1> \", color=red, penwidth=2];"));
+        assert_eq!(dot.matches("... 2 subtrees").count(), 1);
+    }
+
+    #[test]
+    fn test_escape_record() {
+        let mut tree = Btree::new(3);
+        tree.insert("a|b".to_string(), 1);
+        assert!(tree.to_dot().contains(r#"[label="\"a\|b\""]"#));
+    }
+}
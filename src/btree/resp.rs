@@ -0,0 +1,232 @@
+use std::io::{self, BufRead, Read, Write};
+
+/// 1つの文字列の長さの上限（Redisと同じ512MiB）
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// 配列を入れ子にできる深さの上限
+const MAX_DEPTH: usize = 8;
+
+/// RESP（Redisのプロトコル）の値
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// `+OK`のような改行を含まない文字列
+    Simple(String),
+    /// `-ERR ...`
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    /// 存在しないことを表す`$-1`
+    Null,
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Value::Simple(text) => write!(writer, "+{text}\r\n"),
+            Value::Error(text) => write!(writer, "-{text}\r\n"),
+            Value::Integer(n) => write!(writer, ":{n}\r\n"),
+            Value::Bulk(bytes) => {
+                write!(writer, "${}\r\n", bytes.len())?;
+                writer.write_all(bytes)?;
+                writer.write_all(b"\r\n")
+            }
+            Value::Null => writer.write_all(b"$-1\r\n"),
+            Value::Array(items) => {
+                write!(writer, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.write_to(writer))
+            }
+        }
+    }
+
+    /// 値を1つ読む（接続が閉じていれば`None`）
+    pub fn read_from(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+        match read_line(reader)? {
+            None => Ok(None),
+            Some(line) => parse(reader, line, 0).map(Some),
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// `\r\n`で終わる1行を、改行を除いて読む
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = vec![];
+    // 長すぎる行で際限なく確保しないよう、上限までしか読まない
+    reader
+        .take(MAX_BULK_LEN as u64)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    match line.strip_suffix(b"\r\n").or(line.strip_suffix(b"\n")) {
+        Some(content) => Ok(Some(content.to_vec())),
+        None => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+fn parse_length(text: &[u8]) -> io::Result<i64> {
+    std::str::from_utf8(text)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| invalid("invalid length"))
+}
+
+fn parse(reader: &mut impl BufRead, line: Vec<u8>, depth: usize) -> io::Result<Value> {
+    let Some((&kind, rest)) = line.split_first() else {
+        return Err(invalid("empty line"));
+    };
+    let text = || String::from_utf8_lossy(rest).into_owned();
+    match kind {
+        b'+' => Ok(Value::Simple(text())),
+        b'-' => Ok(Value::Error(text())),
+        b':' => Ok(Value::Integer(parse_length(rest)?)),
+        b'$' => {
+            let len = parse_length(rest)?;
+            if len < 0 {
+                return Ok(Value::Null);
+            }
+            let len = len as usize;
+            if len > MAX_BULK_LEN {
+                return Err(invalid("bulk string too long"));
+            }
+            let mut bytes = vec![];
+            reader.take(len as u64 + 2).read_to_end(&mut bytes)?;
+            if bytes.len() != len + 2 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if !bytes.ends_with(b"\r\n") {
+                return Err(invalid("bulk string not terminated by CRLF"));
+            }
+            bytes.truncate(len);
+            Ok(Value::Bulk(bytes))
+        }
+        b'*' => {
+            let len = parse_length(rest)?;
+            if len < 0 {
+                return Ok(Value::Null);
+            }
+            if depth >= MAX_DEPTH {
+                return Err(invalid("arrays nested too deeply"));
+            }
+            let mut items = vec![];
+            for _ in 0..len {
+                let line = read_line(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+                items.push(parse(reader, line, depth + 1)?);
+            }
+            Ok(Value::Array(items))
+        }
+        _ => Err(invalid("unknown value type")),
+    }
+}
+
+/// コマンドを1つ読み、引数の列を返す（接続が閉じていれば`None`）
+///
+/// 文字列の配列の他に、`telnet`などから打ち込める空白区切りの1行も受け付ける。
+pub(crate) fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    loop {
+        let Some(line) = read_line(reader)? else {
+            return Ok(None);
+        };
+        if line.first() != Some(&b'*') {
+            let args: Vec<Vec<u8>> = line
+                .split(|b| b.is_ascii_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect();
+            if args.is_empty() {
+                continue;
+            }
+            return Ok(Some(args));
+        }
+        let Value::Array(items) = parse(reader, line, 0)? else {
+            return Err(invalid("expected an array of bulk strings"));
+        };
+        let args: Vec<Vec<u8>> = items
+            .into_iter()
+            .map(|item| match item {
+                Value::Bulk(bytes) => Ok(bytes),
+                _ => Err(invalid("expected an array of bulk strings")),
+            })
+            .collect::<io::Result<_>>()?;
+        // 空の配列は、空行と同じく読み飛ばす
+        if !args.is_empty() {
+            return Ok(Some(args));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut bytes = vec![];
+        value.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = Value::Array(vec![
+            Value::Simple("OK".to_string()),
+            Value::Error("ERR no".to_string()),
+            Value::Integer(-3),
+            Value::Bulk(b"a\r\nb".to_vec()),
+            Value::Null,
+            Value::Array(vec![]),
+        ]);
+        let bytes = encode(&value);
+        assert_eq!(
+            bytes,
+            b"*6\r\n+OK\r\n-ERR no\r\n:-3\r\n$4\r\na\r\nb\r\n$-1\r\n*0\r\n".to_vec()
+        );
+        let mut reader = bytes.as_slice();
+        assert_eq!(Value::read_from(&mut reader).unwrap(), Some(value));
+        assert_eq!(Value::read_from(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_command() {
+        let mut input: &[u8] = b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n\r\nSET  t k v\r\n";
+        assert_eq!(
+            read_command(&mut input).unwrap(),
+            Some(vec![b"GET".to_vec(), b"k".to_vec()])
+        );
+        assert_eq!(
+            read_command(&mut input).unwrap(),
+            Some(vec![
+                b"SET".to_vec(),
+                b"t".to_vec(),
+                b"k".to_vec(),
+                b"v".to_vec()
+            ])
+        );
+        assert_eq!(read_command(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        let cases: [&[u8]; 5] = [
+            b"$5\r\nab\r\n",
+            b"$2\r\nabcd\r\n",
+            b"*2\r\n$1\r\na\r\n",
+            b"?x\r\n",
+            b":abc\r\n",
+        ];
+        for case in cases {
+            let mut reader = case;
+            assert!(Value::read_from(&mut reader).is_err(), "{case:?}");
+        }
+        assert!(read_command(&mut &b"*1\r\n:1\r\n"[..]).is_err());
+        assert_eq!(
+            read_command(&mut &b"*0\r\n*1\r\n$4\r\nPING\r\n"[..]).unwrap(),
+            Some(vec![b"PING".to_vec()])
+        );
+        assert_eq!(read_command(&mut &b"*0\r\n"[..]).unwrap(), None);
+        let nested = "*1\r\n".repeat(MAX_DEPTH + 1);
+        assert!(Value::read_from(&mut nested.as_bytes()).is_err());
+    }
+}
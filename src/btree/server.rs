@@ -0,0 +1,348 @@
+use crate::btree::resp::{self, Value};
+use crate::btree::tree::Btree;
+use crate::btree::{Delete, Insert, Search};
+use std::collections::HashMap;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+type Tree = Btree<Vec<u8>, Vec<u8>>;
+
+/// `SCAN`で`COUNT`を省略した場合に返すエントリの数
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// 接続の間で共有する、名前を付けた木の集まり
+struct State {
+    trees: RwLock<HashMap<Vec<u8>, Tree>>,
+    max_count: usize,
+}
+
+/// 名前を付けた`Btree`を、Redisと同じ形式（RESP）のコマンドで読み書きさせるサーバー
+///
+/// キー・値・木の名前はバイト列で、木は最初の`SET`で作られる。
+///
+/// | コマンド | 応答 |
+/// |---|---|
+/// | `PING` | `PONG` |
+/// | `GET tree key` | 値、無ければnull |
+/// | `SET tree key value` | `OK` |
+/// | `DEL tree key` | 削除したら1、無ければ0 |
+/// | `RANGE tree min max [LIMIT n]` | キーと値を交互に並べた配列 |
+/// | `SCAN tree cursor [COUNT n]` | 次のカーソルと、キーと値を交互に並べた配列 |
+/// | `TREES` | 木の名前の配列 |
+/// | `QUIT` | `OK`を返して接続を閉じる |
+///
+/// `RANGE`の範囲はRedisの`ZRANGEBYLEX`と同じく、`[key`（含む）、`(key`（含まない）、
+/// `-`（最小）、`+`（最大）で指定する。`SCAN`は`0`から始め、返されたカーソルが`0`になるまで続ける。
+pub struct Server {
+    listener: TcpListener,
+    state: Arc<State>,
+}
+
+impl Server {
+    /// `addr`で接続を待ち受ける（ポートが0なら空いているポートを使う）
+    ///
+    /// `max_count`が3未満なら`InvalidInput`のエラーを返す。
+    pub fn bind(addr: impl ToSocketAddrs, max_count: usize) -> io::Result<Self> {
+        if max_count < 3 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "max_count must be at least 3",
+            ));
+        }
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            state: Arc::new(State {
+                trees: RwLock::new(HashMap::new()),
+                max_count,
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// 接続を受け付け続け、接続ごとにスレッドを作ってコマンドを処理する
+    pub fn run(&self) {
+        for stream in self.listener.incoming() {
+            // 受け付けに失敗した接続は捨てて、次を待つ
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let state = Arc::clone(&self.state);
+            thread::spawn(move || {
+                // 接続が切れたら、そのスレッドを終える
+                let _ = serve(&state, stream);
+            });
+        }
+    }
+}
+
+fn serve(state: &State, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        let args = match resp::read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                // どこまでが1つのコマンドか分からなくなったので、接続を閉じる
+                Value::Error(format!("ERR protocol error: {e}")).write_to(&mut writer)?;
+                return writer.flush();
+            }
+            Err(e) => return Err(e),
+        };
+        let quit = matches!(args.split_first(), Some((command, _)) if command.eq_ignore_ascii_case(b"QUIT"));
+        execute(state, &args).write_to(&mut writer)?;
+        writer.flush()?;
+        if quit {
+            return Ok(());
+        }
+    }
+}
+
+fn error(message: impl std::fmt::Display) -> Value {
+    Value::Error(format!("ERR {message}"))
+}
+
+/// `RANGE`の端を読む（`None`は何も含まない範囲を表す）
+fn parse_bound(arg: &[u8], is_start: bool) -> Result<Option<Bound<Vec<u8>>>, Value> {
+    match arg {
+        b"-" if is_start => Ok(Some(Bound::Unbounded)),
+        b"+" if !is_start => Ok(Some(Bound::Unbounded)),
+        b"-" | b"+" => Ok(None),
+        [b'[', key @ ..] => Ok(Some(Bound::Included(key.to_vec()))),
+        [b'(', key @ ..] => Ok(Some(Bound::Excluded(key.to_vec()))),
+        _ => Err(error(
+            "range bound must start with '[' or '(', or be '-' or '+'",
+        )),
+    }
+}
+
+/// `NAME n`の形のオプションを読む
+fn parse_option(args: &[Vec<u8>], name: &str, default: usize) -> Result<usize, Value> {
+    match args {
+        [] => Ok(default),
+        [option, n] if option.eq_ignore_ascii_case(name.as_bytes()) => std::str::from_utf8(n)
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| error("value is not an integer or out of range")),
+        _ => Err(error("syntax error")),
+    }
+}
+
+fn entries<'a>(iter: impl Iterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>) -> Vec<Value> {
+    iter.flat_map(|(key, value)| [Value::Bulk(key.clone()), Value::Bulk(value.clone())])
+        .collect()
+}
+
+fn execute(state: &State, args: &[Vec<u8>]) -> Value {
+    let Some((command, args)) = args.split_first() else {
+        return error("empty command");
+    };
+    let command = String::from_utf8_lossy(command).to_ascii_uppercase();
+    let result = match (command.as_str(), args) {
+        ("PING", []) => Ok(Value::Simple("PONG".to_string())),
+        ("QUIT", []) => Ok(Value::Simple("OK".to_string())),
+        ("TREES", []) => {
+            let trees = state.trees.read().unwrap();
+            let mut names: Vec<&Vec<u8>> = trees.keys().collect();
+            names.sort();
+            Ok(Value::Array(
+                names
+                    .into_iter()
+                    .map(|name| Value::Bulk(name.clone()))
+                    .collect(),
+            ))
+        }
+        ("GET", [tree, key]) => {
+            let trees = state.trees.read().unwrap();
+            Ok(match trees.get(tree).and_then(|tree| tree.search(key)) {
+                Some((_, value)) => Value::Bulk(value),
+                None => Value::Null,
+            })
+        }
+        ("SET", [tree, key, value]) => {
+            let mut trees = state.trees.write().unwrap();
+            trees
+                .entry(tree.clone())
+                .or_insert_with(|| Tree::new(state.max_count))
+                .insert(key.clone(), value.clone());
+            Ok(Value::Simple("OK".to_string()))
+        }
+        ("DEL", [tree, key]) => {
+            let mut trees = state.trees.write().unwrap();
+            let deleted = match trees.get_mut(tree) {
+                Some(tree) if tree.search(key).is_some() => {
+                    tree.delete(key);
+                    1
+                }
+                _ => 0,
+            };
+            Ok(Value::Integer(deleted))
+        }
+        ("RANGE", [tree, min, max, options @ ..]) => (|| {
+            let start = parse_bound(min, true)?;
+            let end = parse_bound(max, false)?;
+            let limit = parse_option(options, "LIMIT", usize::MAX)?;
+            let trees = state.trees.read().unwrap();
+            let (Some(tree), Some(start), Some(end)) = (trees.get(tree), start, end) else {
+                return Ok(Value::Array(vec![]));
+            };
+            Ok(Value::Array(entries(tree.range((start, end)).take(limit))))
+        })(),
+        ("SCAN", [tree, cursor, options @ ..]) => (|| {
+            let start = match cursor.as_slice() {
+                b"0" => Bound::Unbounded,
+                [b'(', key @ ..] => Bound::Excluded(key.to_vec()),
+                _ => return Err(error("invalid cursor")),
+            };
+            let count = parse_option(options, "COUNT", DEFAULT_SCAN_COUNT)?.max(1);
+            let trees = state.trees.read().unwrap();
+            let Some(tree) = trees.get(tree) else {
+                return Ok(Value::Array(vec![
+                    Value::Bulk(b"0".to_vec()),
+                    Value::Array(vec![]),
+                ]));
+            };
+            // 1つ多く読んで、続きがあるかを調べる
+            let page: Vec<_> = tree
+                .range((start, Bound::Unbounded))
+                .take(count + 1)
+                .collect();
+            let next = match page.get(count) {
+                Some(_) => [b"(".as_slice(), page[count - 1].0].concat(),
+                None => b"0".to_vec(),
+            };
+            Ok(Value::Array(vec![
+                Value::Bulk(next),
+                Value::Array(entries(page.into_iter().take(count))),
+            ]))
+        })(),
+        ("PING" | "QUIT" | "TREES" | "GET" | "SET" | "DEL" | "RANGE" | "SCAN", _) => {
+            Err(error(format_args!(
+                "wrong number of arguments for '{}'",
+                command.to_ascii_lowercase()
+            )))
+        }
+        _ => Err(error(format_args!("unknown command '{command}'"))),
+    };
+    result.unwrap_or_else(|error| error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State {
+        State {
+            trees: RwLock::new(HashMap::new()),
+            max_count: 3,
+        }
+    }
+
+    fn run(state: &State, command: &str) -> Value {
+        let args: Vec<Vec<u8>> = command
+            .split_whitespace()
+            .map(|word| word.as_bytes().to_vec())
+            .collect();
+        execute(state, &args)
+    }
+
+    fn bulks(words: &[&str]) -> Value {
+        Value::Array(
+            words
+                .iter()
+                .map(|w| Value::Bulk(w.as_bytes().to_vec()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_get_set_del() {
+        let state = state();
+        assert_eq!(run(&state, "get t a"), Value::Null);
+        assert_eq!(run(&state, "SET t a 1"), Value::Simple("OK".to_string()));
+        assert_eq!(run(&state, "GET t a"), Value::Bulk(b"1".to_vec()));
+        assert_eq!(run(&state, "GET u a"), Value::Null);
+        assert_eq!(run(&state, "DEL t a"), Value::Integer(1));
+        assert_eq!(run(&state, "DEL t a"), Value::Integer(0));
+        assert_eq!(run(&state, "SET u b 2"), Value::Simple("OK".to_string()));
+        assert_eq!(run(&state, "TREES"), bulks(&["t", "u"]));
+    }
+
+    #[test]
+    fn test_range_bounds() {
+        let state = state();
+        for key in ["a", "b", "c", "d"] {
+            run(&state, &format!("SET t {key} {key}{key}"));
+        }
+        assert_eq!(run(&state, "RANGE t [b (d"), bulks(&["b", "bb", "c", "cc"]));
+        assert_eq!(run(&state, "RANGE t (b +"), bulks(&["c", "cc", "d", "dd"]));
+        assert_eq!(run(&state, "RANGE t - + LIMIT 1"), bulks(&["a", "aa"]));
+        assert_eq!(run(&state, "RANGE t + -"), bulks(&[]));
+        assert_eq!(run(&state, "RANGE missing - +"), bulks(&[]));
+        assert!(matches!(run(&state, "RANGE t b d"), Value::Error(_)));
+        assert!(matches!(
+            run(&state, "RANGE t - + LIMIT x"),
+            Value::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_scan_pages() {
+        let state = state();
+        for i in 0..25 {
+            run(&state, &format!("SET t k{i:02} v"));
+        }
+        let mut cursor = b"0".to_vec();
+        let mut keys = vec![];
+        let mut pages = 0;
+        loop {
+            let args = vec![
+                b"SCAN".to_vec(),
+                b"t".to_vec(),
+                cursor,
+                b"COUNT".to_vec(),
+                b"10".to_vec(),
+            ];
+            let Value::Array(reply) = execute(&state, &args) else {
+                panic!("unexpected reply");
+            };
+            let [Value::Bulk(next), Value::Array(page)] = reply.as_slice() else {
+                panic!("unexpected reply");
+            };
+            keys.extend(page.iter().step_by(2).cloned());
+            pages += 1;
+            cursor = next.clone();
+            if cursor == b"0" {
+                break;
+            }
+        }
+        assert_eq!(pages, 3);
+        let expected: Vec<String> = (0..25).map(|i| format!("k{i:02}")).collect();
+        let expected: Vec<&str> = expected.iter().map(String::as_str).collect();
+        assert_eq!(Value::Array(keys), bulks(&expected));
+    }
+
+    #[test]
+    fn test_errors() {
+        let state = state();
+        assert_eq!(
+            run(&state, "FLY t"),
+            Value::Error("ERR unknown command 'FLY'".to_string())
+        );
+        assert_eq!(
+            run(&state, "get t"),
+            Value::Error("ERR wrong number of arguments for 'get'".to_string())
+        );
+        assert!(matches!(run(&state, "SCAN t 5"), Value::Error(_)));
+        assert_eq!(
+            execute(&state, &[]),
+            Value::Error("ERR empty command".to_string())
+        );
+    }
+}
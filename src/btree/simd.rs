@@ -0,0 +1,169 @@
+use std::any::TypeId;
+
+/// 一度に比較するキーのバイト数（AVX2のレジスタ1つ分）
+///
+/// 分岐のない比較をこの幅ずつ並べることで、コンパイラがSIMD命令の比較にまとめる。
+const CHUNK_BYTES: usize = 32;
+
+/// これより少ないキーはまとめずに比較する
+///
+/// まとめて比較して速くなるのは、`max_count`が64以上の幅の広いノード（`Btree::fixed::<64>()`など）だけ。
+/// 小さいノードでは汎用の探索より遅く、この値を下げても速くならなかった（READMEの`node_search`の結果）。
+const SCALAR_LIMIT: usize = 16;
+
+/// 整数のキーなら、ノード内のキーをまとめて比較して`key`の位置を探す
+///
+/// `T`が対応する整数型でなければ`None`を返すので、呼び出し側で汎用の探索を使う。
+/// `keys`は昇順に並んでいる必要がある。
+pub(crate) fn lookup_integer<T: 'static>(keys: &[T], key: &T) -> Option<Result<usize, usize>> {
+    lookup_as::<T, u64>(keys, key)
+        .or_else(|| lookup_as::<T, i64>(keys, key))
+        .or_else(|| lookup_as::<T, u32>(keys, key))
+        .or_else(|| lookup_as::<T, i32>(keys, key))
+        .or_else(|| lookup_as::<T, usize>(keys, key))
+        .or_else(|| lookup_as::<T, isize>(keys, key))
+}
+
+fn lookup_as<T: 'static, U: 'static + Copy + Ord>(
+    keys: &[T],
+    key: &T,
+) -> Option<Result<usize, usize>> {
+    let keys = cast::<T, U>(keys)?;
+    let key = cast::<T, U>(std::slice::from_ref(key))?[0];
+    Some(lookup(keys, key))
+}
+
+/// `T`と`U`が同じ型の場合だけ、スライスを`U`のスライスとして見る
+fn cast<T: 'static, U: 'static>(slice: &[T]) -> Option<&[U]> {
+    if TypeId::of::<T>() != TypeId::of::<U>() {
+        return None;
+    }
+    // SAFETY: `T`と`U`は同じ型
+    Some(unsafe { std::slice::from_raw_parts(slice.as_ptr().cast::<U>(), slice.len()) })
+}
+
+fn lookup<T: Copy + Ord>(keys: &[T], key: T) -> Result<usize, usize> {
+    // `key`より小さいキーの数が、`key`と等しい最初のキーか挿入位置になる
+    let index = count_less_dispatch(keys, key);
+    if keys.get(index) == Some(&key) {
+        Ok(index)
+    } else {
+        Err(index)
+    }
+}
+
+fn count_less_dispatch<T: Copy + Ord>(keys: &[T], key: T) -> usize {
+    if keys.len() < SCALAR_LIMIT {
+        // 少ないキーは、命令を切り替える手間より1つずつ比較する方が速い
+        return keys.iter().take_while(|&&k| k < key).count();
+    }
+    #[cfg(target_arch = "x86_64")]
+    if std::arch::is_x86_feature_detected!("avx2") {
+        // SAFETY: 実行中のCPUがAVX2に対応していることを確認済み
+        return unsafe { count_less_avx2(keys, key) };
+    }
+    #[cfg(target_arch = "x86_64")]
+    if std::arch::is_x86_feature_detected!("sse4.2") {
+        // SAFETY: 実行中のCPUがSSE4.2に対応していることを確認済み
+        return unsafe { count_less_sse42(keys, key) };
+    }
+    // aarch64ではNEONが常に使えるので、ここでもまとめて比較される
+    count_less(keys, key)
+}
+
+/// 64ビット整数をまとめて比較できるAVX2を有効にして`count_less`をコンパイルする
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+fn count_less_avx2<T: Copy + Ord>(keys: &[T], key: T) -> usize {
+    count_less(keys, key)
+}
+
+/// 64ビット整数を2つずつ比較できるSSE4.2を有効にして`count_less`をコンパイルする
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+fn count_less_sse42<T: Copy + Ord>(keys: &[T], key: T) -> usize {
+    count_less(keys, key)
+}
+
+/// 昇順に並んだ`keys`のうち、`key`より小さいものの数
+#[inline(always)]
+fn count_less<T: Copy + Ord>(keys: &[T], key: T) -> usize {
+    let lanes = CHUNK_BYTES / std::mem::size_of::<T>();
+    let mut count = 0;
+    let mut chunks = keys.chunks_exact(lanes);
+    for chunk in &mut chunks {
+        let less: usize = chunk.iter().map(|&k| usize::from(k < key)).sum();
+        count += less;
+        if less < lanes {
+            // このまとまりの中に`key`以上のキーがあるので、後ろは全て`key`以上
+            return count;
+        }
+    }
+    count
+        + chunks
+            .remainder()
+            .iter()
+            .map(|&k| usize::from(k < key))
+            .sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::test_util::XorShift;
+
+    /// 比較を1つずつ行う探索
+    fn scalar_lookup<T: Ord>(keys: &[T], key: &T) -> Result<usize, usize> {
+        match keys.iter().position(|k| k >= key) {
+            Some(i) if keys[i] == *key => Ok(i),
+            Some(i) => Err(i),
+            None => Err(keys.len()),
+        }
+    }
+
+    #[test]
+    fn test_unsupported_type() {
+        let keys = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(lookup_integer(&keys, &"b".to_string()), None);
+        assert_eq!(lookup_integer(&[1u8, 2], &2), None);
+    }
+
+    #[test]
+    fn test_matches_scalar_lookup() {
+        let mut rng = XorShift::default();
+        for len in 0..40 {
+            let mut keys: Vec<i64> = (0..len)
+                .map(|_| (rng.next_u64() % 50) as i64 - 25)
+                .collect();
+            keys.sort();
+            for key in -27..27 {
+                assert_eq!(
+                    lookup_integer(&keys, &key),
+                    Some(scalar_lookup(&keys, &key))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_extreme_values() {
+        let keys = vec![i64::MIN, -1, 0, 1, i64::MAX];
+        for key in [i64::MIN, i64::MIN + 1, -1, 0, 2, i64::MAX - 1, i64::MAX] {
+            assert_eq!(
+                lookup_integer(&keys, &key),
+                Some(scalar_lookup(&keys, &key))
+            );
+        }
+        // 符号なしの大きな値を負の数として比較しない
+        let keys: Vec<u64> = (0..20).map(|i| u64::MAX - 40 + i * 2).collect();
+        for key in [0, u64::MAX / 2, u64::MAX - 40, u64::MAX - 3, u64::MAX] {
+            assert_eq!(
+                lookup_integer(&keys, &key),
+                Some(scalar_lookup(&keys, &key))
+            );
+        }
+        let keys: Vec<u32> = vec![0, 1, u32::MAX / 2 + 1, u32::MAX];
+        assert_eq!(lookup_integer(&keys, &u32::MAX), Some(Ok(3)));
+        assert_eq!(lookup_integer(&keys, &(u32::MAX / 2)), Some(Err(2)));
+    }
+}
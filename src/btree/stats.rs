@@ -0,0 +1,168 @@
+use crate::btree::node::BtreeNode;
+use crate::btree::tree::Btree;
+
+/// 木を作ってから行ったノードの分割・併合・回転の回数
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RestructureCounts {
+    pub splits: u64,
+    pub merges: u64,
+    pub rotations: u64,
+}
+
+/// ヒープ上の使用量の見積もり（バイト数）
+///
+/// `Vec`の確保済みの容量で数える。キーや値自身が持つヒープ領域（`String`の中身など）は含まない。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub keys: usize,
+    pub values: usize,
+    /// ノード本体と子ノードへのポインタ
+    pub nodes: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.keys + self.values + self.nodes
+    }
+}
+
+/// `Btree::stats`の結果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TreeStats {
+    pub height: usize,
+    /// 深さごとのノード数（根が先頭）
+    pub nodes_per_level: Vec<usize>,
+    pub leaves: usize,
+    pub keys: usize,
+    /// ノードのキーの数を最大数で割った値の平均・最小・最大
+    pub average_fill: f64,
+    pub min_fill: f64,
+    pub max_fill: f64,
+    pub memory: MemoryUsage,
+    pub restructures: RestructureCounts,
+}
+
+impl TreeStats {
+    pub fn nodes(&self) -> usize {
+        self.nodes_per_level.iter().sum()
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> BtreeNode<K, V> {
+    fn collect_stats(&self, depth: usize, capacity: usize, stats: &mut TreeStats) {
+        if stats.nodes_per_level.len() <= depth {
+            stats.nodes_per_level.push(0);
+        }
+        stats.nodes_per_level[depth] += 1;
+        stats.keys += self.keys().len();
+
+        let fill = self.keys().len() as f64 / capacity as f64;
+        stats.average_fill += fill;
+        stats.min_fill = stats.min_fill.min(fill);
+        stats.max_fill = stats.max_fill.max(fill);
+
+        let memory = self.memory();
+        stats.memory.keys += memory.keys;
+        stats.memory.values += memory.values;
+        stats.memory.nodes += memory.nodes;
+
+        if self.is_leaf() {
+            stats.leaves += 1;
+        }
+        for child in self.children() {
+            child.collect_stats(depth + 1, capacity, stats);
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    /// 木の形とメモリ使用量の統計を集める
+    ///
+    /// 全てのノードをたどるので、ノード数に比例した時間がかかる。
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            min_fill: f64::INFINITY,
+            restructures: self.restructures(),
+            ..TreeStats::default()
+        };
+        let Some(root) = self.root() else {
+            stats.min_fill = 0.0;
+            return stats;
+        };
+        root.collect_stats(0, self.max_count() - 1, &mut stats);
+        // 根は`Btree`に直接置かれているので、ヒープ上にはない
+        stats.memory.nodes -= std::mem::size_of::<BtreeNode<K, V>>();
+        stats.height = stats.nodes_per_level.len();
+        stats.average_fill /= stats.nodes() as f64;
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{Delete, Insert};
+    use std::mem::size_of;
+
+    #[test]
+    fn test_empty_stats() {
+        let tree: Btree<i32, i32> = Btree::new(3);
+        let stats = tree.stats();
+        assert_eq!(stats.height, 0);
+        assert_eq!(stats.nodes(), 0);
+        assert_eq!(stats.min_fill, 0.0);
+        assert_eq!(stats.memory.total(), 0);
+    }
+
+    #[test]
+    fn test_shape() {
+        let mut tree = Btree::new(3);
+        for i in 1..=7 {
+            tree.insert(i * 10, i);
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.height, 3);
+        assert_eq!(stats.nodes_per_level, vec![1, 2, 4]);
+        assert_eq!(stats.leaves, 4);
+        assert_eq!(stats.keys, 7);
+        assert_eq!(stats.min_fill, 0.5);
+        assert_eq!(stats.max_fill, 0.5);
+        assert_eq!(stats.average_fill, 0.5);
+        assert!(stats.memory.keys >= 7 * size_of::<i32>());
+        assert!(stats.memory.values >= 7 * size_of::<i32>());
+        assert!(stats.memory.nodes >= 6 * size_of::<BtreeNode<i32, i32>>());
+    }
+
+    #[test]
+    fn test_restructure_counts() {
+        let mut tree = Btree::new(3);
+        for i in 1..=7 {
+            tree.insert(i * 10, i);
+        }
+        // 根の分割も1回と数える
+        assert_eq!(
+            tree.stats().restructures,
+            RestructureCounts {
+                splits: 4,
+                merges: 0,
+                rotations: 0
+            }
+        );
+
+        tree.insert(25, 0);
+        tree.delete(&10);
+        assert_eq!(tree.stats().restructures.rotations, 1);
+        tree.delete(&20);
+        tree.delete(&25);
+        assert!(tree.stats().restructures.merges >= 1);
+
+        for i in 1..=7 {
+            tree.delete(&(i * 10));
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.keys, 0);
+        assert!(stats.restructures.merges >= 2);
+    }
+}
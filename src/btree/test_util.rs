@@ -0,0 +1,92 @@
+use crate::btree::node::{NodeStorage, NodeVec};
+use crate::btree::{Delete, Insert, Search};
+use std::collections::BTreeMap;
+
+/// テストで操作列を作るための、シードで決まる擬似乱数（xorshift64）
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    /// `seed`が0だと0しか返さないので、0以外を渡す
+    pub(crate) fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "xorshift seed must not be zero");
+        XorShift(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+impl Default for XorShift {
+    fn default() -> Self {
+        XorShift::new(0x9E37_79B9_7F4A_7C15)
+    }
+}
+
+/// 挿入と削除を混ぜて行い、どのキーの検索結果も`BTreeMap`と一致することを確かめる
+pub(crate) fn random_operations<T: Insert<i32, i32> + Delete<i32> + Search<i32, i32>>(
+    tree: &mut T,
+) {
+    let mut expected = BTreeMap::new();
+    let mut rng = XorShift::default();
+    for step in 0..5000 {
+        let seed = rng.next_u64();
+        let key = (seed % 400) as i32;
+        if seed.is_multiple_of(3) {
+            tree.delete(&key);
+            expected.remove(&key);
+        } else {
+            tree.insert(key, step);
+            expected.insert(key, step);
+        }
+    }
+    for key in 0..400 {
+        assert_eq!(
+            tree.search(&key).map(|(_, v)| v),
+            expected.get(&key).copied()
+        );
+    }
+}
+
+/// `node`を根とする部分木のキーの順序と要素数を検証し、葉までの深さを返す
+///
+/// キーが`max_count`個になったら分割する木として検証する。`child`は子ノードの参照からノードを引く。
+pub(crate) fn check_node<'a, N: NodeStorage<Key: PartialOrd>>(
+    node: &'a N,
+    child: &impl Fn(&'a N::Child) -> &'a N,
+    max_count: usize,
+    is_root: bool,
+) -> usize {
+    let (keys, values, children) = node.parts();
+    let (keys, children) = (keys.as_slice(), children.as_slice());
+    assert!(keys.len() < max_count);
+    assert_eq!(keys.len(), values.as_slice().len());
+    if !is_root {
+        assert!(keys.len() >= (max_count - 1) / 2);
+    }
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    if children.is_empty() {
+        return 0;
+    }
+    assert_eq!(children.len(), keys.len() + 1);
+    let depths: Vec<usize> = children
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let c = child(c);
+            let child_keys = c.parts().0.as_slice();
+            if i > 0 {
+                assert!(child_keys.iter().all(|k| *k > keys[i - 1]));
+            }
+            if i < keys.len() {
+                assert!(child_keys.iter().all(|k| *k < keys[i]));
+            }
+            check_node(c, child, max_count, false)
+        })
+        .collect();
+    assert!(depths.windows(2).all(|w| w[0] == w[1]));
+    depths[0] + 1
+}
@@ -0,0 +1,279 @@
+use crate::btree::iter::Range;
+use crate::btree::tree::{Btree, RUNTIME_CAPACITY};
+use crate::btree::{Delete, Insert, Search};
+use std::iter::Peekable;
+use std::ops::RangeBounds;
+
+/// `Btree`への書き込みをまとめて適用するトランザクション
+///
+/// 書き込みは木に直接反映せず、オーバーレイ（`None`は削除を表す）に溜めておく。
+/// 読み込みはオーバーレイを優先して元の木と合わせて返すので、自分の書き込みが見える。
+/// `commit`するまで元の木は変わらず、`rollback`するかそのまま破棄すればすべての書き込みが取り消される。
+pub struct Transaction<
+    'a,
+    K: 'static + Clone + PartialEq + PartialOrd,
+    V: 'static + Clone,
+    const B: usize = RUNTIME_CAPACITY,
+> {
+    base: &'a mut Btree<K, V, B>,
+    overlay: Btree<K, Option<V>, B>,
+}
+
+/// トランザクションから見えるエントリを、キーの昇順にたどるイテレータ
+pub struct TransactionRange<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+    base: Peekable<Range<'a, K, V>>,
+    overlay: Peekable<Range<'a, K, Option<V>>>,
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    /// この木に対するトランザクションを始める
+    pub fn transaction(&mut self) -> Transaction<'_, K, V, B> {
+        let max_count = self.max_count();
+        Transaction {
+            base: self,
+            overlay: Btree::with_max_count(max_count),
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Transaction<'_, K, V, B>
+{
+    pub fn search(&self, key: &K) -> Option<(K, V)> {
+        match self.overlay.search(key) {
+            Some((key, Some(value))) => Some((key, value)),
+            Some((_, None)) => None,
+            None => self.base.search(key),
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.overlay.insert(key, Some(value));
+    }
+
+    pub fn delete(&mut self, key: &K) {
+        self.overlay.insert(key.clone(), None);
+    }
+
+    pub fn iter(&self) -> TransactionRange<'_, K, V> {
+        self.range(..)
+    }
+
+    pub fn range<R: RangeBounds<K> + Clone>(&self, range: R) -> TransactionRange<'_, K, V> {
+        TransactionRange {
+            base: self.base.range(range.clone()).peekable(),
+            overlay: self.overlay.range(range).peekable(),
+        }
+    }
+
+    /// 溜めておいた書き込みを元の木に反映する
+    ///
+    /// 履歴を記録していれば、反映した書き込みは1回の`undo`でまとめて取り消せる。
+    /// 購読者には、書き込みごとにキーの昇順で通知する。
+    pub fn commit(self) {
+        let overlay = self.overlay;
+        self.base.record_as_one(|base| {
+            for (key, value) in overlay.iter() {
+                match value {
+                    Some(value) => base.insert(key.clone(), value.clone()),
+                    None => base.delete(key),
+                }
+            }
+        });
+    }
+
+    /// `validate`が成功した場合だけ書き込みを反映し、失敗した場合はすべて取り消す
+    pub fn commit_with<E>(self, validate: impl FnOnce(&Self) -> Result<(), E>) -> Result<(), E> {
+        match validate(&self) {
+            Ok(()) => {
+                self.commit();
+                Ok(())
+            }
+            Err(error) => {
+                self.rollback();
+                Err(error)
+            }
+        }
+    }
+
+    /// 溜めておいた書き込みを捨てる
+    pub fn rollback(self) {}
+}
+
+impl<'a, K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Iterator
+    for TransactionRange<'a, K, V>
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let take_overlay = match (self.base.peek(), self.overlay.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => false,
+                (None, Some(_)) => true,
+                (Some((base_key, _)), Some((overlay_key, _))) => {
+                    if base_key == overlay_key {
+                        // 同じキーはオーバーレイの書き込みが優先される
+                        self.base.next();
+                        true
+                    } else {
+                        overlay_key < base_key
+                    }
+                }
+            };
+
+            if !take_overlay {
+                return self.base.next();
+            }
+            if let Some((key, Some(value))) = self.overlay.next() {
+                return Some((key, value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_tree() -> Btree<i32, i32> {
+        let mut tree = Btree::new(3);
+        for i in 1..=10 {
+            tree.insert(i * 10, i * 100);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_reads_your_writes() {
+        let mut tree = sample_tree();
+        let mut tx = tree.transaction();
+
+        tx.insert(15, 150);
+        tx.insert(20, 201);
+        tx.delete(&30);
+
+        assert_eq!(tx.search(&15), Some((15, 150)));
+        assert_eq!(tx.search(&20), Some((20, 201)));
+        assert_eq!(tx.search(&30), None);
+        assert_eq!(tx.search(&40), Some((40, 400)));
+
+        // 削除したキーを再び挿入する
+        tx.insert(30, 301);
+        assert_eq!(tx.search(&30), Some((30, 301)));
+    }
+
+    #[test]
+    fn test_iter_merges_overlay() {
+        let mut tree = sample_tree();
+        let mut tx = tree.transaction();
+        tx.insert(5, 50);
+        tx.insert(55, 550);
+        tx.insert(100, 1001);
+        tx.delete(&10);
+        tx.delete(&60);
+        tx.delete(&65);
+
+        let entries: Vec<(i32, i32)> = tx.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(
+            entries,
+            vec![
+                (5, 50),
+                (20, 200),
+                (30, 300),
+                (40, 400),
+                (50, 500),
+                (55, 550),
+                (70, 700),
+                (80, 800),
+                (90, 900),
+                (100, 1001),
+            ]
+        );
+
+        let keys: Vec<i32> = tx.range(40..=70).map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![40, 50, 55, 70]);
+    }
+
+    #[test]
+    fn test_commit() {
+        let mut tree = sample_tree();
+        let mut tx = tree.transaction();
+        tx.insert(15, 150);
+        tx.delete(&20);
+        tx.commit();
+
+        assert_eq!(tree.search(&15), Some((15, 150)));
+        assert_eq!(tree.search(&20), None);
+        assert_eq!(tree.iter().count(), 10);
+    }
+
+    #[test]
+    fn test_commit_is_one_undo_unit() {
+        let mut tree = sample_tree();
+        tree.enable_history(3);
+        tree.insert(5, 50);
+        let before: Vec<_> = tree.iter().map(|(k, v)| (*k, *v)).collect();
+        let mut tx = tree.transaction();
+        for i in 1..=5 {
+            tx.insert(i * 10, 0);
+        }
+        tx.delete(&5);
+        tx.commit();
+
+        // 履歴の上限より多い書き込みでも、まとめて取り消せる
+        assert!(tree.undo());
+        assert_eq!(
+            tree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            before
+        );
+        assert!(tree.redo());
+        assert_eq!(tree.search(&5), None);
+        assert_eq!(tree.search(&30), Some((30, 0)));
+        // 上限を超えたので、それより前の書き込みは捨てられている
+        assert!(tree.undo());
+        assert_eq!(tree.search(&5), Some((5, 50)));
+        assert!(!tree.undo());
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut tree = sample_tree();
+        let mut tx = tree.transaction();
+        tx.insert(15, 150);
+        tx.delete(&20);
+        tx.rollback();
+
+        // 破棄しても同じ
+        let mut tx = tree.transaction();
+        tx.delete(&30);
+        drop(tx);
+
+        assert!(tree.iter().eq(sample_tree().iter()));
+    }
+
+    #[test]
+    fn test_commit_with_validation() {
+        let mut tree = sample_tree();
+
+        let mut tx = tree.transaction();
+        tx.insert(10, -1);
+        tx.insert(20, 2);
+        let result = tx.commit_with(|tx| {
+            if tx.iter().all(|(_, v)| *v >= 0) {
+                Ok(())
+            } else {
+                Err("negative value")
+            }
+        });
+        assert_eq!(result, Err("negative value"));
+        assert!(tree.iter().eq(sample_tree().iter()));
+
+        let mut tx = tree.transaction();
+        tx.insert(20, 2);
+        assert_eq!(tx.commit_with(|_| Ok::<(), ()>(())), Ok(()));
+        assert_eq!(tree.search(&20), Some((20, 2)));
+    }
+}
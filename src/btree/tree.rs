@@ -1,22 +1,134 @@
+use crate::btree::history::History;
+use crate::btree::iter::Range;
 use crate::btree::node::BtreeNode;
-use crate::btree::{Delete, Insert, Search};
+use crate::btree::observe::Observers;
+use crate::btree::stats::RestructureCounts;
+use crate::btree::{BinarySearch, Delete, Insert, Search};
+use std::ops::RangeBounds;
 
+/// `Btree`の`B`に指定すると、ノードの最大要素数を実行時に`Btree::new`で決める
+pub const RUNTIME_CAPACITY: usize = 0;
+
+/// B木
+///
+/// `B`はノードの最大要素数で、コンパイル時に決まる。
+/// 省略した場合（`RUNTIME_CAPACITY`）は`Btree::new`に渡した値を使う。
 #[derive(Clone)]
-pub struct Btree<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> {
+pub struct Btree<
+    K: 'static + Clone + PartialEq + PartialOrd,
+    V: 'static + Clone,
+    const B: usize = RUNTIME_CAPACITY,
+> {
     root: Option<BtreeNode<K, V>>,
     max_count: usize,
+    history: Option<History<K, V>>,
+    restructures: RestructureCounts,
+    observers: Observers<K, V>,
 }
 
 impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Btree<K, V> {
-    fn new(max_count: usize) -> Self {
+    /// ノードの最大要素数が`max_count`の木を作る
+    ///
+    /// # Panics
+    ///
+    /// `max_count`が3未満だとパニックする。
+    pub fn new(max_count: usize) -> Self {
+        assert!(max_count >= 3, "max_count must be at least 3");
+        Btree::with_max_count(max_count)
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    const VALID_CAPACITY: () = assert!(B >= 3, "B must be at least 3");
+
+    /// ノードの最大要素数が`B`の木を作る
+    ///
+    /// `B`が3未満の場合はコンパイルエラーになる。
+    ///
+    /// ```compile_fail
+    /// use btree_rust::Btree;
+    ///
+    /// let tree: Btree<i32, i32, 2> = Btree::fixed();
+    /// ```
+    pub fn fixed() -> Self {
+        let () = Self::VALID_CAPACITY;
+        Btree::with_max_count(B)
+    }
+
+    pub(crate) fn with_max_count(max_count: usize) -> Self {
         Btree {
             root: None,
             max_count,
+            history: None,
+            restructures: RestructureCounts::default(),
+            observers: Observers::default(),
+        }
+    }
+
+    pub(crate) fn max_count(&self) -> usize {
+        if B == RUNTIME_CAPACITY {
+            self.max_count
+        } else {
+            B
+        }
+    }
+
+    pub(crate) fn root(&self) -> Option<&BtreeNode<K, V>> {
+        self.root.as_ref()
+    }
+
+    pub(crate) fn root_mut(&mut self) -> &mut Option<BtreeNode<K, V>> {
+        &mut self.root
+    }
+
+    pub(crate) fn restructures(&self) -> RestructureCounts {
+        self.restructures
+    }
+
+    pub(crate) fn history(&self) -> Option<&History<K, V>> {
+        self.history.as_ref()
+    }
+
+    pub(crate) fn history_mut(&mut self) -> &mut Option<History<K, V>> {
+        &mut self.history
+    }
+
+    pub(crate) fn observers(&self) -> &Observers<K, V> {
+        &self.observers
+    }
+
+    pub(crate) fn observers_mut(&mut self) -> &mut Observers<K, V> {
+        &mut self.observers
+    }
+
+    /// 全てのエントリをキーの昇順にたどる
+    pub fn iter(&self) -> Range<'_, K, V> {
+        Range::new(self.root.as_ref(), ..)
+    }
+
+    /// 指定した範囲のエントリをキーの昇順にたどる
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        Range::new(self.root.as_ref(), range)
+    }
+
+    /// `key`の値を、`search`と違って複製せずに借りる
+    pub(crate) fn get(&self, key: &K) -> Option<&V> {
+        let mut node = self.root.as_ref()?;
+        loop {
+            match node.keys().binary_lookup(key) {
+                Ok(i) => return Some(&node.values()[i]),
+                Err(_) if node.is_leaf() => return None,
+                Err(i) => node = &node.children()[i],
+            }
         }
     }
 }
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Search<K, V> for Btree<K, V> {
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Search<K, V>
+    for Btree<K, V, B>
+{
     fn search(&self, target_key: &K) -> Option<(K, V)> {
         match &self.root {
             None => None,
@@ -25,42 +137,83 @@ impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Search<K,
     }
 }
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Insert<K, V> for Btree<K, V> {
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Insert<K, V>
+    for Btree<K, V, B>
+{
     fn insert(&mut self, key: K, value: V) {
-        let mut root = self.root.take().unwrap_or(BtreeNode::new(self.max_count));
-        root.insert(key, value);
-
-        if root.is_full() {
-            let ((key, value), (left, right)) = root.split_node();
-            root = BtreeNode::from(
-                vec![key],
-                vec![value],
-                vec![Box::new(left), Box::new(right)],
-                self.max_count,
-            );
+        self.record(&key, Some(&value));
+        self.insert_into_root(key, value);
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Delete<K>
+    for Btree<K, V, B>
+{
+    fn delete(&mut self, key: &K) {
+        self.record(key, None);
+        self.delete_from_root(key);
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize> Extend<(K, V)>
+    for Btree<K, V, B>
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    pub(crate) fn insert_into_root(&mut self, key: K, value: V) {
+        self.insert_with(key, value, &mut || Box::new(BtreeNode::new()));
+    }
+
+    /// `new_node`から分割に使うノードを受け取って挿入する
+    pub(crate) fn insert_with(
+        &mut self,
+        key: K,
+        value: V,
+        new_node: &mut impl FnMut() -> Box<BtreeNode<K, V>>,
+    ) {
+        let event = self.prepare_event(&key, Some(&value));
+        let max_count = self.max_count();
+        let mut root = self.root.take().unwrap_or(BtreeNode::new());
+        root.insert(key, value, max_count, &mut self.restructures, new_node);
+
+        if root.is_full(max_count) {
+            root = root.split_root(new_node);
+            self.restructures.splits += 1;
         }
 
         self.root = Some(root);
+        self.emit(event);
     }
-}
 
-impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> Delete<K> for Btree<K, V> {
-    fn delete(&mut self, key: &K) {
-        match &mut self.root {
-            None => {}
-            Some(root) => {
-                root.delete(key);
+    pub(crate) fn delete_from_root(&mut self, key: &K) {
+        let event = self.prepare_event(key, None);
+        let max_count = self.max_count();
+        if let Some(mut root) = self.root.take() {
+            root.delete(key, max_count, &mut self.restructures);
 
-                if root.is_empty() {
-                    self.root = None;
-                }
-            }
+            // 根の要素がなくなった場合は、唯一の子ノードを新しい根にする
+            self.root = if root.is_empty() {
+                root.into_only_child()
+            } else {
+                Some(root)
+            };
         }
+        self.emit(event);
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
+    use crate::btree::test_util::XorShift;
 
     #[test]
     fn test_empty_tree_search() {
@@ -68,6 +221,12 @@ mod tests {
         assert_eq!(tree.search(&1), None);
     }
 
+    #[test]
+    #[should_panic(expected = "max_count must be at least 3")]
+    fn test_max_count_too_small() {
+        Btree::<i32, i32>::new(2);
+    }
+
     #[test]
     fn test_insert_and_search() {
         let mut tree = Btree::new(3);
@@ -185,4 +344,114 @@ mod tests {
             assert_eq!(tree.search(&k), Some((k, k * 10)));
         }
     }
+
+    #[test]
+    fn test_delete_random_operations() {
+        // 挿入と削除をランダムに繰り返し、毎回B木の条件を満たしてBTreeMapと同じ結果になることを確認
+        for max_count in 3..8 {
+            for initial_seed in [12345, 0x9E37_79B9_7F4A_7C15, 0xDEAD_BEEF] {
+                let mut tree = Btree::new(max_count);
+                let mut expected = std::collections::BTreeMap::new();
+                let mut rng = XorShift::new(initial_seed);
+                for step in 0..3000 {
+                    let seed = rng.next_u64();
+                    // 操作の選択と偶奇が揃わないよう、キーには別のビットを使う
+                    let key = ((seed >> 8) % 200) as i32;
+                    if seed.is_multiple_of(2) {
+                        tree.delete(&key);
+                        expected.remove(&key);
+                    } else {
+                        tree.insert(key, step);
+                        expected.insert(key, step);
+                    }
+                    assert_eq!(tree.verify(), Ok(()));
+                }
+                for key in 0..200 {
+                    assert_eq!(
+                        tree.search(&key).map(|(_, v)| v),
+                        expected.get(&key).copied()
+                    );
+                    assert_eq!(tree.get(&key), expected.get(&key));
+                }
+                assert!(tree.iter().eq(expected.iter()));
+                let stats = tree.stats();
+                assert_eq!(stats.keys, expected.len());
+                assert_eq!(stats.leaves, *stats.nodes_per_level.last().unwrap_or(&0));
+                assert!(stats.max_fill <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut tree = Btree::new(3);
+        assert_eq!(tree.iter().next(), None);
+
+        for i in (1..=20).rev() {
+            tree.insert(i * 10, i * 100);
+        }
+        let keys: Vec<i32> = tree.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, (1..=20).map(|i| i * 10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut tree = Btree::new(4);
+        for i in 0..100 {
+            tree.insert(i * 2, i);
+        }
+        let keys = |range: Range<'_, i32, i32>| range.map(|(k, _)| *k).collect::<Vec<_>>();
+
+        // 存在するキーと存在しないキーの両方を境界に使う
+        assert_eq!(keys(tree.range(10..16)), vec![10, 12, 14]);
+        assert_eq!(keys(tree.range(11..=16)), vec![12, 14, 16]);
+        assert_eq!(keys(tree.range(190..)), vec![190, 192, 194, 196, 198]);
+        assert_eq!(keys(tree.range(..5)), vec![0, 2, 4]);
+        assert_eq!(keys(tree.range(500..)), Vec::<i32>::new());
+
+        // 開始キーを含まない範囲
+        use std::ops::Bound::{Excluded, Included};
+        for start in 0..200 {
+            let expected: Vec<i32> = (start + 1..=(start + 6).min(198))
+                .filter(|k| k % 2 == 0)
+                .collect();
+            assert_eq!(
+                keys(tree.range((Excluded(start), Included(start + 6)))),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_fixed_capacity() {
+        // コンパイル時に最大要素数を決めた木も、実行時に決めた木と同じように動く
+        let mut fixed: Btree<i32, i32, 4> = Btree::fixed();
+        let mut runtime = Btree::new(4);
+        assert_eq!(fixed.max_count(), 4);
+        for i in 0..300 {
+            let key = (i * 37) % 101;
+            if i % 3 == 0 {
+                fixed.delete(&key);
+                runtime.delete(&key);
+            } else {
+                fixed.insert(key, i);
+                runtime.insert(key, i);
+            }
+        }
+        assert!(fixed.iter().eq(runtime.iter()));
+
+        let mut tx = fixed.transaction();
+        tx.insert(1000, 1000);
+        tx.commit();
+        assert_eq!(fixed.search(&1000), Some((1000, 1000)));
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut tree = Btree::new(3);
+        tree.extend((0..50).map(|i| (i, i * 2)));
+        tree.extend([(10, 0)]);
+        assert_eq!(tree.search(&10), Some((10, 0)));
+        assert_eq!(tree.iter().count(), 50);
+    }
 }
@@ -0,0 +1,271 @@
+use crate::btree::tree::Btree;
+use crate::btree::{Delete, Insert, Search};
+use std::ops::RangeBounds;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// `TtlBtree`が期限の判定に使う時計
+///
+/// 時刻は、時計ごとに決まった起点からの経過時間で表す。
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+/// 作成した時点を起点に、実際の経過時間を返す時計
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// `advance`か`set`を呼んだときだけ進む時計
+///
+/// 複製した時計は同じ時刻を共有するので、木に渡した後も手元の複製から進められる。
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    now: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+
+    pub fn set(&self, now: Duration) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// エントリに有効期限を付けられるB木
+///
+/// 期限（`None`は無期限）を値と一緒に持ち、期限を過ぎたエントリは削除されるまで`search`や`iter`から見えない。
+/// 期限の順に並べた索引も持っているので、`purge_expired`は期限切れのエントリだけをたどって削除できる。
+pub struct TtlBtree<
+    K: 'static + Clone + PartialEq + PartialOrd,
+    V: 'static + Clone,
+    C: Clock = SystemClock,
+> {
+    entries: Btree<K, (V, Option<Duration>)>,
+    /// 期限付きのエントリの`(期限, キー)`
+    expiries: Btree<(Duration, K), ()>,
+    clock: C,
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone> TtlBtree<K, V> {
+    pub fn new(max_count: usize) -> Self {
+        TtlBtree::with_clock(max_count, SystemClock::default())
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, C: Clock> TtlBtree<K, V, C> {
+    /// 時刻を`clock`から読む木を作る
+    ///
+    /// # Panics
+    ///
+    /// `max_count`が3未満の場合（`Btree::new`と同じ）
+    pub fn with_clock(max_count: usize, clock: C) -> Self {
+        TtlBtree {
+            entries: Btree::new(max_count),
+            expiries: Btree::new(max_count),
+            clock,
+        }
+    }
+
+    /// 時計の現在時刻
+    pub fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    /// 無期限のエントリを挿入する
+    pub fn insert(&mut self, key: K, value: V) {
+        self.insert_with_expiry(key, value, None);
+    }
+
+    /// 今から`ttl`が経つと期限が切れるエントリを挿入する
+    ///
+    /// 期限が`Duration`で表せないほど`ttl`が長い場合（`Duration::MAX`など）は、無期限になる。
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) {
+        let expiry = self.now().checked_add(ttl);
+        self.insert_with_expiry(key, value, expiry);
+    }
+
+    fn insert_with_expiry(&mut self, key: K, value: V, expiry: Option<Duration>) {
+        self.remove_expiry(&key);
+        if let Some(expiry) = expiry {
+            self.expiries.insert((expiry, key.clone()), ());
+        }
+        self.entries.insert(key, (value, expiry));
+    }
+
+    pub fn delete(&mut self, key: &K) {
+        self.remove_expiry(key);
+        self.entries.delete(key);
+    }
+
+    /// 期限の索引から`key`を外す
+    fn remove_expiry(&mut self, key: &K) {
+        if let Some((_, (_, Some(expiry)))) = self.entries.search(key) {
+            self.expiries.delete(&(expiry, key.clone()));
+        }
+    }
+
+    pub fn search(&self, key: &K) -> Option<(K, V)> {
+        let now = self.now();
+        match self.entries.search(key)? {
+            (key, (value, expiry)) if is_live(expiry, now) => Some((key, value)),
+            _ => None,
+        }
+    }
+
+    /// 期限が切れるまでの残り時間（無期限なら`None`）
+    pub fn ttl(&self, key: &K) -> Option<Duration> {
+        let now = self.now();
+        match self.entries.search(key)? {
+            (_, (_, Some(expiry))) if now < expiry => Some(expiry - now),
+            _ => None,
+        }
+    }
+
+    /// 期限が切れていないエントリを、キーの昇順にたどる
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.range(..)
+    }
+
+    /// 指定した範囲の、期限が切れていないエントリをキーの昇順にたどる
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> impl Iterator<Item = (&K, &V)> {
+        let now = self.now();
+        self.entries
+            .range(range)
+            .filter(move |(_, (_, expiry))| is_live(*expiry, now))
+            .map(|(key, (value, _))| (key, value))
+    }
+
+    /// `now`までに期限が切れたエントリを削除し、削除した数を返す
+    ///
+    /// 期限の索引の先頭から期限切れのエントリだけを読むので、期限切れでないエントリの数によらない。
+    pub fn purge_expired(&mut self, now: Duration) -> usize {
+        let expired: Vec<(Duration, K)> = self
+            .expiries
+            .prefix_range(..=(now,))
+            .map(|(entry, _)| entry.clone())
+            .collect();
+        for entry in &expired {
+            self.expiries.delete(entry);
+            self.entries.delete(&entry.1);
+        }
+        expired.len()
+    }
+}
+
+/// 期限が`now`より後なら有効（ちょうど`now`に切れる）
+fn is_live(expiry: Option<Duration>, now: Duration) -> bool {
+    expiry.is_none_or(|expiry| now < expiry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_expired_entries_are_hidden() {
+        let clock = ManualClock::default();
+        let mut tree = TtlBtree::with_clock(3, clock.clone());
+        tree.insert(1, "forever");
+        tree.insert_with_ttl(2, "short", SECOND);
+        tree.insert_with_ttl(3, "long", 10 * SECOND);
+        assert_eq!(tree.search(&2), Some((2, "short")));
+        assert_eq!(tree.ttl(&3), Some(10 * SECOND));
+        assert_eq!(tree.ttl(&1), None);
+
+        clock.advance(SECOND);
+        assert_eq!(tree.search(&2), None);
+        assert_eq!(tree.ttl(&2), None);
+        assert_eq!(tree.ttl(&3), Some(9 * SECOND));
+        let live: Vec<_> = tree.iter().map(|(key, _)| *key).collect();
+        assert_eq!(live, vec![1, 3]);
+        assert_eq!(tree.range(2..).count(), 1);
+    }
+
+    #[test]
+    fn test_overwrite_and_delete_update_expiry_index() {
+        let clock = ManualClock::default();
+        let mut tree = TtlBtree::with_clock(3, clock.clone());
+        tree.insert_with_ttl(1, 'a', SECOND);
+        // 無期限で上書きすると、期限切れで消えなくなる
+        tree.insert(1, 'b');
+        tree.insert_with_ttl(2, 'c', SECOND);
+        tree.insert_with_ttl(2, 'd', 5 * SECOND);
+        tree.insert_with_ttl(3, 'e', SECOND);
+        tree.delete(&3);
+
+        clock.set(2 * SECOND);
+        assert_eq!(tree.purge_expired(tree.now()), 0);
+        assert_eq!(tree.search(&1), Some((1, 'b')));
+        assert_eq!(tree.search(&2), Some((2, 'd')));
+        assert_eq!(tree.purge_expired(5 * SECOND), 1);
+        assert_eq!(tree.search(&2), None);
+    }
+
+    #[test]
+    fn test_huge_ttl_never_expires() {
+        let clock = ManualClock::default();
+        clock.set(SECOND);
+        let mut tree = TtlBtree::with_clock(3, clock.clone());
+        tree.insert_with_ttl(1, 'a', Duration::MAX);
+        assert_eq!(tree.ttl(&1), None);
+        clock.set(Duration::MAX);
+        assert_eq!(tree.search(&1), Some((1, 'a')));
+        assert_eq!(tree.purge_expired(Duration::MAX), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "max_count must be at least 3")]
+    fn test_max_count_too_small() {
+        TtlBtree::<i32, ()>::new(2);
+    }
+
+    #[test]
+    fn test_expiry_boundaries() {
+        let clock = ManualClock::default();
+        let mut tree = TtlBtree::with_clock(3, clock.clone());
+        // 長さ0の期限は、挿入した時点で切れている
+        tree.insert_with_ttl(0, 'z', Duration::ZERO);
+        assert_eq!(tree.search(&0), None);
+        // 同じ期限のキーが多くのノードにまたがっても、まとめて削除できる
+        for key in 1..50 {
+            tree.insert_with_ttl(key, 'a', SECOND);
+        }
+        tree.insert_with_ttl(50, 'b', 2 * SECOND);
+
+        assert_eq!(tree.purge_expired(SECOND - Duration::from_nanos(1)), 1);
+        clock.set(SECOND);
+        assert_eq!(tree.iter().count(), 1);
+        // 期限が切れて削除されていないエントリも、上書きすれば見える
+        tree.insert_with_ttl(1, 'c', SECOND);
+        assert_eq!(tree.search(&1), Some((1, 'c')));
+        assert_eq!(tree.purge_expired(tree.now()), 48);
+        assert_eq!(tree.expiries.iter().count(), 2);
+        assert_eq!(tree.purge_expired(2 * SECOND), 2);
+        assert_eq!(tree.entries.iter().count(), 0);
+    }
+}
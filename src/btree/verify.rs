@@ -0,0 +1,215 @@
+use crate::btree::node::BtreeNode;
+use crate::btree::tree::Btree;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// 満たされていない木の条件
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// キーが昇順に並んでいないか、親のキーで決まる範囲の外にある
+    KeyOrder { index: usize },
+    /// キーの数が最大数以上
+    Overfull { count: usize },
+    /// 根以外のノードのキーが最小数より少ない（根はキーが0個の場合）
+    Underfull { count: usize },
+    /// 子ノードの数がキーの数より1つ多くない
+    ChildCount { keys: usize, children: usize },
+    /// 葉の深さが揃っていない
+    LeafDepth { expected: usize, found: usize },
+}
+
+/// `Btree::verify`が見つけた最初の違反と、そのノードの位置
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyError {
+    /// 根からたどる子ノードの番号の列（根は空）
+    pub path: Vec<usize>,
+    pub violation: Violation,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node at path {:?}: ", self.path)?;
+        match &self.violation {
+            Violation::KeyOrder { index } => write!(f, "key {index} is out of order"),
+            Violation::Overfull { count } => write!(f, "{count} keys exceed the capacity"),
+            Violation::Underfull { count } => write!(f, "{count} keys are below the minimum"),
+            Violation::ChildCount { keys, children } => {
+                write!(f, "{children} children for {keys} keys")
+            }
+            Violation::LeafDepth { expected, found } => {
+                write!(f, "leaf at depth {found}, expected {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+struct Verifier {
+    max_count: usize,
+    path: Vec<usize>,
+    leaf_depth: Option<usize>,
+}
+
+impl Verifier {
+    fn error(&self, violation: Violation) -> VerifyError {
+        VerifyError {
+            path: self.path.clone(),
+            violation,
+        }
+    }
+
+    /// `lower`と`upper`は親のキーで決まる、このノードのキーが入るべき範囲（両端を含まない）
+    fn node<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone>(
+        &mut self,
+        node: &BtreeNode<K, V>,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> Result<(), VerifyError> {
+        let keys = node.keys();
+        let count = keys.len();
+        if count >= self.max_count {
+            return Err(self.error(Violation::Overfull { count }));
+        }
+        let min_count = if self.path.is_empty() {
+            1
+        } else {
+            BtreeNode::<K, V>::min_count(self.max_count)
+        };
+        if count < min_count {
+            return Err(self.error(Violation::Underfull { count }));
+        }
+        // 比較できない値も順序の違反とみなす
+        let less = |a: &K, b: &K| a.partial_cmp(b) == Some(Ordering::Less);
+        for (index, key) in keys.iter().enumerate() {
+            let previous = if index == 0 {
+                lower
+            } else {
+                Some(&keys[index - 1])
+            };
+            if previous.is_some_and(|p| !less(p, key)) || upper.is_some_and(|u| !less(key, u)) {
+                return Err(self.error(Violation::KeyOrder { index }));
+            }
+        }
+
+        let children = node.children();
+        if children.is_empty() {
+            let depth = self.path.len();
+            match self.leaf_depth {
+                Some(expected) if expected != depth => {
+                    return Err(self.error(Violation::LeafDepth {
+                        expected,
+                        found: depth,
+                    }));
+                }
+                _ => self.leaf_depth = Some(depth),
+            }
+            return Ok(());
+        }
+        if children.len() != count + 1 {
+            return Err(self.error(Violation::ChildCount {
+                keys: count,
+                children: children.len(),
+            }));
+        }
+        for (i, child) in children.iter().enumerate() {
+            let lower = if i == 0 { lower } else { Some(&keys[i - 1]) };
+            let upper = keys.get(i).or(upper);
+            self.path.push(i);
+            self.node(child, lower, upper)?;
+            self.path.pop();
+        }
+        Ok(())
+    }
+}
+
+impl<K: 'static + Clone + PartialEq + PartialOrd, V: 'static + Clone, const B: usize>
+    Btree<K, V, B>
+{
+    /// 木がB木の条件を満たしているかを調べる
+    ///
+    /// キーの順序、ノードごとのキーと子ノードの数、葉の深さを確かめ、最初に見つかった違反を返す。
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let Some(root) = self.root() else {
+            return Ok(());
+        };
+        Verifier {
+            max_count: self.max_count(),
+            path: vec![],
+            leaf_depth: None,
+        }
+        .node(root, None, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(keys: Vec<i32>) -> Box<BtreeNode<i32, i32>> {
+        let values = keys.clone();
+        Box::new(BtreeNode::from(keys, values, vec![]))
+    }
+
+    fn tree_with_root(root: BtreeNode<i32, i32>) -> Btree<i32, i32> {
+        let mut tree = Btree::new(4);
+        *tree.root_mut() = Some(root);
+        tree
+    }
+
+    #[test]
+    fn test_violations() {
+        let root = |children| BtreeNode::from(vec![10], vec![10], children);
+
+        let tree = tree_with_root(root(vec![leaf(vec![5]), leaf(vec![10])]));
+        assert_eq!(
+            tree.verify(),
+            Err(VerifyError {
+                path: vec![1],
+                violation: Violation::KeyOrder { index: 0 }
+            })
+        );
+
+        let tree = tree_with_root(root(vec![leaf(vec![1, 2, 3, 4]), leaf(vec![11])]));
+        assert_eq!(
+            tree.verify().unwrap_err().violation,
+            Violation::Overfull { count: 4 }
+        );
+
+        let tree = tree_with_root(root(vec![leaf(vec![]), leaf(vec![11])]));
+        assert_eq!(
+            tree.verify().unwrap_err().violation,
+            Violation::Underfull { count: 0 }
+        );
+
+        let deep = Box::new(root(vec![leaf(vec![1]), leaf(vec![25])]));
+        let tree = tree_with_root(BtreeNode::from(
+            vec![20],
+            vec![20],
+            vec![deep, leaf(vec![21])],
+        ));
+        let error = tree.verify().unwrap_err();
+        assert_eq!(error.path, vec![0, 1]);
+        assert_eq!(error.violation, Violation::KeyOrder { index: 0 });
+
+        let deep = Box::new(root(vec![leaf(vec![1]), leaf(vec![11])]));
+        let tree = tree_with_root(BtreeNode::from(
+            vec![20],
+            vec![20],
+            vec![deep, leaf(vec![21])],
+        ));
+        assert_eq!(
+            tree.verify().unwrap_err().to_string(),
+            "node at path [1]: leaf at depth 1, expected 2"
+        );
+
+        let tree = tree_with_root(root(vec![leaf(vec![1])]));
+        assert_eq!(
+            tree.verify().unwrap_err().violation,
+            Violation::ChildCount {
+                keys: 1,
+                children: 1
+            }
+        );
+    }
+}
@@ -1,6 +1,31 @@
 pub mod btree;
-pub use btree::tree::Btree;
-
-fn main() {
-    println!("Hello, world!");
-}
+pub use btree::arena::ArenaBtree;
+pub use btree::blink::BlinkTree;
+pub use btree::bounded::{BoundedBtree, EvictionPolicy};
+pub use btree::bplus::{BplusRange, BplusTree};
+#[cfg(feature = "server")]
+pub use btree::client::{Client, Entries};
+pub use btree::composite::{CompositeRange, KeyPrefix, OrderedKey};
+pub use btree::concurrent::ConcurrentBtree;
+pub use btree::diff::{Conflict, Diff, DiffEntry};
+pub use btree::fallible::AllocError;
+pub use btree::history::Checkpoint;
+pub use btree::index::{IndexId, IndexedTable};
+pub use btree::inline::InlineBtree;
+pub use btree::interval::IntervalTree;
+pub use btree::iter::Range;
+pub use btree::mvcc::{MvccBtree, Snapshot, SnapshotRange};
+pub use btree::observe::{ChangeEvent, SubscriptionId};
+pub use btree::persist::Persist;
+pub use btree::prefix::{PrefixBtree, PrefixIter};
+pub use btree::render::RenderOptions;
+#[cfg(feature = "server")]
+pub use btree::resp::Value;
+#[cfg(feature = "server")]
+pub use btree::server::Server;
+pub use btree::stats::{MemoryUsage, RestructureCounts, TreeStats};
+pub use btree::transaction::{Transaction, TransactionRange};
+pub use btree::tree::{Btree, RUNTIME_CAPACITY};
+pub use btree::ttl::{Clock, ManualClock, SystemClock, TtlBtree};
+pub use btree::verify::{VerifyError, Violation};
+pub use btree::{Delete, Insert, Search};
@@ -0,0 +1,122 @@
+//! 確保に失敗するアロケータで`Btree::try_insert`を試す
+//!
+//! グローバルアロケータを差し替えるので、他のテストとは別のバイナリにしている。
+
+use btree_rust::{AllocError, Btree, Insert, Search};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+
+/// 残りの回数を使い切ると確保に失敗するアロケータ（設定したスレッドだけに効く）
+struct FailingAllocator;
+
+thread_local! {
+    static BUDGET: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+unsafe impl GlobalAlloc for FailingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let exhausted = BUDGET
+            .try_with(|budget| match budget.get() {
+                Some(0) => true,
+                Some(n) => {
+                    budget.set(Some(n - 1));
+                    false
+                }
+                None => false,
+            })
+            .unwrap_or(false);
+        if exhausted {
+            return std::ptr::null_mut();
+        }
+        // SAFETY: 呼び出し側の条件をそのまま引き継ぐ
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr`は`System`から確保した領域
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: FailingAllocator = FailingAllocator;
+
+fn with_budget<T>(budget: usize, f: impl FnOnce() -> T) -> T {
+    BUDGET.with(|b| b.set(Some(budget)));
+    let result = f();
+    BUDGET.with(|b| b.set(None));
+    result
+}
+
+fn entries(tree: &Btree<i32, i32>) -> Vec<(i32, i32)> {
+    tree.iter().map(|(k, v)| (*k, *v)).collect()
+}
+
+#[test]
+fn test_try_insert_without_failure() {
+    let mut tree = Btree::new(4);
+    let mut expected = BTreeMap::new();
+    for i in 0..200 {
+        let key = (i * 37) % 101;
+        tree.try_insert(key, i).unwrap();
+        expected.insert(key, i);
+    }
+    let expected: Vec<(i32, i32)> = expected.into_iter().collect();
+    assert_eq!(entries(&tree), expected);
+}
+
+#[test]
+fn test_failure_leaves_tree_unchanged() {
+    for max_count in [3, 4, 5] {
+        let mut tree = Btree::new(max_count);
+        // 順序がばらけ、後半は既にあるキーの上書きになる
+        for i in 0..150 {
+            let key = (i * 37) % 101;
+
+            // 成功するまで確保できる回数を1つずつ増やす
+            let mut budget = 0;
+            loop {
+                let before = entries(&tree);
+                let stats = tree.stats();
+                match with_budget(budget, || tree.try_insert(key, budget as i32)) {
+                    Ok(()) => break,
+                    Err(AllocError) => {
+                        assert_eq!(entries(&tree), before);
+                        assert_eq!(tree.stats().nodes_per_level, stats.nodes_per_level);
+                        assert_eq!(tree.stats().restructures, stats.restructures);
+                    }
+                }
+                budget += 1;
+            }
+            assert_eq!(tree.search(&key), Some((key, budget as i32)));
+        }
+    }
+}
+
+#[test]
+fn test_overwrite_needs_no_allocation() {
+    let mut tree = Btree::new(3);
+    for i in 0..20 {
+        tree.insert(i, i);
+    }
+    assert_eq!(with_budget(0, || tree.try_insert(7, 70)), Ok(()));
+    assert_eq!(tree.search(&7), Some((7, 70)));
+
+    let mut empty: Btree<i32, i32> = Btree::new(3);
+    assert_eq!(with_budget(0, || empty.try_insert(1, 1)), Err(AllocError));
+    assert!(empty.stats().nodes_per_level.is_empty());
+}
+
+#[test]
+fn test_failure_keeps_history() {
+    let mut tree = Btree::new(3);
+    tree.enable_history(10);
+    tree.insert(1, 1);
+    tree.insert(2, 2);
+    // 3つ目のキーで根が分割されるので、ノードを確保できずに失敗する
+    assert_eq!(with_budget(0, || tree.try_insert(3, 3)), Err(AllocError));
+    assert_eq!(entries(&tree), vec![(1, 1), (2, 2)]);
+    assert!(tree.undo());
+    assert_eq!(entries(&tree), vec![(1, 1)]);
+}
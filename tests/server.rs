@@ -0,0 +1,154 @@
+//! 空いているポートでサーバーを起動し、TCP越しにコマンドを送る
+
+use btree_rust::{Client, Server};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::ops::Bound;
+use std::thread;
+
+fn start_server() -> SocketAddr {
+    let server = Server::bind("127.0.0.1:0", 4).unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    addr
+}
+
+#[test]
+fn test_get_set_del() {
+    let mut client = Client::connect(start_server()).unwrap();
+    client.ping().unwrap();
+    assert_eq!(client.get("users", b"alice").unwrap(), None);
+    client.set("users", b"alice", b"admin").unwrap();
+    client.set("users", b"bob", b"\x00binary\r\n").unwrap();
+    assert_eq!(
+        client.get("users", b"alice").unwrap(),
+        Some(b"admin".to_vec())
+    );
+    assert_eq!(
+        client.get("users", b"bob").unwrap(),
+        Some(b"\x00binary\r\n".to_vec())
+    );
+    assert!(client.del("users", b"alice").unwrap());
+    assert!(!client.del("users", b"alice").unwrap());
+    assert_eq!(client.get("users", b"alice").unwrap(), None);
+}
+
+#[test]
+fn test_named_trees_are_separate() {
+    let mut client = Client::connect(start_server()).unwrap();
+    client.set("a", b"k", b"1").unwrap();
+    client.set("b", b"k", b"2").unwrap();
+    assert_eq!(client.get("a", b"k").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(client.get("b", b"k").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(client.trees().unwrap(), vec![b"a".to_vec(), b"b".to_vec()]);
+}
+
+#[test]
+fn test_range_and_scan() {
+    let mut client = Client::connect(start_server()).unwrap();
+    for i in 0..100 {
+        let key = format!("key{i:03}");
+        client
+            .set("t", key.as_bytes(), i.to_string().as_bytes())
+            .unwrap();
+    }
+
+    let entries = client
+        .range(
+            "t",
+            Bound::Included(b"key010"),
+            Bound::Excluded(b"key013"),
+            None,
+        )
+        .unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            (b"key010".to_vec(), b"10".to_vec()),
+            (b"key011".to_vec(), b"11".to_vec()),
+            (b"key012".to_vec(), b"12".to_vec()),
+        ]
+    );
+    let tail = client
+        .range("t", Bound::Excluded(b"key097"), Bound::Unbounded, Some(5))
+        .unwrap();
+    assert_eq!(tail.len(), 2);
+
+    let mut cursor = b"0".to_vec();
+    let mut keys = vec![];
+    loop {
+        let (next, page) = client.scan("t", &cursor, 30).unwrap();
+        assert!(page.len() <= 30);
+        keys.extend(page.into_iter().map(|(key, _)| key));
+        if next == b"0" {
+            break;
+        }
+        cursor = next;
+    }
+    let expected: Vec<Vec<u8>> = (0..100)
+        .map(|i| format!("key{i:03}").into_bytes())
+        .collect();
+    assert_eq!(keys, expected);
+}
+
+#[test]
+fn test_concurrent_clients() {
+    let addr = start_server();
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            thread::spawn(move || {
+                let mut client = Client::connect(addr).unwrap();
+                for i in 0..50 {
+                    let key = format!("{t}-{i:02}");
+                    client.set("shared", key.as_bytes(), b"x").unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    let mut client = Client::connect(addr).unwrap();
+    let all = client
+        .range("shared", Bound::Unbounded, Bound::Unbounded, None)
+        .unwrap();
+    assert_eq!(all.len(), 200);
+}
+
+#[test]
+fn test_errors_and_inline_commands() {
+    let addr = start_server();
+    let mut client = Client::connect(addr).unwrap();
+    let error = client.command(&[b"NOPE"]).unwrap_err();
+    assert_eq!(error.to_string(), "ERR unknown command 'NOPE'");
+    // エラーの後も同じ接続を使い続けられる
+    client.ping().unwrap();
+
+    // telnetのように空白区切りの1行でも送れる
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"SET t k v\r\nGET t k\r\nQUIT\r\n")
+        .unwrap();
+    let lines: Vec<String> = BufReader::new(stream)
+        .lines()
+        .map(|line| line.unwrap())
+        .collect();
+    assert_eq!(lines, vec!["+OK", "$1", "v", "+OK"]);
+
+    // 空の配列は読み飛ばし、接続を続ける
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"*0\r\n*1\r\n$4\r\nPING\r\n*0\r\nQUIT\r\n")
+        .unwrap();
+    let lines: Vec<String> = BufReader::new(stream)
+        .lines()
+        .map(|line| line.unwrap())
+        .collect();
+    assert_eq!(lines, vec!["+PONG", "+OK"]);
+}
+
+#[test]
+fn test_bind_rejects_small_max_count() {
+    let error = Server::bind("127.0.0.1:0", 2).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}